@@ -0,0 +1,254 @@
+//! Removing track points that cannot be right: invalid positions, duplicates and GPS spikes.
+
+use crate::gpxxml::PositionedPoint;
+use crate::{TrackPoint, haversine_distance};
+
+/// Largest plausible movement between two points for [`clean`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CleanLimits {
+    /// Speed in m/s.
+    pub max_speed: f64,
+    /// Change of speed between consecutive intervals in m/s².
+    pub max_acceleration: f64,
+}
+
+/// Why [`clean`] drops a point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DropReason {
+    /// Latitude outside ±90° or longitude outside ±180°.
+    OutOfRange,
+    /// Exactly (0, 0), which receivers report when they have no fix.
+    ZeroCoordinates,
+    /// Same position, elevation and time as the previous point, or no time like it.
+    Duplicate,
+    /// Reaching the point would need this speed in m/s.
+    Speed(f64),
+    /// Reaching the point would need this acceleration in m/s².
+    Acceleration(f64),
+}
+
+/// Number of following points that must agree with a suspicious point for it to be kept.
+const CONFIRMATION_POINTS: usize = 2;
+
+/// Returns for each point of a track segment why it is dropped, or `None` to keep it.
+///
+/// Points with invalid coordinates and exact duplicates are dropped first. The remaining points
+/// with a time are compared with the last kept point: one that would need more than the allowed speed or
+/// acceleration is a spike and dropped, unless the next two points continue plausibly from it,
+/// in which case the track really went there and it is kept. The first point of the segment is
+/// dropped instead when it is the one that does not fit the points after it, as after a cold
+/// start. Intervals shorter than a second count as one second, since timestamps are usually
+/// rounded to whole seconds.
+pub fn clean(track_points: &[PositionedPoint], limits: &CleanLimits) -> Vec<Option<DropReason>> {
+    let mut reasons: Vec<Option<DropReason>> = track_points
+        .iter()
+        .map(|point| {
+            if !(-90.0..=90.0).contains(&point.lat) || !(-180.0..=180.0).contains(&point.lon) {
+                Some(DropReason::OutOfRange)
+            } else if point.lat == 0.0 && point.lon == 0.0 {
+                Some(DropReason::ZeroCoordinates)
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    let mut previous: Option<&PositionedPoint> = None;
+    for (current, reason) in track_points.iter().zip(&mut reasons) {
+        if reason.is_some() {
+            continue;
+        }
+        if previous.is_some_and(|previous| previous == current) {
+            *reason = Some(DropReason::Duplicate);
+        } else {
+            previous = Some(current);
+        }
+    }
+
+    // Spikes are judged by speed, so only points with a time take part.
+    let candidates: Vec<(usize, TrackPoint)> = track_points
+        .iter()
+        .enumerate()
+        .filter(|&(idx, _)| reasons[idx].is_none())
+        .filter_map(|(idx, point)| Some((idx, TrackPoint::new(point.lat, point.lon, point.time?))))
+        .collect();
+    let mut kept: Vec<&TrackPoint> = Vec::new();
+    for (position, (idx, point)) in candidates.iter().enumerate() {
+        let following: Vec<&TrackPoint> = candidates[position + 1..]
+            .iter()
+            .take(CONFIRMATION_POINTS + 1)
+            .map(|(_, point)| point)
+            .collect();
+
+        let implausible = match kept.as_slice() {
+            [] => following.first().and_then(|&next| {
+                // Only a first point that the track does not continue from is dropped.
+                let reason = check_movement(None, point, next, limits)?;
+                is_confirmed(None, next, &following[1..], limits).then_some(reason)
+            }),
+            [.., anchor] => {
+                let before = kept.len().checked_sub(2).map(|idx| kept[idx]);
+                let confirmation = &following[..CONFIRMATION_POINTS.min(following.len())];
+                check_movement(before, anchor, point, limits)
+                    .filter(|_| !is_confirmed(None, point, confirmation, limits))
+            }
+        };
+        match implausible {
+            Some(reason) => reasons[*idx] = Some(reason),
+            None => kept.push(point),
+        }
+    }
+    reasons
+}
+
+/// Returns why moving from `anchor` to `point` is implausible, `before` being the point before
+/// `anchor` if there is one.
+fn check_movement(
+    before: Option<&TrackPoint>,
+    anchor: &TrackPoint,
+    point: &TrackPoint,
+    limits: &CleanLimits,
+) -> Option<DropReason> {
+    let (current_speed, interval) = speed(anchor, point);
+    if current_speed > limits.max_speed {
+        return Some(DropReason::Speed(current_speed));
+    }
+    let (previous_speed, previous_interval) = speed(before?, anchor);
+    let acceleration =
+        (current_speed - previous_speed).abs() / ((interval + previous_interval) / 2.0);
+    (acceleration > limits.max_acceleration).then_some(DropReason::Acceleration(acceleration))
+}
+
+/// Returns whether the track continues plausibly from `point` through `following`, which must
+/// not be empty.
+fn is_confirmed<'a>(
+    mut before: Option<&'a TrackPoint>,
+    mut point: &'a TrackPoint,
+    following: &[&'a TrackPoint],
+    limits: &CleanLimits,
+) -> bool {
+    !following.is_empty()
+        && following.iter().all(|&next| {
+            let plausible = check_movement(before, point, next, limits).is_none();
+            (before, point) = (Some(point), next);
+            plausible
+        })
+}
+
+/// Returns the speed between two points and the interval it was measured over, at least one
+/// second.
+fn speed(from: &TrackPoint, to: &TrackPoint) -> (f64, f64) {
+    let distance = haversine_distance(from.lat, from.lon, to.lat, to.lon);
+    let interval = (to.time - from.time).as_seconds_f64().max(1.0);
+    (distance / interval, interval)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::{Duration, OffsetDateTime};
+
+    const LIMITS: CleanLimits = CleanLimits {
+        max_speed: 10.0,
+        max_acceleration: 5.0,
+    };
+
+    /// One point per second heading north, `meters` from the start.
+    fn track(meters: &[f64]) -> Vec<PositionedPoint> {
+        let start = OffsetDateTime::from_unix_timestamp(1_686_823_200).unwrap();
+        meters
+            .iter()
+            .enumerate()
+            .map(|(idx, &meters)| {
+                let lat = 47.0 + meters / 111_195.0;
+                PositionedPoint {
+                    lat,
+                    lon: 8.0,
+                    ele: None,
+                    time: Some(start + Duration::seconds(idx as i64)),
+                }
+            })
+            .collect()
+    }
+
+    fn dropped(reasons: &[Option<DropReason>]) -> Vec<usize> {
+        (0..reasons.len())
+            .filter(|&idx| reasons[idx].is_some())
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_removes_invalid_points() {
+        let mut points = track(&[0.0, 3.0, 6.0, 9.0, 12.0]);
+        points[1].lat = 91.0;
+        points[2].lat = 0.0;
+        points[2].lon = 0.0;
+        points[4] = points[3];
+
+        let reasons = clean(&points, &LIMITS);
+        assert_eq!(
+            reasons,
+            [
+                None,
+                Some(DropReason::OutOfRange),
+                Some(DropReason::ZeroCoordinates),
+                None,
+                Some(DropReason::Duplicate)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clean_removes_spikes() {
+        let points = track(&[0.0, 3.0, 6.0, 500.0, 9.0, 12.0, 15.0, 900.0]);
+        let reasons = clean(&points, &LIMITS);
+        assert_eq!(dropped(&reasons), [3, 7]);
+        assert!(matches!(reasons[3], Some(DropReason::Speed(speed)) if speed > 400.0));
+
+        // Fast enough to pass the speed limit, but only by jumping from a standstill.
+        let points = track(&[0.0, 0.0, 0.0, 9.0, 0.0, 0.0, 0.0]);
+        let reasons = clean(&points, &LIMITS);
+        assert_eq!(dropped(&reasons), [3]);
+        assert!(matches!(reasons[3], Some(DropReason::Acceleration(_))));
+    }
+
+    #[test]
+    fn test_clean_checks_untimed_points_by_position_only() {
+        let mut points = track(&[0.0, 3.0, 500.0, 6.0, 9.0, 12.0, 15.0]);
+        for point in &mut points[1..=4] {
+            point.time = None;
+        }
+        points[3] = points[2];
+        points[4].lat = 0.0;
+        points[4].lon = 0.0;
+
+        // The untimed jump is kept since it has no speed, but its invalid position and
+        // duplicate are dropped.
+        assert_eq!(
+            clean(&points, &LIMITS),
+            [
+                None,
+                None,
+                None,
+                Some(DropReason::Duplicate),
+                Some(DropReason::ZeroCoordinates),
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clean_keeps_confirmed_jumps() {
+        // The track continues from the jump, e.g. after leaving a tunnel.
+        let points = track(&[0.0, 3.0, 500.0, 503.0, 506.0, 509.0]);
+        assert!(dropped(&clean(&points, &LIMITS)).is_empty());
+    }
+
+    #[test]
+    fn test_clean_drops_cold_start_point() {
+        let points = track(&[-800.0, 0.0, 3.0, 6.0, 9.0]);
+        assert_eq!(dropped(&clean(&points, &LIMITS)), [0]);
+        assert!(clean(&[], &LIMITS).is_empty());
+    }
+}
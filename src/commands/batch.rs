@@ -0,0 +1,266 @@
+use crate::commands::io_args::{IoArgs, OutputTarget};
+use gpxwrench::{GpxInput, GpxWrenchError};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+struct BatchResult {
+    input: PathBuf,
+    outcome: Result<PathBuf, String>,
+}
+
+/// Runs `process` for every file selected by `pattern`, writing each result into `output_dir`
+/// under the name `name_template` expands to.
+///
+/// A failing file is recorded and the run continues with the next one. A summary table is
+/// printed once all files have been processed.
+pub fn process_batch<F>(
+    args: &IoArgs,
+    pattern: &Path,
+    output_dir: &Path,
+    name_template: &str,
+    mut process: F,
+) -> Result<(), GpxWrenchError>
+where
+    F: FnMut(&mut GpxInput, &OutputTarget) -> Result<(), GpxWrenchError>,
+{
+    let inputs = find_batch_inputs(pattern)?;
+    if inputs.is_empty() {
+        return Err(GpxWrenchError::InvalidArgument(format!(
+            "No input files match {}",
+            pattern.display()
+        )));
+    }
+
+    // Inputs exist, so resolving both sides catches outputs that are the same file under another
+    // spelling, such as an output directory equal to the input directory.
+    let resolved_inputs: HashSet<PathBuf> = inputs
+        .iter()
+        .filter_map(|input| fs::canonicalize(input).ok())
+        .collect();
+    let mut output_paths = Vec::with_capacity(inputs.len());
+    let mut seen_names = HashSet::new();
+    for input in &inputs {
+        let name = expand_name_template(name_template, input)?;
+        if !seen_names.insert(name.clone()) {
+            return Err(GpxWrenchError::InvalidArgument(format!(
+                "Name template {name_template:?} maps more than one input to {name:?}"
+            )));
+        }
+        let output_path = output_dir.join(name);
+        if fs::canonicalize(&output_path).is_ok_and(|path| resolved_inputs.contains(&path)) {
+            return Err(GpxWrenchError::InvalidArgument(format!(
+                "Batch output {} would overwrite an input file",
+                output_path.display()
+            )));
+        }
+        output_paths.push(output_path);
+    }
+
+    fs::create_dir_all(output_dir).map_err(|e| GpxWrenchError::from(e).for_path(output_dir))?;
+
+    let mut results = Vec::with_capacity(inputs.len());
+    for (input, output_path) in inputs.into_iter().zip(output_paths) {
+        let outcome = args
+            .open_file(&input)
+            .and_then(|mut gpx_input| {
+                process(&mut gpx_input, &OutputTarget::File(output_path.clone()))
+            })
+            .map(|()| output_path)
+            .map_err(|e| e.to_string());
+        results.push(BatchResult { input, outcome });
+    }
+
+    print_summary(&results);
+
+    let failed = results.iter().filter(|r| r.outcome.is_err()).count();
+    if failed > 0 {
+        return Err(GpxWrenchError::Failures {
+            failed,
+            total: results.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Lists the files selected by a batch pattern.
+///
+/// A directory selects every `.gpx` file directly inside it. Otherwise the last path component
+/// may contain `*` and `?` wildcards; a path without wildcards selects that single file.
+fn find_batch_inputs(pattern: &Path) -> Result<Vec<PathBuf>, GpxWrenchError> {
+    if pattern.is_dir() {
+        return list_files(pattern, |name| {
+            Path::new(name)
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("gpx"))
+        });
+    }
+
+    let file_pattern = pattern
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| {
+            GpxWrenchError::InvalidArgument(format!("Invalid batch pattern: {}", pattern.display()))
+        })?;
+    if !file_pattern.contains(['*', '?']) {
+        return Ok(vec![pattern.to_path_buf()]);
+    }
+
+    let dir = match pattern.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    if dir.to_string_lossy().contains(['*', '?']) {
+        return Err(GpxWrenchError::InvalidArgument(
+            "Wildcards are only supported in the last path component".into(),
+        ));
+    }
+
+    let file_pattern: Vec<char> = file_pattern.chars().collect();
+    list_files(dir, |name| {
+        matches_wildcard(&file_pattern, &name.chars().collect::<Vec<_>>())
+    })
+}
+
+fn list_files<P>(dir: &Path, mut predicate: P) -> Result<Vec<PathBuf>, GpxWrenchError>
+where
+    P: FnMut(&str) -> bool,
+{
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).map_err(|e| GpxWrenchError::from(e).for_path(dir))? {
+        let entry = entry?;
+        if entry.file_type()?.is_file()
+            && let Some(name) = entry.file_name().to_str()
+            && predicate(name)
+        {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Matches `name` against a pattern of `*` and `?` wildcards.
+///
+/// On a mismatch the last `*` takes one more character and matching resumes after it, which
+/// keeps the work linear in the length of the name for each star.
+fn matches_wildcard(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&expected) if expected == '?' || expected == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    backtrack = Some((star_p, star_n + 1));
+                    p = star_p;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Expands `{stem}` and `{name}` in a batch output name template for the given input path.
+fn expand_name_template(template: &str, input: &Path) -> Result<String, GpxWrenchError> {
+    let name = input.file_name().unwrap_or_default().to_string_lossy();
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        expanded.push_str(&rest[..open]);
+        let close = rest[open..].find('}').ok_or_else(|| {
+            GpxWrenchError::InvalidArgument(format!(
+                "Unterminated placeholder in name template {template:?}"
+            ))
+        })?;
+        match &rest[open + 1..open + close] {
+            "stem" => expanded.push_str(&stem),
+            "name" => expanded.push_str(&name),
+            other => {
+                return Err(GpxWrenchError::InvalidArgument(format!(
+                    "Unknown placeholder {{{other}}} in name template"
+                )));
+            }
+        }
+        rest = &rest[open + close + 1..];
+    }
+    expanded.push_str(rest);
+
+    if expanded.is_empty() || expanded.contains(['/', '\\']) {
+        return Err(GpxWrenchError::InvalidArgument(format!(
+            "Name template {template:?} must produce a plain file name"
+        )));
+    }
+    Ok(expanded)
+}
+
+fn print_summary(results: &[BatchResult]) {
+    let inputs: Vec<String> = results
+        .iter()
+        .map(|result| result.input.display().to_string())
+        .collect();
+    let width = inputs.iter().map(String::len).max().unwrap_or(0).max(5);
+
+    println!("{:<7} {:<width$} RESULT", "STATUS", "INPUT");
+    for (result, input) in results.iter().zip(&inputs) {
+        match &result.outcome {
+            Ok(output) => println!("{:<7} {input:<width$} {}", "ok", output.display()),
+            Err(message) => println!("{:<7} {input:<width$} {message}", "failed"),
+        }
+    }
+
+    let failures = results.iter().filter(|r| r.outcome.is_err()).count();
+    println!("{} succeeded, {failures} failed", results.len() - failures);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wildcard(pattern: &str, name: &str) -> bool {
+        matches_wildcard(
+            &pattern.chars().collect::<Vec<_>>(),
+            &name.chars().collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn test_matches_wildcard() {
+        assert!(wildcard("*.gpx", "ride.gpx"));
+        assert!(wildcard("ride-??.gpx", "ride-01.gpx"));
+        assert!(wildcard("*", ""));
+        assert!(!wildcard("*.gpx", "ride.fit"));
+        assert!(!wildcard("ride-?.gpx", "ride-01.gpx"));
+        assert!(wildcard("*ride*.gpx", "morning-ride-01.gpx"));
+        assert!(wildcard("a*b*c", "aXbYbZc"));
+        assert!(!wildcard("a*b*c", "aXbYbZ"));
+
+        // Many stars against a long name that does not match must not take exponential time.
+        let name = "a".repeat(200);
+        assert!(!wildcard(&format!("{}b", "*a".repeat(20)), &name));
+    }
+
+    #[test]
+    fn test_expand_name_template() {
+        let input = Path::new("exports/ride.gpx");
+        assert_eq!(
+            expand_name_template("{stem}-trimmed.gpx", input).unwrap(),
+            "ride-trimmed.gpx"
+        );
+        assert_eq!(expand_name_template("{name}", input).unwrap(), "ride.gpx");
+        assert!(expand_name_template("{unknown}.gpx", input).is_err());
+        assert!(expand_name_template("{stem", input).is_err());
+        assert!(expand_name_template("out/{stem}.gpx", input).is_err());
+    }
+}
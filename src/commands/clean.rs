@@ -0,0 +1,118 @@
+use crate::commands::io_args::{IoArgs, process_inputs};
+use crate::commands::stats::format_time;
+use crate::commands::trim_to_activity::ActivityProfile;
+use gpxwrench::GpxWrenchError;
+use gpxwrench::clean::{CleanLimits, DropReason, clean};
+use gpxwrench::gpxxml::{
+    GroupSplit, PositionedPoint, SegmentPlanSelector, extract_positioned_segments,
+    filter_xml_by_selector,
+};
+
+/// Plausibility limits of each profile; generous, since only clearly impossible movement should
+/// be removed.
+fn profile_limits(profile: ActivityProfile) -> CleanLimits {
+    let (max_speed, max_acceleration) = match profile {
+        ActivityProfile::Hike => (7.0, 4.0),
+        ActivityProfile::Run => (12.0, 6.0),
+        ActivityProfile::Bike => (35.0, 8.0),
+        ActivityProfile::Drive => (90.0, 12.0),
+    };
+    CleanLimits {
+        max_speed,
+        max_acceleration,
+    }
+}
+
+pub fn clean_command(
+    profile: Option<ActivityProfile>,
+    max_speed: Option<f64>,
+    max_acceleration: Option<f64>,
+    io_args: &IoArgs,
+) -> Result<(), GpxWrenchError> {
+    let mut limits = profile_limits(profile.unwrap_or(ActivityProfile::Drive));
+    if let Some(max_speed) = max_speed {
+        limits.max_speed = max_speed;
+    }
+    if let Some(max_acceleration) = max_acceleration {
+        limits.max_acceleration = max_acceleration;
+    }
+    for (name, value) in [
+        ("Maximum speed", limits.max_speed),
+        ("Maximum acceleration", limits.max_acceleration),
+    ] {
+        if !value.is_finite() || value <= 0.0 {
+            return Err(GpxWrenchError::InvalidArgument(format!(
+                "{name} must be a finite positive number"
+            )));
+        }
+    }
+
+    process_inputs(io_args, |input, output| {
+        let segments = extract_positioned_segments(input.reader()?)?;
+        let reasons: Vec<Vec<Option<DropReason>>> = segments
+            .iter()
+            .map(|segment| clean(segment, &limits))
+            .collect();
+        let prefix = input
+            .path()
+            .map(|path| format!("{}: ", path.display()))
+            .unwrap_or_default();
+        report(&prefix, &segments, &reasons, &limits)?;
+
+        let groups = reasons
+            .iter()
+            .map(|reasons| {
+                reasons
+                    .iter()
+                    .map(|reason| reason.is_none().then_some(0))
+                    .collect()
+            })
+            .collect();
+        let mut selector = SegmentPlanSelector::new(groups).including_untimed();
+        output.write_with(|writer| {
+            filter_xml_by_selector(input.reader()?, &mut selector, GroupSplit::Segment, writer)
+        })
+    })
+}
+
+/// Prints every dropped point with the reason to stderr, followed by a summary. Each line starts
+/// with `prefix`, which names the input file when there is one.
+fn report(
+    prefix: &str,
+    segments: &[Vec<PositionedPoint>],
+    reasons: &[Vec<Option<DropReason>>],
+    limits: &CleanLimits,
+) -> Result<(), GpxWrenchError> {
+    let mut dropped = 0;
+    for (segment, reasons) in segments.iter().zip(reasons) {
+        for (point, reason) in segment.iter().zip(reasons) {
+            let Some(reason) = reason else {
+                continue;
+            };
+            dropped += 1;
+            let explanation = match reason {
+                DropReason::OutOfRange => "coordinates out of range".to_string(),
+                DropReason::ZeroCoordinates => "zero coordinates".to_string(),
+                DropReason::Duplicate => "duplicate of the previous point".to_string(),
+                DropReason::Speed(speed) => {
+                    format!("speed {speed:.1} m/s exceeds {:.1} m/s", limits.max_speed)
+                }
+                DropReason::Acceleration(acceleration) => format!(
+                    "acceleration {acceleration:.1} m/s² exceeds {:.1} m/s²",
+                    limits.max_acceleration
+                ),
+            };
+            let time = match point.time {
+                Some(time) => format_time(time)?,
+                None => "point without time".to_string(),
+            };
+            eprintln!(
+                "{prefix}Removed {time} ({}, {}): {explanation}",
+                point.lat, point.lon
+            );
+        }
+    }
+    let total: usize = segments.iter().map(Vec::len).sum();
+    eprintln!("{prefix}Removed {dropped} of {total} points");
+    Ok(())
+}
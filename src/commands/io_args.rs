@@ -0,0 +1,280 @@
+use crate::commands::batch::process_batch;
+use clap::Args;
+use gpxwrench::{GpxInput, GpxWrenchError, MAX_INPUT_BYTES};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Debug)]
+pub struct IoArgs {
+    #[arg(
+        value_name = "INPUT",
+        help = "Input GPX files (reads stdin when omitted)"
+    )]
+    pub inputs: Vec<PathBuf>,
+    #[arg(
+        long,
+        short,
+        value_name = "FILE",
+        conflicts_with = "in_place",
+        help = "Write output to FILE instead of stdout"
+    )]
+    pub output: Option<PathBuf>,
+    #[arg(long, help = "Replace each input file with its processed output")]
+    pub in_place: bool,
+    #[arg(
+        long,
+        value_name = "DIR_OR_GLOB",
+        conflicts_with_all = ["inputs", "output", "in_place"],
+        requires = "output_dir",
+        help = "Process every .gpx file in a directory, or every file matching a glob such as 'exports/*.gpx'"
+    )]
+    pub batch: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "DIR",
+        requires = "batch",
+        help = "Directory that receives batch results"
+    )]
+    pub output_dir: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        requires = "batch",
+        help = "Batch output file name; {stem} and {name} expand to the input file stem and name (default: {stem}.gpx, or an extension matching the report format)"
+    )]
+    pub name_template: Option<String>,
+    #[arg(
+        long,
+        help = "Re-read input files from disk (spooling stdin to a temporary file) instead of buffering them in memory"
+    )]
+    pub stream: bool,
+    #[arg(
+        long,
+        value_name = "BYTES",
+        help = "Reject inputs larger than BYTES (default: 104857600, or no limit with --stream)"
+    )]
+    pub max_input_bytes: Option<u64>,
+}
+
+impl IoArgs {
+    pub fn open_stdin(&self) -> Result<GpxInput, GpxWrenchError> {
+        let stdin = io::stdin();
+        if self.stream {
+            GpxInput::spooled(stdin.lock(), self.max_input_bytes)
+        } else {
+            GpxInput::buffered(
+                stdin.lock(),
+                self.max_input_bytes.unwrap_or(MAX_INPUT_BYTES),
+            )
+        }
+    }
+
+    pub fn open_file(&self, path: &Path) -> Result<GpxInput, GpxWrenchError> {
+        let file = File::open(path).map_err(|e| GpxWrenchError::from(e).for_path(path))?;
+        let input = if self.stream {
+            GpxInput::from_file(file, self.max_input_bytes)
+        } else {
+            GpxInput::buffered(file, self.max_input_bytes.unwrap_or(MAX_INPUT_BYTES))
+        };
+        input
+            .map(|input| input.with_path(path))
+            .map_err(|e| e.for_path(path))
+    }
+
+    /// Returns where a single result is written: the `--output` file or stdout.
+    pub fn output_target(&self) -> OutputTarget {
+        match &self.output {
+            Some(path) => OutputTarget::File(path.clone()),
+            None => OutputTarget::Stdout,
+        }
+    }
+}
+
+/// Where a command writes its result.
+///
+/// File output is written to a temporary file next to the destination and renamed into place
+/// once complete, so a failed run never leaves a partially written file behind.
+#[derive(Debug)]
+pub enum OutputTarget {
+    Stdout,
+    File(PathBuf),
+}
+
+impl OutputTarget {
+    pub fn write_with<F>(&self, write: F) -> Result<(), GpxWrenchError>
+    where
+        F: FnOnce(&mut dyn Write) -> Result<(), GpxWrenchError>,
+    {
+        match self {
+            OutputTarget::Stdout => {
+                let stdout = io::stdout();
+                let mut writer = BufWriter::new(stdout.lock());
+                write(&mut writer)?;
+                writer.flush()?;
+                Ok(())
+            }
+            OutputTarget::File(path) => write_atomically(path, write),
+        }
+    }
+
+    /// Opens the target as an [`OutputFile`], for results that are written in steps rather than
+    /// by a single closure.
+    pub fn create(&self) -> Result<OutputFile, GpxWrenchError> {
+        match self {
+            OutputTarget::Stdout => Err(GpxWrenchError::InvalidArgument(
+                "This output requires an output file (--output or --batch)".into(),
+            )),
+            OutputTarget::File(path) => OutputFile::create(path),
+        }
+    }
+
+    /// Returns the target for the `number`-th of several outputs, e.g. `ride-2.gpx` for
+    /// `ride.gpx`.
+    pub fn numbered(&self, number: usize) -> Result<OutputTarget, GpxWrenchError> {
+        let OutputTarget::File(path) = self else {
+            return Err(GpxWrenchError::InvalidArgument(
+                "Writing several outputs requires an output file (--output or --batch)".into(),
+            ));
+        };
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match path.extension() {
+            Some(extension) => format!("{stem}-{number}.{}", extension.to_string_lossy()),
+            None => format!("{stem}-{number}"),
+        };
+        Ok(OutputTarget::File(path.with_file_name(name)))
+    }
+}
+
+/// Reads each input selected by `args` and hands it to `process` together with the matching
+/// output target.
+///
+/// Without input paths the input is read from stdin. A single input path may be combined with
+/// `--output`; several input paths are only accepted with `--in-place`. In batch mode every
+/// matching file is processed and a summary is printed instead.
+pub fn process_inputs<F>(args: &IoArgs, process: F) -> Result<(), GpxWrenchError>
+where
+    F: FnMut(&mut GpxInput, &OutputTarget) -> Result<(), GpxWrenchError>,
+{
+    process_inputs_with_extension(args, "gpx", process)
+}
+
+/// Like [`process_inputs`], for commands whose output is not GPX: without `--name-template`,
+/// batch results are named `{stem}.<extension>`.
+pub fn process_inputs_with_extension<F>(
+    args: &IoArgs,
+    extension: &str,
+    mut process: F,
+) -> Result<(), GpxWrenchError>
+where
+    F: FnMut(&mut GpxInput, &OutputTarget) -> Result<(), GpxWrenchError>,
+{
+    if let (Some(pattern), Some(output_dir)) = (&args.batch, &args.output_dir) {
+        let name_template = match &args.name_template {
+            Some(name_template) => name_template.clone(),
+            None => format!("{{stem}}.{extension}"),
+        };
+        return process_batch(args, pattern, output_dir, &name_template, process);
+    }
+
+    if args.in_place {
+        if args.inputs.is_empty() {
+            return Err(GpxWrenchError::InvalidArgument(
+                "--in-place requires at least one input file".into(),
+            ));
+        }
+        for path in &args.inputs {
+            let mut input = args.open_file(path)?;
+            process(&mut input, &OutputTarget::File(path.clone())).map_err(|e| e.for_path(path))?;
+        }
+        return Ok(());
+    }
+
+    let output = args.output_target();
+
+    match args.inputs.as_slice() {
+        [] => process(&mut args.open_stdin()?, &output),
+        [path] => {
+            let mut input = args.open_file(path)?;
+            process(&mut input, &output).map_err(|e| e.for_path(path))
+        }
+        _ => Err(GpxWrenchError::InvalidArgument(
+            "Multiple input files require --in-place".into(),
+        )),
+    }
+}
+
+fn write_atomically<F>(path: &Path, write: F) -> Result<(), GpxWrenchError>
+where
+    F: FnOnce(&mut dyn Write) -> Result<(), GpxWrenchError>,
+{
+    let mut file = OutputFile::create(path)?;
+    write(&mut file)?;
+    file.commit()
+}
+
+/// An output file that is written to a temporary file next to its destination and only renamed
+/// into place by [`OutputFile::commit`]. Dropping it before then removes the temporary file.
+#[derive(Debug)]
+pub struct OutputFile {
+    path: PathBuf,
+    temp_path: PathBuf,
+    writer: BufWriter<File>,
+    committed: bool,
+}
+
+impl OutputFile {
+    pub fn create(path: &Path) -> Result<Self, GpxWrenchError> {
+        let file_name = path.file_name().ok_or_else(|| {
+            GpxWrenchError::InvalidArgument("Output path has no file name".into()).for_path(path)
+        })?;
+        let temp_path = path.with_file_name(format!(
+            ".{}.{}.tmp",
+            file_name.to_string_lossy(),
+            std::process::id()
+        ));
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+            .map_err(|e| GpxWrenchError::from(e).for_path(&temp_path))?;
+        Ok(OutputFile {
+            path: path.to_path_buf(),
+            temp_path,
+            writer: BufWriter::new(file),
+            committed: false,
+        })
+    }
+
+    /// Syncs the written data to disk and replaces the destination with it.
+    pub fn commit(mut self) -> Result<(), GpxWrenchError> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()?;
+        // Keep the permissions of a file that is being replaced, e.g. with --in-place.
+        if let Ok(metadata) = fs::metadata(&self.path) {
+            fs::set_permissions(&self.temp_path, metadata.permissions())?;
+        }
+        fs::rename(&self.temp_path, &self.path)?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl Drop for OutputFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
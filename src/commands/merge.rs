@@ -0,0 +1,66 @@
+use crate::commands::io_args::IoArgs;
+use crate::commands::stats::format_time;
+use clap::ValueEnum;
+use gpxwrench::GpxWrenchError;
+use gpxwrench::document::GpxDocument;
+use gpxwrench::merge::{MergeMode, merge_documents};
+
+/// How `merge` combines the tracks of its inputs.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MergeInto {
+    /// Keep every track, ordered by start time
+    Tracks,
+    /// One track with a segment per input segment
+    #[default]
+    Segments,
+    /// One track with a single continuous segment
+    Continuous,
+}
+
+pub fn merge_command(into: MergeInto, io_args: &IoArgs) -> Result<(), GpxWrenchError> {
+    if io_args.in_place || io_args.batch.is_some() {
+        return Err(GpxWrenchError::InvalidArgument(
+            "merge writes a single output and cannot be used with --in-place or --batch".into(),
+        ));
+    }
+    if io_args.inputs.is_empty() {
+        return Err(GpxWrenchError::InvalidArgument(
+            "merge requires input files".into(),
+        ));
+    }
+
+    let documents = io_args
+        .inputs
+        .iter()
+        .map(|path| {
+            let mut input = io_args.open_file(path)?;
+            GpxDocument::parse(input.reader()?).map_err(|e| e.for_path(path))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mode = match into {
+        MergeInto::Tracks => MergeMode::Tracks,
+        MergeInto::Segments => MergeMode::Segments,
+        MergeInto::Continuous => MergeMode::Continuous,
+    };
+    let result = merge_documents(documents, mode);
+
+    for overlap in &result.overlaps {
+        eprintln!(
+            "Warning: {} and {} both cover {} to {}",
+            io_args.inputs[overlap.first].display(),
+            io_args.inputs[overlap.second].display(),
+            format_time(overlap.start)?,
+            format_time(overlap.end)?
+        );
+    }
+    if result.duplicate_waypoints > 0 {
+        eprintln!(
+            "Removed {} duplicate waypoint(s)",
+            result.duplicate_waypoints
+        );
+    }
+
+    io_args
+        .output_target()
+        .write_with(|writer| result.document.write_to(writer))
+}
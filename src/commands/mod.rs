@@ -1,2 +1,13 @@
+pub mod batch;
+pub mod clean;
+pub mod io_args;
+pub mod merge;
+pub mod privacy;
+pub mod remove_pauses;
+pub mod resample;
+pub mod simplify;
+pub mod smooth;
+pub mod split;
+pub mod stats;
 pub mod trim;
 pub mod trim_to_activity;
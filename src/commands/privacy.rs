@@ -0,0 +1,84 @@
+use crate::commands::io_args::{IoArgs, process_inputs};
+use gpxwrench::gpxxml::{GroupSplit, filter_xml_by_selector, find_track_distances};
+use gpxwrench::privacy::{
+    Anonymization, PrivacySelector, anonymize_xml, days_until, find_earliest_time, parse_circle,
+    parse_polygons,
+};
+use gpxwrench::{GpxInput, GpxWrenchError, parse_distance};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use time::Date;
+use time::format_description::well_known::Iso8601;
+
+pub fn privacy_command(
+    zones: &[String],
+    zone_files: &[PathBuf],
+    trim_ends: Option<&str>,
+    shift_date: Option<&str>,
+    strip_metadata: bool,
+    io_args: &IoArgs,
+) -> Result<(), GpxWrenchError> {
+    let mut parsed_zones = zones
+        .iter()
+        .map(|zone| parse_circle(zone))
+        .collect::<Result<Vec<_>, _>>()?;
+    for path in zone_files {
+        let text = fs::read_to_string(path).map_err(|e| GpxWrenchError::from(e).for_path(path))?;
+        parsed_zones.extend(parse_polygons(&text).map_err(|e| e.for_path(path))?);
+    }
+    let trim_ends = trim_ends.map(parse_distance).transpose()?;
+    let shift_date = shift_date
+        .map(|date| {
+            Date::parse(date, &Iso8601::DEFAULT)
+                .map_err(|e| GpxWrenchError::InvalidArgument(format!("Invalid date {date:?}: {e}")))
+        })
+        .transpose()?;
+    let removes_points = !parsed_zones.is_empty() || trim_ends.is_some();
+
+    process_inputs(io_args, |input, output| {
+        let time_shift = match shift_date {
+            Some(date) => {
+                find_earliest_time(input.reader()?)?.map(|earliest| days_until(earliest, date))
+            }
+            None => None,
+        };
+        let anonymization = Anonymization {
+            zones: parsed_zones.clone(),
+            strip_bounds: removes_points,
+            time_shift,
+            strip_metadata,
+        };
+        if !removes_points {
+            return output
+                .write_with(|writer| anonymize_xml(input.reader()?, &anonymization, writer));
+        }
+
+        let mut selector = PrivacySelector::new(parsed_zones.clone());
+        if let Some(meters) = trim_ends {
+            selector = selector.with_trimmed_ends(meters, find_track_distances(input.reader()?)?);
+        }
+        let mut filter = |filtered: &mut dyn Write| {
+            filter_xml_by_selector(
+                input.reader()?,
+                &mut selector,
+                GroupSplit::Segment,
+                filtered,
+            )
+        };
+        // The filtered document is kept like the input: in memory, or in a temporary file
+        // with --stream.
+        output.write_with(|writer| {
+            if io_args.stream {
+                let mut filtered = GpxInput::spooled_with(filter)?;
+                anonymize_xml(filtered.reader()?, &anonymization, writer)
+            } else {
+                let mut filtered = Vec::new();
+                filter(&mut filtered)?;
+                anonymize_xml(filtered.as_slice(), &anonymization, writer)
+            }
+        })?;
+        eprintln!("Removed {} of {} points", selector.removed, selector.total);
+        Ok(())
+    })
+}
@@ -0,0 +1,31 @@
+use crate::commands::io_args::{IoArgs, process_inputs};
+use crate::commands::trim_to_activity::validate_speed_threshold;
+use gpxwrench::GpxWrenchError;
+use gpxwrench::gpxxml::{
+    GroupSplit, SegmentPlanSelector, extract_track_segments, filter_xml_by_selector,
+};
+use gpxwrench::pauses::pause_groups;
+use time::Duration;
+
+pub fn remove_pauses_command(
+    speed_threshold: f64,
+    min_pause: u64,
+    io_args: &IoArgs,
+) -> Result<(), GpxWrenchError> {
+    validate_speed_threshold(speed_threshold)?;
+    let min_pause = Duration::seconds(i64::try_from(min_pause).map_err(|_| {
+        GpxWrenchError::InvalidArgument("Minimum pause exceeds supported duration range".into())
+    })?);
+
+    process_inputs(io_args, |input, output| {
+        let groups = extract_track_segments(input.reader()?)?
+            .iter()
+            .map(|segment| pause_groups(segment, speed_threshold, min_pause))
+            .collect();
+        let mut selector = SegmentPlanSelector::new(groups);
+
+        output.write_with(|writer| {
+            filter_xml_by_selector(input.reader()?, &mut selector, GroupSplit::Segment, writer)
+        })
+    })
+}
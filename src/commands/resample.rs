@@ -0,0 +1,84 @@
+use crate::commands::io_args::{IoArgs, process_inputs};
+use gpxwrench::document::GpxDocument;
+use gpxwrench::resample::{ResampleStep, resample_document};
+use gpxwrench::{GpxWrenchError, parse_distance, parse_duration};
+
+/// Parses the step of `resample` from `--every-time` or `--every-distance`, exactly one of
+/// which is given.
+fn parse_step(
+    every_time: Option<&str>,
+    every_distance: Option<&str>,
+) -> Result<ResampleStep, GpxWrenchError> {
+    let step = match (every_time, every_distance) {
+        (Some(duration), None) => ResampleStep::Time(parse_duration(duration)?),
+        (None, Some(distance)) => ResampleStep::Distance(parse_distance(distance)?),
+        _ => {
+            return Err(GpxWrenchError::InvalidArgument(
+                "Pass exactly one of --every-time and --every-distance".into(),
+            ));
+        }
+    };
+    let is_positive = match step {
+        ResampleStep::Time(duration) => duration.is_positive(),
+        ResampleStep::Distance(meters) => meters.is_finite() && meters > 0.0,
+    };
+    if !is_positive {
+        return Err(GpxWrenchError::InvalidArgument(
+            "Resampling step must be greater than zero".into(),
+        ));
+    }
+    Ok(step)
+}
+
+pub fn resample_command(
+    every_time: Option<&str>,
+    every_distance: Option<&str>,
+    max_gap: &str,
+    io_args: &IoArgs,
+) -> Result<(), GpxWrenchError> {
+    let step = parse_step(every_time, every_distance)?;
+    let max_gap = parse_duration(max_gap)?;
+    if max_gap.is_negative() {
+        return Err(GpxWrenchError::InvalidArgument(
+            "--max-gap must not be negative".into(),
+        ));
+    }
+
+    process_inputs(io_args, |input, output| {
+        let mut document = GpxDocument::parse(input.reader()?)?;
+        resample_document(&mut document, step, max_gap)?;
+        output.write_with(|writer| document.write_to(writer))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::Duration;
+
+    #[test]
+    fn test_parse_step() {
+        assert_eq!(
+            parse_step(Some("1s"), None).unwrap(),
+            ResampleStep::Time(Duration::seconds(1))
+        );
+        // Minutes, as in --max-gap and trim ranges.
+        assert_eq!(
+            parse_step(Some("5m"), None).unwrap(),
+            ResampleStep::Time(Duration::minutes(5))
+        );
+        assert_eq!(
+            parse_step(None, Some("10m")).unwrap(),
+            ResampleStep::Distance(10.0)
+        );
+        assert_eq!(
+            parse_step(None, Some("1km")).unwrap(),
+            ResampleStep::Distance(1000.0)
+        );
+        assert!(parse_step(Some("0s"), None).is_err());
+        assert!(parse_step(None, Some("0m")).is_err());
+        assert!(parse_step(None, Some("10s")).is_err());
+        assert!(parse_step(Some("fast"), None).is_err());
+        assert!(parse_step(None, None).is_err());
+    }
+}
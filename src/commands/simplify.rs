@@ -0,0 +1,52 @@
+use crate::commands::io_args::{IoArgs, process_inputs};
+use clap::ValueEnum;
+use gpxwrench::GpxWrenchError;
+use gpxwrench::gpxxml::{
+    GroupSplit, SegmentPlanSelector, extract_positioned_segments, filter_xml_by_selector,
+};
+use gpxwrench::simplify::{SimplifyAlgorithm, simplify};
+
+/// Simplification algorithm used by `simplify`.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SimplifyMethod {
+    /// Ramer–Douglas–Peucker: keep points farther than the tolerance from the simplified line
+    #[default]
+    Rdp,
+    /// Visvalingam–Whyatt: drop points forming triangles smaller than the tolerance squared
+    Visvalingam,
+}
+
+pub fn simplify_command(
+    tolerance: f64,
+    method: SimplifyMethod,
+    use_elevation: bool,
+    io_args: &IoArgs,
+) -> Result<(), GpxWrenchError> {
+    if !tolerance.is_finite() || tolerance < 0.0 {
+        return Err(GpxWrenchError::InvalidArgument(
+            "Tolerance must be a finite non-negative number".into(),
+        ));
+    }
+    let algorithm = match method {
+        SimplifyMethod::Rdp => SimplifyAlgorithm::RamerDouglasPeucker,
+        SimplifyMethod::Visvalingam => SimplifyAlgorithm::VisvalingamWhyatt,
+    };
+
+    process_inputs(io_args, |input, output| {
+        // The shape only depends on positions, so points without a time are simplified too.
+        let groups = extract_positioned_segments(input.reader()?)?
+            .into_iter()
+            .map(|segment| {
+                simplify(&segment, tolerance, algorithm, use_elevation)
+                    .into_iter()
+                    .map(|keep| keep.then_some(0))
+                    .collect()
+            })
+            .collect();
+        let mut selector = SegmentPlanSelector::new(groups).including_untimed();
+
+        output.write_with(|writer| {
+            filter_xml_by_selector(input.reader()?, &mut selector, GroupSplit::Segment, writer)
+        })
+    })
+}
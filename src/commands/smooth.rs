@@ -0,0 +1,76 @@
+use crate::commands::io_args::{IoArgs, process_inputs};
+use clap::ValueEnum;
+use gpxwrench::GpxWrenchError;
+use gpxwrench::gpxxml::{
+    GroupSplit, SegmentPlanSelector, extract_track_segments, filter_xml_by_selector,
+};
+use gpxwrench::smooth::{KalmanOptions, SmoothingMethod, smooth};
+
+/// Smoothing method used by `smooth`.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SmoothMethod {
+    /// Constant-velocity Kalman filter, weighting points by their HDOP
+    #[default]
+    Kalman,
+    /// Mean position of a window of neighbouring points
+    MovingAverage,
+}
+
+pub fn smooth_command(
+    method: SmoothMethod,
+    process_noise: f64,
+    measurement_noise: f64,
+    forward_only: bool,
+    window: usize,
+    io_args: &IoArgs,
+) -> Result<(), GpxWrenchError> {
+    let method = match method {
+        SmoothMethod::Kalman => {
+            for (name, value) in [
+                ("Process noise", process_noise),
+                ("Measurement noise", measurement_noise),
+            ] {
+                if !value.is_finite() || value <= 0.0 {
+                    return Err(GpxWrenchError::InvalidArgument(format!(
+                        "{name} must be a finite positive number"
+                    )));
+                }
+            }
+            SmoothingMethod::Kalman(KalmanOptions {
+                process_noise,
+                measurement_noise,
+                backward_pass: !forward_only,
+            })
+        }
+        SmoothMethod::MovingAverage => {
+            if window == 0 {
+                return Err(GpxWrenchError::InvalidArgument(
+                    "Window must be at least 1".into(),
+                ));
+            }
+            SmoothingMethod::MovingAverage { radius: window / 2 }
+        }
+    };
+
+    process_inputs(io_args, |input, output| {
+        let segments = extract_track_segments(input.reader()?)?;
+        let groups = segments
+            .iter()
+            .map(|segment| vec![Some(0); segment.len()])
+            .collect();
+        let positions = segments
+            .iter()
+            .map(|segment| {
+                smooth(segment, &method)
+                    .iter()
+                    .map(|point| (point.lat, point.lon))
+                    .collect()
+            })
+            .collect();
+        let mut selector = SegmentPlanSelector::new(groups).with_positions(positions);
+
+        output.write_with(|writer| {
+            filter_xml_by_selector(input.reader()?, &mut selector, GroupSplit::Segment, writer)
+        })
+    })
+}
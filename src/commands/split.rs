@@ -0,0 +1,93 @@
+use crate::commands::io_args::{IoArgs, OutputFile, process_inputs};
+use clap::ValueEnum;
+use gpxwrench::gpxxml::{
+    GroupSplit, SplitCriteria, SplitSelector, filter_xml_by_selector, split_xml_by_selector,
+};
+use gpxwrench::{GpxWrenchError, parse_distance, parse_duration, parse_utc_offset};
+use time::{Duration, UtcOffset};
+
+/// Where `split` writes each part of a track.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SplitInto {
+    /// One track segment per part
+    #[default]
+    Segment,
+    /// One track per part
+    Track,
+    /// One file per part, numbered after the output file
+    File,
+}
+
+pub fn split_command(
+    gap: Option<&str>,
+    day: bool,
+    tz: Option<&str>,
+    distance: Option<&str>,
+    points: Option<usize>,
+    into: SplitInto,
+    io_args: &IoArgs,
+) -> Result<(), GpxWrenchError> {
+    let criteria = SplitCriteria {
+        max_gap: gap.map(parse_gap).transpose()?,
+        day_offset: day
+            .then(|| tz.map_or(Ok(UtcOffset::UTC), parse_utc_offset))
+            .transpose()?,
+        distance: distance.map(parse_part_distance).transpose()?,
+        points: points
+            .map(|points| {
+                if points == 0 {
+                    return Err(GpxWrenchError::InvalidArgument(
+                        "--points must be at least 1".into(),
+                    ));
+                }
+                Ok(points)
+            })
+            .transpose()?,
+    };
+
+    process_inputs(io_args, |input, output| {
+        let group_split = match into {
+            SplitInto::Segment => GroupSplit::Segment,
+            SplitInto::Track => GroupSplit::Track,
+            SplitInto::File => {
+                let parts = split_xml_by_selector(
+                    input.reader()?,
+                    &mut SplitSelector::new(criteria),
+                    |part| output.numbered(part + 1)?.create(),
+                    OutputFile::commit,
+                )?;
+                if parts == 0 {
+                    return Err(GpxWrenchError::InsufficientData(
+                        "No track points to split into files".into(),
+                    ));
+                }
+                return Ok(());
+            }
+        };
+
+        let mut selector = SplitSelector::new(criteria);
+        output.write_with(|writer| {
+            filter_xml_by_selector(input.reader()?, &mut selector, group_split, writer)
+        })
+    })
+}
+
+fn parse_gap(s: &str) -> Result<Duration, GpxWrenchError> {
+    let gap = parse_duration(s)?;
+    if gap.is_negative() {
+        return Err(GpxWrenchError::InvalidArgument(
+            "--gap must not be negative".into(),
+        ));
+    }
+    Ok(gap)
+}
+
+fn parse_part_distance(s: &str) -> Result<f64, GpxWrenchError> {
+    let distance = parse_distance(s)?;
+    if distance <= 0.0 {
+        return Err(GpxWrenchError::InvalidArgument(
+            "--distance must be greater than zero".into(),
+        ));
+    }
+    Ok(distance)
+}
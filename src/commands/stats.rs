@@ -0,0 +1,206 @@
+use crate::commands::io_args::{IoArgs, process_inputs_with_extension};
+use crate::commands::trim_to_activity::validate_speed_threshold;
+use gpxwrench::GpxWrenchError;
+use gpxwrench::gpxxml::extract_positioned_segments;
+use gpxwrench::stats::{TrackStats, compute_track_stats};
+use std::io::Write;
+use time::format_description::well_known::Rfc3339;
+use time::{Duration, OffsetDateTime};
+
+pub fn stats_command(
+    speed_threshold: f64,
+    json: bool,
+    io_args: &IoArgs,
+) -> Result<(), GpxWrenchError> {
+    validate_speed_threshold(speed_threshold)?;
+    if io_args.in_place {
+        return Err(GpxWrenchError::InvalidArgument(
+            "stats does not modify its input and cannot be used with --in-place".into(),
+        ));
+    }
+
+    let extension = if json { "json" } else { "txt" };
+    process_inputs_with_extension(io_args, extension, |input, output| {
+        let segments = extract_positioned_segments(input.reader()?)?;
+        let stats = compute_track_stats(&segments, speed_threshold);
+
+        output.write_with(|writer| {
+            if json {
+                write_json(writer, &stats)
+            } else {
+                write_text(writer, &stats)
+            }
+        })
+    })
+}
+
+fn write_text(writer: &mut dyn Write, stats: &TrackStats) -> Result<(), GpxWrenchError> {
+    let speed = |speed: Option<f64>| match speed {
+        Some(speed) => format!("{:.1} km/h ({speed:.2} m/s)", speed * 3.6),
+        None => "-".to_string(),
+    };
+    let time = |time: Option<OffsetDateTime>| match time {
+        Some(time) => format_time(time),
+        None => Ok("-".to_string()),
+    };
+
+    writeln!(writer, "Points:          {}", stats.point_count)?;
+    writeln!(writer, "Segments:        {}", stats.segment_count)?;
+    writeln!(writer, "Distance:        {:.2} km", stats.distance / 1000.0)?;
+    writeln!(writer, "Start:           {}", time(stats.start_time)?)?;
+    writeln!(writer, "End:             {}", time(stats.end_time)?)?;
+    writeln!(
+        writer,
+        "Elapsed time:    {}",
+        format_duration(stats.elapsed_time)
+    )?;
+    writeln!(
+        writer,
+        "Moving time:     {}",
+        format_duration(stats.moving_time)
+    )?;
+    writeln!(writer, "Average speed:   {}", speed(stats.average_speed()))?;
+    writeln!(
+        writer,
+        "Moving speed:    {}",
+        speed(stats.average_moving_speed())
+    )?;
+    writeln!(writer, "Max speed:       {}", speed(Some(stats.max_speed)))?;
+    writeln!(writer, "Elevation gain:  {:.1} m", stats.elevation_gain)?;
+    writeln!(writer, "Elevation loss:  {:.1} m", stats.elevation_loss)?;
+    match &stats.bounds {
+        Some(bounds) => writeln!(
+            writer,
+            "Bounds:          {:.6},{:.6} to {:.6},{:.6}",
+            bounds.min_lat, bounds.min_lon, bounds.max_lat, bounds.max_lon
+        )?,
+        None => writeln!(writer, "Bounds:          -")?,
+    }
+    Ok(())
+}
+
+fn write_json(writer: &mut dyn Write, stats: &TrackStats) -> Result<(), GpxWrenchError> {
+    // JSON has no NaN or infinity, so such values are written as null.
+    let number = |value: Option<f64>| {
+        value
+            .filter(|value| value.is_finite())
+            .map_or("null".to_string(), |value| value.to_string())
+    };
+    let time = |time: Option<OffsetDateTime>| match time {
+        Some(time) => Ok(format!("\"{}\"", format_time(time)?)),
+        None => Ok::<_, GpxWrenchError>("null".to_string()),
+    };
+    let bounds = match &stats.bounds {
+        Some(bounds) => format!(
+            "{{\"min_lat\": {}, \"min_lon\": {}, \"max_lat\": {}, \"max_lon\": {}}}",
+            number(Some(bounds.min_lat)),
+            number(Some(bounds.min_lon)),
+            number(Some(bounds.max_lat)),
+            number(Some(bounds.max_lon))
+        ),
+        None => "null".to_string(),
+    };
+
+    writeln!(writer, "{{")?;
+    writeln!(writer, "  \"points\": {},", stats.point_count)?;
+    writeln!(writer, "  \"segments\": {},", stats.segment_count)?;
+    writeln!(
+        writer,
+        "  \"distance_m\": {},",
+        number(Some(stats.distance))
+    )?;
+    writeln!(writer, "  \"start_time\": {},", time(stats.start_time)?)?;
+    writeln!(writer, "  \"end_time\": {},", time(stats.end_time)?)?;
+    writeln!(
+        writer,
+        "  \"elapsed_s\": {},",
+        number(Some(stats.elapsed_time.as_seconds_f64()))
+    )?;
+    writeln!(
+        writer,
+        "  \"moving_s\": {},",
+        number(Some(stats.moving_time.as_seconds_f64()))
+    )?;
+    writeln!(
+        writer,
+        "  \"average_speed_mps\": {},",
+        number(stats.average_speed())
+    )?;
+    writeln!(
+        writer,
+        "  \"average_moving_speed_mps\": {},",
+        number(stats.average_moving_speed())
+    )?;
+    writeln!(
+        writer,
+        "  \"max_speed_mps\": {},",
+        number(Some(stats.max_speed))
+    )?;
+    writeln!(
+        writer,
+        "  \"elevation_gain_m\": {},",
+        number(Some(stats.elevation_gain))
+    )?;
+    writeln!(
+        writer,
+        "  \"elevation_loss_m\": {},",
+        number(Some(stats.elevation_loss))
+    )?;
+    writeln!(writer, "  \"bounds\": {bounds}")?;
+    writeln!(writer, "}}")?;
+    Ok(())
+}
+
+pub fn format_time(time: OffsetDateTime) -> Result<String, GpxWrenchError> {
+    time.format(&Rfc3339)
+        .map_err(|e| GpxWrenchError::TimestampOverflow(format!("Cannot format time: {e}")))
+}
+
+/// Formats a duration as `H:MM:SS`, rounded down to whole seconds.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.whole_seconds();
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::ZERO), "0:00:00");
+        assert_eq!(format_duration(Duration::seconds(3725)), "1:02:05");
+        assert_eq!(format_duration(Duration::milliseconds(59_999)), "0:00:59");
+    }
+
+    #[test]
+    fn test_write_json_writes_non_finite_numbers_as_null() {
+        let stats = TrackStats {
+            point_count: 2,
+            segment_count: 1,
+            distance: 10.0,
+            timed_distance: 10.0,
+            start_time: None,
+            end_time: None,
+            elapsed_time: Duration::ZERO,
+            moving_time: Duration::ZERO,
+            moving_distance: 0.0,
+            max_speed: f64::INFINITY,
+            elevation_gain: 1.5,
+            elevation_loss: f64::NAN,
+            bounds: None,
+        };
+        let mut output = Vec::new();
+        write_json(&mut output, &stats).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("\"max_speed_mps\": null,"));
+        assert!(output.contains("\"elevation_gain_m\": 1.5,"));
+        assert!(output.contains("\"elevation_loss_m\": null,"));
+        assert!(!output.contains("NaN") && !output.contains("inf"));
+    }
+}
@@ -1,32 +1,121 @@
-use crate::gpxxml::{filter_xml_by_time_range, find_minimum_time};
-use gpxwrench::{MAX_INPUT_BYTES, TrimRange, parse_range, read_to_end_limited};
-use std::error::Error;
-use std::io;
-use time::OffsetDateTime;
+use crate::commands::io_args::{IoArgs, OutputFile, OutputTarget, process_inputs};
+use clap::ValueEnum;
+use gpxwrench::gpxxml::{
+    Interval, IntervalSet, TimeInterval, filter_xml_by_distance_intervals,
+    filter_xml_by_time_intervals, find_time_bounds, find_track_distance,
+};
+use gpxwrench::{
+    DistanceRange, GpxInput, GpxWrenchError, TrimRange, parse_distance_ranges,
+    parse_ranges_with_tz, parse_utc_offset,
+};
+use std::io::Write;
 
-pub fn trim_command(range_str: &str) -> Result<(), Box<dyn Error>> {
-    let range = parse_range(range_str)?;
+/// What the ranges given to `trim` measure.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TrimBy {
+    /// Time offsets or datetimes
+    #[default]
+    Time,
+    /// Distance along the track, e.g. 500m,12km
+    Distance,
+}
 
-    let stdin = io::stdin();
-    let input = read_to_end_limited(stdin.lock(), MAX_INPUT_BYTES)?;
+pub fn trim_command(
+    ranges_str: &str,
+    by: TrimBy,
+    tz: Option<&str>,
+    split_output: bool,
+    io_args: &IoArgs,
+) -> Result<(), GpxWrenchError> {
+    match by {
+        TrimBy::Time => {
+            let tz = tz.map(parse_utc_offset).transpose()?;
+            let ranges = parse_ranges_with_tz(ranges_str, tz)?;
+            process_inputs(io_args, |input, output| {
+                let intervals = resolve_time_ranges(&ranges, input)?;
+                write_intervals(input, output, &intervals, split_output, |input, set, w| {
+                    filter_xml_by_time_intervals(input.reader()?, set, w)
+                })
+            })
+        }
+        TrimBy::Distance => {
+            if tz.is_some() {
+                return Err(GpxWrenchError::InvalidArgument(
+                    "--tz only applies to time ranges".into(),
+                ));
+            }
+            let ranges = parse_distance_ranges(ranges_str)?;
+            process_inputs(io_args, |input, output| {
+                let intervals = resolve_distance_ranges(&ranges, input)?;
+                write_intervals(input, output, &intervals, split_output, |input, set, w| {
+                    filter_xml_by_distance_intervals(input.reader()?, set, w)
+                })
+            })
+        }
+    }
+}
 
-    let min_time = find_minimum_time(&input)?;
+fn resolve_time_ranges(
+    ranges: &[TrimRange],
+    input: &mut GpxInput,
+) -> Result<Vec<TimeInterval>, GpxWrenchError> {
+    let track_times = if ranges.iter().any(TrimRange::needs_track_times) {
+        find_time_bounds(input.reader()?)?
+    } else {
+        None
+    };
+    ranges
+        .iter()
+        .map(|range| range.resolve(track_times))
+        .collect()
+}
 
-    let (start_threshold, end_threshold) = if let Some(min_t) = min_time {
-        let (TrimRange::Duration { start, end } | TrimRange::Timestamp { start, end }) = range;
-        (
-            min_t
-                .checked_add(start)
-                .ok_or("Trim start exceeds supported timestamp range")?,
-            min_t
-                .checked_add(end)
-                .ok_or("Trim end exceeds supported timestamp range")?,
-        )
+fn resolve_distance_ranges(
+    ranges: &[DistanceRange],
+    input: &mut GpxInput,
+) -> Result<Vec<Interval<f64>>, GpxWrenchError> {
+    let total_distance = if ranges.iter().any(DistanceRange::needs_total_distance) {
+        find_track_distance(input.reader()?)?
     } else {
-        (OffsetDateTime::UNIX_EPOCH, OffsetDateTime::UNIX_EPOCH)
+        0.0
     };
+    Ok(ranges
+        .iter()
+        .map(|range| range.resolve(total_distance))
+        .collect())
+}
 
-    filter_xml_by_time_range(&input, start_threshold, end_threshold)?;
+/// Writes the points within `intervals` to `output`, merging overlapping intervals. With
+/// `split_output`, every interval is written to its own numbered output instead, in the order the
+/// ranges were given, so overlapping ranges share points; a range without any points is then an
+/// error and no file is written.
+fn write_intervals<T, F>(
+    input: &mut GpxInput,
+    output: &OutputTarget,
+    intervals: &[Interval<T>],
+    split_output: bool,
+    filter: F,
+) -> Result<(), GpxWrenchError>
+where
+    T: PartialOrd + Copy,
+    F: Fn(&mut GpxInput, &IntervalSet<T>, &mut dyn Write) -> Result<usize, GpxWrenchError>,
+{
+    if !split_output {
+        let intervals = IntervalSet::new(intervals.iter().copied());
+        return output.write_with(|writer| filter(input, &intervals, writer).map(drop));
+    }
 
-    Ok(())
+    // Files are only put in place once every range turned out to have points.
+    let mut files = Vec::with_capacity(intervals.len());
+    for (index, interval) in intervals.iter().enumerate() {
+        let mut file = output.numbered(index + 1)?.create()?;
+        if filter(input, &IntervalSet::new([*interval]), &mut file)? == 0 {
+            return Err(GpxWrenchError::InsufficientData(format!(
+                "Range {} contains no track points",
+                index + 1
+            )));
+        }
+        files.push(file);
+    }
+    files.into_iter().try_for_each(OutputFile::commit)
 }
@@ -1,20 +1,431 @@
-use crate::gpxxml::{extract_track_points, filter_xml_by_time_range_inclusive_end};
-use gpxwrench::{MAX_INPUT_BYTES, detect_activity_bounds, read_to_end_limited};
-use std::error::Error;
-use std::io;
+use crate::commands::io_args::{IoArgs, process_inputs_with_extension};
+use crate::commands::stats::{format_duration, format_time};
+use clap::{Args, ValueEnum};
+use gpxwrench::gpxxml::{
+    GroupSplit, Interval, IntervalSet, TimeIntervalSelector, extract_track_points,
+    filter_xml_by_selector, filter_xml_by_time_range_inclusive_end,
+};
+use gpxwrench::{
+    ActivityAnalysis, ActivityOptions, GpxWrenchError, SpeedFilter, TrackPoint, analyze_activity,
+    calculate_speed, detect_activity_bounds_with_options, detect_activity_intervals_with_options,
+    haversine_distance,
+};
+use std::io::Write;
+use time::{Duration, OffsetDateTime};
 
-pub fn trim_to_activity_command(speed_threshold: f64, buffer: u64) -> Result<(), Box<dyn Error>> {
-    if !speed_threshold.is_finite() || speed_threshold < 0.0 {
-        return Err("Speed threshold must be a finite non-negative number".into());
+/// Preset detection parameters for common kinds of activity.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActivityProfile {
+    /// Walking and hiking: low thresholds, long confirmation and generous buffer
+    Hike,
+    /// Running
+    Run,
+    /// Cycling
+    Bike,
+    /// Driving: high thresholds, no smoothing
+    Drive,
+}
+
+impl ActivityProfile {
+    fn options(self) -> ActivityOptions {
+        let (start_threshold, stop_threshold, min_run, speed_filter, buffer_seconds) = match self {
+            ActivityProfile::Hike => (0.5, 0.3, 5, SpeedFilter::Median(5), 60),
+            ActivityProfile::Run => (1.5, 1.0, 4, SpeedFilter::Median(5), 30),
+            ActivityProfile::Bike => (2.0, 1.5, 3, SpeedFilter::Median(5), 30),
+            ActivityProfile::Drive => (5.0, 3.0, 3, SpeedFilter::None, 15),
+        };
+        ActivityOptions {
+            start_threshold,
+            stop_threshold,
+            min_run,
+            speed_filter,
+            buffer_seconds,
+        }
     }
+}
+
+/// Speed smoothing applied before activity detection.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Smoothing {
+    None,
+    /// Rolling mean
+    Mean,
+    /// Rolling median, robust against single GPS jumps
+    Median,
+}
+
+const DEFAULT_SMOOTH_WINDOW: usize = 5;
+
+/// Activity detection parameters shared by commands that detect activity.
+#[derive(Args, Debug)]
+pub struct DetectionArgs {
+    #[arg(
+        long,
+        value_enum,
+        help = "Preset detection parameters; explicitly given options take precedence"
+    )]
+    pub profile: Option<ActivityProfile>,
+    #[arg(
+        long,
+        short,
+        help = "Minimum speed (m/s) to consider as activity [default: 1.0]"
+    )]
+    pub speed_threshold: Option<f64>,
+    #[arg(
+        long,
+        help = "Speed (m/s) below which an activity ends [default: the speed threshold]"
+    )]
+    pub stop_threshold: Option<f64>,
+    #[arg(
+        long,
+        short,
+        help = "Buffer time (seconds) to add before/after detected activity [default: 30]"
+    )]
+    pub buffer: Option<u64>,
+    #[arg(
+        long,
+        value_name = "INTERVALS",
+        help = "Consecutive fast intervals needed to start an activity [default: 3]"
+    )]
+    pub min_run: Option<usize>,
+    #[arg(
+        long,
+        value_enum,
+        help = "Smooth speeds before detection [default: none]"
+    )]
+    pub smooth: Option<Smoothing>,
+    #[arg(
+        long,
+        value_name = "INTERVALS",
+        help = "Number of intervals averaged by --smooth [default: 5]"
+    )]
+    pub smooth_window: Option<usize>,
+}
+
+impl DetectionArgs {
+    /// Combines the explicitly given options with the selected profile, or with the defaults.
+    pub fn options(&self) -> Result<ActivityOptions, GpxWrenchError> {
+        let mut options = self
+            .profile
+            .map_or_else(|| ActivityOptions::new(1.0, 30), ActivityProfile::options);
+
+        if let Some(threshold) = self.speed_threshold {
+            options.start_threshold = threshold;
+            options.stop_threshold = threshold;
+        }
+        if let Some(threshold) = self.stop_threshold {
+            options.stop_threshold = threshold;
+        }
+        if let Some(buffer) = self.buffer {
+            options.buffer_seconds = buffer;
+        }
+        if let Some(min_run) = self.min_run {
+            options.min_run = min_run;
+        }
+        let window = match (self.smooth_window, options.speed_filter) {
+            (Some(window), _) => window,
+            (None, SpeedFilter::Mean(window) | SpeedFilter::Median(window)) => window,
+            (None, SpeedFilter::None) => DEFAULT_SMOOTH_WINDOW,
+        };
+        options.speed_filter = match self.smooth {
+            Some(Smoothing::None) => SpeedFilter::None,
+            Some(Smoothing::Mean) => SpeedFilter::Mean(window),
+            Some(Smoothing::Median) => SpeedFilter::Median(window),
+            None => match options.speed_filter {
+                SpeedFilter::None => SpeedFilter::None,
+                SpeedFilter::Mean(_) => SpeedFilter::Mean(window),
+                SpeedFilter::Median(_) => SpeedFilter::Median(window),
+            },
+        };
+
+        validate_speed_threshold(options.start_threshold)?;
+        validate_speed_threshold(options.stop_threshold)?;
+        if options.stop_threshold > options.start_threshold {
+            return Err(GpxWrenchError::InvalidArgument(
+                "Stop threshold must not exceed the speed threshold".into(),
+            ));
+        }
+        if options.min_run == 0 {
+            return Err(GpxWrenchError::InvalidArgument(
+                "Minimum run must be at least 1".into(),
+            ));
+        }
+        if matches!(
+            options.speed_filter,
+            SpeedFilter::Mean(0) | SpeedFilter::Median(0)
+        ) {
+            return Err(GpxWrenchError::InvalidArgument(
+                "Smoothing window must be at least 1".into(),
+            ));
+        }
+        Ok(options)
+    }
+}
+
+/// What `trim-to-activity` writes instead of the trimmed GPX.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ActivityReport {
+    /// The trimmed GPX
+    #[default]
+    None,
+    /// A summary of what would be trimmed
+    DryRun,
+    /// A CSV line per point with its speed and classification
+    Explain,
+}
+
+/// How `trim-to-activity --max-pause` writes separate activities.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ActivitySplit {
+    /// One track segment per activity
+    #[default]
+    Segment,
+    /// One track per activity
+    Track,
+    /// One file per activity, numbered after the output file
+    File,
+}
+
+pub fn trim_to_activity_command(
+    detection: &DetectionArgs,
+    max_pause: Option<u64>,
+    split: ActivitySplit,
+    report: ActivityReport,
+    io_args: &IoArgs,
+) -> Result<(), GpxWrenchError> {
+    let options = detection.options()?;
+    let extension = match report {
+        ActivityReport::None => "gpx",
+        ActivityReport::DryRun => "txt",
+        ActivityReport::Explain => "csv",
+    };
+
+    process_inputs_with_extension(io_args, extension, |input, output| {
+        let track_points = extract_track_points(input.reader()?)?;
+
+        match report {
+            ActivityReport::None => {}
+            ActivityReport::DryRun => {
+                let analysis = analyze_activity(&track_points, &options)?;
+                return output.write_with(|writer| {
+                    write_dry_run(writer, &track_points, &analysis, &options, max_pause)
+                });
+            }
+            ActivityReport::Explain => {
+                let analysis = analyze_activity(&track_points, &options)?;
+                return output
+                    .write_with(|writer| write_explanation(writer, &track_points, &analysis));
+            }
+        }
 
-    let stdin = io::stdin();
-    let input = read_to_end_limited(stdin.lock(), MAX_INPUT_BYTES)?;
+        let Some(max_pause) = max_pause else {
+            let (start_time, end_time) =
+                detect_activity_bounds_with_options(&track_points, &options)?;
 
-    let track_points = extract_track_points(&input)?;
+            return output.write_with(|writer| {
+                filter_xml_by_time_range_inclusive_end(
+                    input.reader()?,
+                    start_time,
+                    end_time,
+                    writer,
+                )
+            });
+        };
 
-    let (start_time, end_time) = detect_activity_bounds(&track_points, speed_threshold, buffer)?;
+        let activities =
+            detect_activity_intervals_with_options(&track_points, &options, max_pause)?;
+        let intervals = IntervalSet::new(
+            activities
+                .into_iter()
+                .map(|(start, end)| Interval::new(Some(start), Some(end))),
+        );
 
-    filter_xml_by_time_range_inclusive_end(&input, start_time, end_time)?;
+        let group_split = match split {
+            ActivitySplit::Segment => GroupSplit::Segment,
+            ActivitySplit::Track => GroupSplit::Track,
+            ActivitySplit::File => {
+                for (index, interval) in intervals.intervals().iter().enumerate() {
+                    let mut selector = TimeIntervalSelector {
+                        intervals: IntervalSet::new([*interval]),
+                        include_end: true,
+                    };
+                    output.numbered(index + 1)?.write_with(|writer| {
+                        filter_xml_by_selector(
+                            input.reader()?,
+                            &mut selector,
+                            GroupSplit::Segment,
+                            writer,
+                        )
+                    })?;
+                }
+                return Ok(());
+            }
+        };
+
+        let mut selector = TimeIntervalSelector {
+            intervals,
+            include_end: true,
+        };
+        output.write_with(|writer| {
+            filter_xml_by_selector(input.reader()?, &mut selector, group_split, writer)
+        })
+    })
+}
+
+/// Describes the detected activities and what trimming to them would remove.
+fn write_dry_run(
+    writer: &mut dyn Write,
+    track_points: &[TrackPoint],
+    analysis: &ActivityAnalysis,
+    options: &ActivityOptions,
+    max_pause: Option<u64>,
+) -> Result<(), GpxWrenchError> {
+    writeln!(writer, "Points:          {}", track_points.len())?;
+    writeln!(
+        writer,
+        "Thresholds:      start {:.2} m/s, stop {:.2} m/s",
+        options.start_threshold, options.stop_threshold
+    )?;
+
+    let activities = analysis.activities(track_points, max_pause)?;
+    if activities.is_empty() {
+        writeln!(
+            writer,
+            "No activity detected; the whole track would be kept"
+        )?;
+        return Ok(());
+    }
+    let bounds = analysis.activity_bounds(track_points, max_pause, options.buffer_seconds)?;
+    for (number, (&(start_idx, end_idx), &(start_time, end_time))) in
+        activities.iter().zip(&bounds).enumerate()
+    {
+        writeln!(
+            writer,
+            "Activity {}:      {} to {}",
+            number + 1,
+            format_time(start_time)?,
+            format_time(end_time)?
+        )?;
+        writeln!(
+            writer,
+            "  Start trigger: point {start_idx} at {}, {:.2} m/s",
+            format_time(track_points[start_idx].time)?,
+            analysis.speeds[start_idx]
+        )?;
+        writeln!(
+            writer,
+            "  End trigger:   point {end_idx} at {}, {:.2} m/s",
+            format_time(track_points[end_idx].time)?,
+            analysis.speeds[end_idx - 1]
+        )?;
+    }
+
+    // Points are kept if they lie within the buffered bounds, end included.
+    let first_kept = |start: OffsetDateTime| {
+        track_points
+            .iter()
+            .position(|point| point.time >= start)
+            .unwrap_or(0)
+    };
+    let last_kept = |end: OffsetDateTime| {
+        track_points
+            .iter()
+            .rposition(|point| point.time <= end)
+            .unwrap_or(track_points.len() - 1)
+    };
+    let total = track_points.len();
+    let start = &track_points[..=first_kept(bounds[0].0)];
+    write_removed(
+        writer,
+        "Removed at start:",
+        start.len() - 1,
+        &[start],
+        total,
+    )?;
+    if bounds.len() > 1 {
+        // The pauses between activities, each from the last kept point before it to the first
+        // kept point after it.
+        let gaps: Vec<&[TrackPoint]> = bounds
+            .windows(2)
+            .map(|pair| (last_kept(pair[0].1), first_kept(pair[1].0)))
+            .filter(|(before, after)| before < after)
+            .map(|(before, after)| &track_points[before..=after])
+            .collect();
+        let removed = gaps.iter().map(|gap| gap.len() - 2).sum();
+        write_removed(writer, "Removed between:", removed, &gaps, total)?;
+    }
+    let end = &track_points[last_kept(bounds[bounds.len() - 1].1)..];
+    write_removed(writer, "Removed at end:  ", end.len() - 1, &[end], total)?;
+    Ok(())
+}
+
+/// Writes that `removed` points would be removed, with the time and distance that `spans` cover.
+/// Each span runs from a kept point or the end of the track to the next.
+fn write_removed(
+    writer: &mut dyn Write,
+    label: &str,
+    removed: usize,
+    spans: &[&[TrackPoint]],
+    total_points: usize,
+) -> Result<(), GpxWrenchError> {
+    let distance = spans
+        .iter()
+        .flat_map(|points| points.windows(2))
+        .fold(0.0, |distance, pair| {
+            distance + haversine_distance(pair[0].lat, pair[0].lon, pair[1].lat, pair[1].lon)
+        });
+    let duration: Duration = spans
+        .iter()
+        .map(|points| points[points.len() - 1].time - points[0].time)
+        .sum();
+    writeln!(
+        writer,
+        "{label} {removed} of {total_points} points, {}, {distance:.1} m",
+        format_duration(duration),
+    )?;
+    Ok(())
+}
+
+/// Writes a CSV line per point with the speed since the previous point, before and after
+/// smoothing, and whether the point belongs to an active run.
+fn write_explanation(
+    writer: &mut dyn Write,
+    track_points: &[TrackPoint],
+    analysis: &ActivityAnalysis,
+) -> Result<(), GpxWrenchError> {
+    writeln!(
+        writer,
+        "index,time,lat,lon,speed_mps,smoothed_speed_mps,state"
+    )?;
+    for (idx, point) in track_points.iter().enumerate() {
+        let (speed, smoothed) = match idx.checked_sub(1) {
+            Some(previous) => (
+                format!("{:.3}", calculate_speed(&track_points[previous], point)),
+                format!("{:.3}", analysis.speeds[previous]),
+            ),
+            None => (String::new(), String::new()),
+        };
+        let state = if analysis.is_active(idx) {
+            "active"
+        } else {
+            "idle"
+        };
+        writeln!(
+            writer,
+            "{idx},{},{},{},{speed},{smoothed},{state}",
+            format_time(point.time)?,
+            point.lat,
+            point.lon
+        )?;
+    }
+    Ok(())
+}
+
+pub fn validate_speed_threshold(speed_threshold: f64) -> Result<(), GpxWrenchError> {
+    if !speed_threshold.is_finite() || speed_threshold < 0.0 {
+        return Err(GpxWrenchError::InvalidArgument(
+            "Speed threshold must be a finite non-negative number".into(),
+        ));
+    }
     Ok(())
 }
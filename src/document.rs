@@ -0,0 +1,1182 @@
+//! Typed in-memory model of a GPX 1.1 document.
+//!
+//! [`GpxDocument::parse`] reads a complete document into typed structures and
+//! [`GpxDocument::write_to`] serializes it back as GPX 1.1. Content the model does not know about
+//! (vendor extensions, non-standard elements and comments) is kept as raw [`XmlNode`]s so that it
+//! survives a round trip. Whitespace between elements is not preserved; the output is re-indented.
+
+use crate::error::{GpxWrenchError, LineTrackingReader, from_reader_error};
+use crate::parse_finite;
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesCData, BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use std::fmt::Display;
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+use time::OffsetDateTime;
+use time::format_description::well_known::{Iso8601, Rfc3339};
+
+pub const GPX_NAMESPACE: &str = "http://www.topografix.com/GPX/1/1";
+
+/// A piece of XML that is not represented by the typed model.
+#[derive(Debug, Clone, PartialEq)]
+pub enum XmlNode {
+    Element(XmlElement),
+    Text(String),
+    CData(String),
+    Comment(String),
+}
+
+/// An XML element with its qualified name as written in the source, e.g. `gpxtpx:hr`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct XmlElement {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<XmlNode>,
+}
+
+impl XmlElement {
+    pub fn new(name: impl Into<String>) -> Self {
+        XmlElement {
+            name: name.into(),
+            ..XmlElement::default()
+        }
+    }
+
+    /// Returns the element name without its namespace prefix.
+    pub fn local_name(&self) -> &str {
+        self.name
+            .rsplit_once(':')
+            .map_or(self.name.as_str(), |(_, local)| local)
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the concatenated text and CDATA content of the element's direct children.
+    pub fn text(&self) -> String {
+        self.children
+            .iter()
+            .filter_map(|child| match child {
+                XmlNode::Text(text) | XmlNode::CData(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GpxDocument {
+    pub creator: String,
+    /// Attributes of the `<gpx>` element other than `version`, `creator` and the default
+    /// namespace, e.g. namespace prefixes used by extensions and `xsi:schemaLocation`.
+    pub attributes: Vec<(String, String)>,
+    /// Comments that appear before the `<gpx>` element.
+    pub comments: Vec<String>,
+    pub metadata: Option<Metadata>,
+    pub waypoints: Vec<Waypoint>,
+    pub routes: Vec<Route>,
+    pub tracks: Vec<Track>,
+    pub extensions: Vec<XmlNode>,
+    pub unknown: Vec<XmlNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Metadata {
+    pub name: Option<String>,
+    pub desc: Option<String>,
+    pub author: Option<Person>,
+    pub copyright: Option<Copyright>,
+    pub links: Vec<Link>,
+    pub time: Option<OffsetDateTime>,
+    pub keywords: Option<String>,
+    pub bounds: Option<Bounds>,
+    pub extensions: Vec<XmlNode>,
+    pub unknown: Vec<XmlNode>,
+    pub comments: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Person {
+    pub name: Option<String>,
+    /// E-mail address in `id@domain` form.
+    pub email: Option<String>,
+    pub link: Option<Link>,
+    pub unknown: Vec<XmlNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Copyright {
+    pub author: String,
+    pub year: Option<i32>,
+    pub license: Option<String>,
+    pub unknown: Vec<XmlNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Link {
+    pub href: String,
+    pub text: Option<String>,
+    pub link_type: Option<String>,
+    pub unknown: Vec<XmlNode>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Bounds {
+    pub min_lat: f64,
+    pub min_lon: f64,
+    pub max_lat: f64,
+    pub max_lon: f64,
+}
+
+/// Type of GPS fix, as recorded in `<fix>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fix {
+    None,
+    TwoD,
+    ThreeD,
+    Dgps,
+    Pps,
+}
+
+impl Fix {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Fix::None => "none",
+            Fix::TwoD => "2d",
+            Fix::ThreeD => "3d",
+            Fix::Dgps => "dgps",
+            Fix::Pps => "pps",
+        }
+    }
+}
+
+impl FromStr for Fix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Fix::None),
+            "2d" => Ok(Fix::TwoD),
+            "3d" => Ok(Fix::ThreeD),
+            "dgps" => Ok(Fix::Dgps),
+            "pps" => Ok(Fix::Pps),
+            _ => Err(format!("Invalid fix type: {s}")),
+        }
+    }
+}
+
+impl Display for Fix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A `wptType` point, used for waypoints, route points and track points alike.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Waypoint {
+    pub lat: f64,
+    pub lon: f64,
+    pub ele: Option<f64>,
+    pub time: Option<OffsetDateTime>,
+    pub magvar: Option<f64>,
+    pub geoid_height: Option<f64>,
+    pub name: Option<String>,
+    pub cmt: Option<String>,
+    pub desc: Option<String>,
+    pub src: Option<String>,
+    pub links: Vec<Link>,
+    pub sym: Option<String>,
+    pub point_type: Option<String>,
+    pub fix: Option<Fix>,
+    pub sat: Option<u32>,
+    pub hdop: Option<f64>,
+    pub vdop: Option<f64>,
+    pub pdop: Option<f64>,
+    pub age_of_dgps_data: Option<f64>,
+    pub dgps_id: Option<u16>,
+    pub extensions: Vec<XmlNode>,
+    pub unknown: Vec<XmlNode>,
+    pub comments: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Route {
+    pub name: Option<String>,
+    pub cmt: Option<String>,
+    pub desc: Option<String>,
+    pub src: Option<String>,
+    pub links: Vec<Link>,
+    pub number: Option<u32>,
+    pub route_type: Option<String>,
+    pub extensions: Vec<XmlNode>,
+    pub points: Vec<Waypoint>,
+    pub unknown: Vec<XmlNode>,
+    pub comments: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Track {
+    pub name: Option<String>,
+    pub cmt: Option<String>,
+    pub desc: Option<String>,
+    pub src: Option<String>,
+    pub links: Vec<Link>,
+    pub number: Option<u32>,
+    pub track_type: Option<String>,
+    pub extensions: Vec<XmlNode>,
+    pub segments: Vec<TrackSegment>,
+    pub unknown: Vec<XmlNode>,
+    pub comments: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TrackSegment {
+    /// Track points share the waypoint schema.
+    pub points: Vec<Waypoint>,
+    pub extensions: Vec<XmlNode>,
+    pub unknown: Vec<XmlNode>,
+    pub comments: Vec<String>,
+}
+
+impl GpxDocument {
+    pub fn parse<R: BufRead>(input: R) -> Result<Self, GpxWrenchError> {
+        let (comments, root) = parse_xml_tree(input)?;
+        if root.local_name() != "gpx" {
+            return Err(GpxWrenchError::InvalidGpx(format!(
+                "Expected <gpx> root element, found <{}>",
+                root.name
+            )));
+        }
+
+        let mut document = GpxDocument {
+            comments,
+            ..GpxDocument::default()
+        };
+        for (key, value) in root.attributes {
+            match key.as_str() {
+                "creator" => document.creator = value,
+                "version" | "xmlns" => {}
+                _ => document.attributes.push((key, value)),
+            }
+        }
+
+        let mut children = Children::new(root.children);
+        while let Some((element, comments)) = children.next_element(&mut document.unknown) {
+            match element.local_name() {
+                "metadata" => {
+                    let mut metadata = Metadata::from_element(element)?;
+                    metadata.comments = comments;
+                    document.metadata = Some(metadata);
+                }
+                "wpt" => {
+                    let mut waypoint = Waypoint::from_element(element)?;
+                    waypoint.comments = comments;
+                    document.waypoints.push(waypoint);
+                }
+                "rte" => {
+                    let mut route = Route::from_element(element)?;
+                    route.comments = comments;
+                    document.routes.push(route);
+                }
+                "trk" => {
+                    let mut track = Track::from_element(element)?;
+                    track.comments = comments;
+                    document.tracks.push(track);
+                }
+                "extensions" => {
+                    push_comments(&mut document.unknown, comments);
+                    document.extensions = element.children;
+                }
+                _ => {
+                    push_comments(&mut document.unknown, comments);
+                    document.unknown.push(XmlNode::Element(element));
+                }
+            }
+        }
+
+        Ok(document)
+    }
+
+    pub fn write_to<W: Write>(&self, output: W) -> Result<(), GpxWrenchError> {
+        let mut writer = Writer::new_with_indent(output, b' ', 2);
+        writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+        write_comments(&mut writer, &self.comments)?;
+
+        let mut root = BytesStart::new("gpx");
+        root.push_attribute(("version", "1.1"));
+        root.push_attribute(("creator", self.creator.as_str()));
+        root.push_attribute(("xmlns", GPX_NAMESPACE));
+        for (key, value) in &self.attributes {
+            root.push_attribute((key.as_str(), value.as_str()));
+        }
+        writer.write_event(Event::Start(root))?;
+
+        if let Some(metadata) = &self.metadata {
+            metadata.write(&mut writer)?;
+        }
+        for waypoint in &self.waypoints {
+            waypoint.write(&mut writer, "wpt")?;
+        }
+        for route in &self.routes {
+            route.write(&mut writer)?;
+        }
+        for track in &self.tracks {
+            track.write(&mut writer)?;
+        }
+        write_extensions(&mut writer, &self.extensions)?;
+        write_nodes(&mut writer, &self.unknown)?;
+
+        writer.write_event(Event::End(BytesEnd::new("gpx")))?;
+        writer.get_mut().flush()?;
+        Ok(())
+    }
+}
+
+impl Metadata {
+    fn from_element(element: XmlElement) -> Result<Self, GpxWrenchError> {
+        let mut metadata = Metadata::default();
+        let mut children = Children::new(element.children);
+        while let Some((child, comments)) = children.next_element(&mut metadata.unknown) {
+            push_comments(&mut metadata.unknown, comments);
+            match child.local_name() {
+                "name" => metadata.name = Some(child.text()),
+                "desc" => metadata.desc = Some(child.text()),
+                "author" => metadata.author = Some(Person::from_element(child)?),
+                "copyright" => metadata.copyright = Some(Copyright::from_element(child)?),
+                "link" => metadata.links.push(Link::from_element(child)?),
+                "time" => metadata.time = Some(parse_time(&child)?),
+                "keywords" => metadata.keywords = Some(child.text()),
+                "bounds" => metadata.bounds = Some(Bounds::from_element(&child)?),
+                "extensions" => metadata.extensions = child.children,
+                _ => metadata.unknown.push(XmlNode::Element(child)),
+            }
+        }
+        Ok(metadata)
+    }
+
+    fn write<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), GpxWrenchError> {
+        write_comments(writer, &self.comments)?;
+        writer.write_event(Event::Start(BytesStart::new("metadata")))?;
+        write_text_element(writer, "name", self.name.as_ref())?;
+        write_text_element(writer, "desc", self.desc.as_ref())?;
+        if let Some(author) = &self.author {
+            author.write(writer, "author")?;
+        }
+        if let Some(copyright) = &self.copyright {
+            copyright.write(writer)?;
+        }
+        for link in &self.links {
+            link.write(writer)?;
+        }
+        write_time_element(writer, self.time)?;
+        write_text_element(writer, "keywords", self.keywords.as_ref())?;
+        if let Some(bounds) = &self.bounds {
+            bounds.write(writer)?;
+        }
+        write_extensions(writer, &self.extensions)?;
+        write_nodes(writer, &self.unknown)?;
+        writer.write_event(Event::End(BytesEnd::new("metadata")))?;
+        Ok(())
+    }
+}
+
+impl Person {
+    fn from_element(element: XmlElement) -> Result<Self, GpxWrenchError> {
+        let mut person = Person::default();
+        let mut children = Children::new(element.children);
+        while let Some((child, comments)) = children.next_element(&mut person.unknown) {
+            push_comments(&mut person.unknown, comments);
+            match child.local_name() {
+                "name" => person.name = Some(child.text()),
+                "email" => {
+                    let id = child.attribute("id").unwrap_or_default();
+                    let domain = child.attribute("domain").unwrap_or_default();
+                    person.email = Some(format!("{id}@{domain}"));
+                }
+                "link" => person.link = Some(Link::from_element(child)?),
+                _ => person.unknown.push(XmlNode::Element(child)),
+            }
+        }
+        Ok(person)
+    }
+
+    fn write<W: Write>(&self, writer: &mut Writer<W>, name: &str) -> Result<(), GpxWrenchError> {
+        writer.write_event(Event::Start(BytesStart::new(name)))?;
+        write_text_element(writer, "name", self.name.as_ref())?;
+        if let Some(email) = &self.email {
+            let (id, domain) = email.split_once('@').unwrap_or((email.as_str(), ""));
+            writer
+                .create_element("email")
+                .with_attribute(("id", id))
+                .with_attribute(("domain", domain))
+                .write_empty()?;
+        }
+        if let Some(link) = &self.link {
+            link.write(writer)?;
+        }
+        write_nodes(writer, &self.unknown)?;
+        writer.write_event(Event::End(BytesEnd::new(name)))?;
+        Ok(())
+    }
+}
+
+impl Copyright {
+    fn from_element(element: XmlElement) -> Result<Self, GpxWrenchError> {
+        let mut copyright = Copyright {
+            author: element.attribute("author").unwrap_or_default().to_string(),
+            ..Copyright::default()
+        };
+        let mut children = Children::new(element.children);
+        while let Some((child, comments)) = children.next_element(&mut copyright.unknown) {
+            push_comments(&mut copyright.unknown, comments);
+            match child.local_name() {
+                "year" => copyright.year = Some(parse_value(&child)?),
+                "license" => copyright.license = Some(child.text()),
+                _ => copyright.unknown.push(XmlNode::Element(child)),
+            }
+        }
+        Ok(copyright)
+    }
+
+    fn write<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), GpxWrenchError> {
+        let mut start = BytesStart::new("copyright");
+        start.push_attribute(("author", self.author.as_str()));
+        writer.write_event(Event::Start(start))?;
+        write_text_element(writer, "year", self.year.as_ref())?;
+        write_text_element(writer, "license", self.license.as_ref())?;
+        write_nodes(writer, &self.unknown)?;
+        writer.write_event(Event::End(BytesEnd::new("copyright")))?;
+        Ok(())
+    }
+}
+
+impl Link {
+    fn from_element(element: XmlElement) -> Result<Self, GpxWrenchError> {
+        let mut link = Link {
+            href: element
+                .attribute("href")
+                .ok_or_else(|| {
+                    GpxWrenchError::InvalidGpx("Missing href attribute on <link>".into())
+                })?
+                .to_string(),
+            ..Link::default()
+        };
+        let mut children = Children::new(element.children);
+        while let Some((child, comments)) = children.next_element(&mut link.unknown) {
+            push_comments(&mut link.unknown, comments);
+            match child.local_name() {
+                "text" => link.text = Some(child.text()),
+                "type" => link.link_type = Some(child.text()),
+                _ => link.unknown.push(XmlNode::Element(child)),
+            }
+        }
+        Ok(link)
+    }
+
+    fn write<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), GpxWrenchError> {
+        let mut start = BytesStart::new("link");
+        start.push_attribute(("href", self.href.as_str()));
+        if self.text.is_none() && self.link_type.is_none() && self.unknown.is_empty() {
+            writer.write_event(Event::Empty(start))?;
+            return Ok(());
+        }
+        writer.write_event(Event::Start(start))?;
+        write_text_element(writer, "text", self.text.as_ref())?;
+        write_text_element(writer, "type", self.link_type.as_ref())?;
+        write_nodes(writer, &self.unknown)?;
+        writer.write_event(Event::End(BytesEnd::new("link")))?;
+        Ok(())
+    }
+}
+
+impl Bounds {
+    fn from_element(element: &XmlElement) -> Result<Self, GpxWrenchError> {
+        Ok(Bounds {
+            min_lat: parse_number_attribute(element, "minlat")?,
+            min_lon: parse_number_attribute(element, "minlon")?,
+            max_lat: parse_number_attribute(element, "maxlat")?,
+            max_lon: parse_number_attribute(element, "maxlon")?,
+        })
+    }
+
+    fn write<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), GpxWrenchError> {
+        writer
+            .create_element("bounds")
+            .with_attribute(("minlat", self.min_lat.to_string().as_str()))
+            .with_attribute(("minlon", self.min_lon.to_string().as_str()))
+            .with_attribute(("maxlat", self.max_lat.to_string().as_str()))
+            .with_attribute(("maxlon", self.max_lon.to_string().as_str()))
+            .write_empty()?;
+        Ok(())
+    }
+}
+
+impl Waypoint {
+    pub fn new(lat: f64, lon: f64) -> Self {
+        Waypoint {
+            lat,
+            lon,
+            ..Waypoint::default()
+        }
+    }
+
+    fn from_element(element: XmlElement) -> Result<Self, GpxWrenchError> {
+        let mut point = Waypoint::new(
+            parse_number_attribute(&element, "lat")?,
+            parse_number_attribute(&element, "lon")?,
+        );
+        let mut children = Children::new(element.children);
+        while let Some((child, comments)) = children.next_element(&mut point.unknown) {
+            push_comments(&mut point.unknown, comments);
+            match child.local_name() {
+                "ele" => point.ele = Some(parse_number(&child)?),
+                "time" => point.time = Some(parse_time(&child)?),
+                "magvar" => point.magvar = Some(parse_number(&child)?),
+                "geoidheight" => point.geoid_height = Some(parse_number(&child)?),
+                "name" => point.name = Some(child.text()),
+                "cmt" => point.cmt = Some(child.text()),
+                "desc" => point.desc = Some(child.text()),
+                "src" => point.src = Some(child.text()),
+                "link" => point.links.push(Link::from_element(child)?),
+                "sym" => point.sym = Some(child.text()),
+                "type" => point.point_type = Some(child.text()),
+                "fix" => point.fix = Some(parse_value(&child)?),
+                "sat" => point.sat = Some(parse_value(&child)?),
+                "hdop" => point.hdop = Some(parse_number(&child)?),
+                "vdop" => point.vdop = Some(parse_number(&child)?),
+                "pdop" => point.pdop = Some(parse_number(&child)?),
+                "ageofdgpsdata" => point.age_of_dgps_data = Some(parse_number(&child)?),
+                "dgpsid" => point.dgps_id = Some(parse_value(&child)?),
+                "extensions" => point.extensions = child.children,
+                _ => point.unknown.push(XmlNode::Element(child)),
+            }
+        }
+        Ok(point)
+    }
+
+    fn write<W: Write>(&self, writer: &mut Writer<W>, name: &str) -> Result<(), GpxWrenchError> {
+        write_comments(writer, &self.comments)?;
+        let mut start = BytesStart::new(name);
+        start.push_attribute(("lat", self.lat.to_string().as_str()));
+        start.push_attribute(("lon", self.lon.to_string().as_str()));
+        writer.write_event(Event::Start(start))?;
+        write_text_element(writer, "ele", self.ele.as_ref())?;
+        write_time_element(writer, self.time)?;
+        write_text_element(writer, "magvar", self.magvar.as_ref())?;
+        write_text_element(writer, "geoidheight", self.geoid_height.as_ref())?;
+        write_text_element(writer, "name", self.name.as_ref())?;
+        write_text_element(writer, "cmt", self.cmt.as_ref())?;
+        write_text_element(writer, "desc", self.desc.as_ref())?;
+        write_text_element(writer, "src", self.src.as_ref())?;
+        for link in &self.links {
+            link.write(writer)?;
+        }
+        write_text_element(writer, "sym", self.sym.as_ref())?;
+        write_text_element(writer, "type", self.point_type.as_ref())?;
+        write_text_element(writer, "fix", self.fix.as_ref())?;
+        write_text_element(writer, "sat", self.sat.as_ref())?;
+        write_text_element(writer, "hdop", self.hdop.as_ref())?;
+        write_text_element(writer, "vdop", self.vdop.as_ref())?;
+        write_text_element(writer, "pdop", self.pdop.as_ref())?;
+        write_text_element(writer, "ageofdgpsdata", self.age_of_dgps_data.as_ref())?;
+        write_text_element(writer, "dgpsid", self.dgps_id.as_ref())?;
+        write_extensions(writer, &self.extensions)?;
+        write_nodes(writer, &self.unknown)?;
+        writer.write_event(Event::End(BytesEnd::new(name)))?;
+        Ok(())
+    }
+}
+
+impl Route {
+    fn from_element(element: XmlElement) -> Result<Self, GpxWrenchError> {
+        let mut route = Route::default();
+        let mut children = Children::new(element.children);
+        while let Some((child, comments)) = children.next_element(&mut route.unknown) {
+            if child.local_name() == "rtept" {
+                let mut point = Waypoint::from_element(child)?;
+                point.comments = comments;
+                route.points.push(point);
+                continue;
+            }
+            push_comments(&mut route.unknown, comments);
+            match child.local_name() {
+                "name" => route.name = Some(child.text()),
+                "cmt" => route.cmt = Some(child.text()),
+                "desc" => route.desc = Some(child.text()),
+                "src" => route.src = Some(child.text()),
+                "link" => route.links.push(Link::from_element(child)?),
+                "number" => route.number = Some(parse_value(&child)?),
+                "type" => route.route_type = Some(child.text()),
+                "extensions" => route.extensions = child.children,
+                _ => route.unknown.push(XmlNode::Element(child)),
+            }
+        }
+        Ok(route)
+    }
+
+    fn write<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), GpxWrenchError> {
+        write_comments(writer, &self.comments)?;
+        writer.write_event(Event::Start(BytesStart::new("rte")))?;
+        write_text_element(writer, "name", self.name.as_ref())?;
+        write_text_element(writer, "cmt", self.cmt.as_ref())?;
+        write_text_element(writer, "desc", self.desc.as_ref())?;
+        write_text_element(writer, "src", self.src.as_ref())?;
+        for link in &self.links {
+            link.write(writer)?;
+        }
+        write_text_element(writer, "number", self.number.as_ref())?;
+        write_text_element(writer, "type", self.route_type.as_ref())?;
+        write_extensions(writer, &self.extensions)?;
+        for point in &self.points {
+            point.write(writer, "rtept")?;
+        }
+        write_nodes(writer, &self.unknown)?;
+        writer.write_event(Event::End(BytesEnd::new("rte")))?;
+        Ok(())
+    }
+}
+
+impl Track {
+    fn from_element(element: XmlElement) -> Result<Self, GpxWrenchError> {
+        let mut track = Track::default();
+        let mut children = Children::new(element.children);
+        while let Some((child, comments)) = children.next_element(&mut track.unknown) {
+            if child.local_name() == "trkseg" {
+                let mut segment = TrackSegment::from_element(child)?;
+                segment.comments = comments;
+                track.segments.push(segment);
+                continue;
+            }
+            push_comments(&mut track.unknown, comments);
+            match child.local_name() {
+                "name" => track.name = Some(child.text()),
+                "cmt" => track.cmt = Some(child.text()),
+                "desc" => track.desc = Some(child.text()),
+                "src" => track.src = Some(child.text()),
+                "link" => track.links.push(Link::from_element(child)?),
+                "number" => track.number = Some(parse_value(&child)?),
+                "type" => track.track_type = Some(child.text()),
+                "extensions" => track.extensions = child.children,
+                _ => track.unknown.push(XmlNode::Element(child)),
+            }
+        }
+        Ok(track)
+    }
+
+    fn write<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), GpxWrenchError> {
+        write_comments(writer, &self.comments)?;
+        writer.write_event(Event::Start(BytesStart::new("trk")))?;
+        write_text_element(writer, "name", self.name.as_ref())?;
+        write_text_element(writer, "cmt", self.cmt.as_ref())?;
+        write_text_element(writer, "desc", self.desc.as_ref())?;
+        write_text_element(writer, "src", self.src.as_ref())?;
+        for link in &self.links {
+            link.write(writer)?;
+        }
+        write_text_element(writer, "number", self.number.as_ref())?;
+        write_text_element(writer, "type", self.track_type.as_ref())?;
+        write_extensions(writer, &self.extensions)?;
+        for segment in &self.segments {
+            segment.write(writer)?;
+        }
+        write_nodes(writer, &self.unknown)?;
+        writer.write_event(Event::End(BytesEnd::new("trk")))?;
+        Ok(())
+    }
+}
+
+impl TrackSegment {
+    fn from_element(element: XmlElement) -> Result<Self, GpxWrenchError> {
+        let mut segment = TrackSegment::default();
+        let mut children = Children::new(element.children);
+        while let Some((child, comments)) = children.next_element(&mut segment.unknown) {
+            match child.local_name() {
+                "trkpt" => {
+                    let mut point = Waypoint::from_element(child)?;
+                    point.comments = comments;
+                    segment.points.push(point);
+                }
+                "extensions" => {
+                    push_comments(&mut segment.unknown, comments);
+                    segment.extensions = child.children;
+                }
+                _ => {
+                    push_comments(&mut segment.unknown, comments);
+                    segment.unknown.push(XmlNode::Element(child));
+                }
+            }
+        }
+        Ok(segment)
+    }
+
+    fn write<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), GpxWrenchError> {
+        write_comments(writer, &self.comments)?;
+        if self.points.is_empty() && self.extensions.is_empty() && self.unknown.is_empty() {
+            writer.write_event(Event::Empty(BytesStart::new("trkseg")))?;
+            return Ok(());
+        }
+        writer.write_event(Event::Start(BytesStart::new("trkseg")))?;
+        for point in &self.points {
+            point.write(writer, "trkpt")?;
+        }
+        write_extensions(writer, &self.extensions)?;
+        write_nodes(writer, &self.unknown)?;
+        writer.write_event(Event::End(BytesEnd::new("trkseg")))?;
+        Ok(())
+    }
+}
+
+/// Walks the children of an element, pairing each child element with the comments directly
+/// preceding it. Text between elements is moved to the `unknown` list given to `next_element`,
+/// as are comments that are not followed by an element.
+struct Children {
+    nodes: std::vec::IntoIter<XmlNode>,
+}
+
+impl Children {
+    fn new(nodes: Vec<XmlNode>) -> Self {
+        Children {
+            nodes: nodes.into_iter(),
+        }
+    }
+
+    fn next_element(&mut self, unknown: &mut Vec<XmlNode>) -> Option<(XmlElement, Vec<String>)> {
+        let mut comments = Vec::new();
+        for node in self.nodes.by_ref() {
+            match node {
+                XmlNode::Element(element) => return Some((element, comments)),
+                XmlNode::Comment(comment) => comments.push(comment),
+                other => unknown.push(other),
+            }
+        }
+        push_comments(unknown, comments);
+        None
+    }
+}
+
+fn push_comments(unknown: &mut Vec<XmlNode>, comments: Vec<String>) {
+    unknown.extend(comments.into_iter().map(XmlNode::Comment));
+}
+
+fn parse_value<T>(element: &XmlElement) -> Result<T, GpxWrenchError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let text = element.text();
+    text.trim().parse().map_err(|e| {
+        GpxWrenchError::InvalidGpx(format!("Invalid <{}> value {text:?}: {e}", element.name))
+    })
+}
+
+fn parse_attribute<T>(element: &XmlElement, name: &str) -> Result<T, GpxWrenchError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = element.attribute(name).ok_or_else(|| {
+        GpxWrenchError::InvalidGpx(format!("Missing {name} attribute on <{}>", element.name))
+    })?;
+    value.trim().parse().map_err(|e| {
+        GpxWrenchError::InvalidGpx(format!(
+            "Invalid {name} attribute {value:?} on <{}>: {e}",
+            element.name
+        ))
+    })
+}
+
+/// Like [`parse_value`] for numbers, rejecting `NaN` and infinities.
+fn parse_number(element: &XmlElement) -> Result<f64, GpxWrenchError> {
+    let text = element.text();
+    parse_finite(text.trim()).ok_or_else(|| {
+        GpxWrenchError::InvalidGpx(format!(
+            "Invalid <{}> value {text:?}: expected a finite number",
+            element.name
+        ))
+    })
+}
+
+/// Like [`parse_attribute`] for numbers, rejecting `NaN` and infinities.
+fn parse_number_attribute(element: &XmlElement, name: &str) -> Result<f64, GpxWrenchError> {
+    let value: String = parse_attribute(element, name)?;
+    parse_finite(value.trim()).ok_or_else(|| {
+        GpxWrenchError::InvalidGpx(format!(
+            "Invalid {name} attribute {value:?} on <{}>: expected a finite number",
+            element.name
+        ))
+    })
+}
+
+fn parse_time(element: &XmlElement) -> Result<OffsetDateTime, GpxWrenchError> {
+    let text = element.text();
+    OffsetDateTime::parse(text.trim(), &Iso8601::DEFAULT).map_err(|e| {
+        GpxWrenchError::InvalidGpx(format!("Invalid <{}> value {text:?}: {e}", element.name))
+    })
+}
+
+/// Parses an XML document into a tree, returning the comments that precede the root element
+/// together with the root element. Whitespace-only text is dropped.
+fn parse_xml_tree<R: BufRead>(input: R) -> Result<(Vec<String>, XmlElement), GpxWrenchError> {
+    let mut reader = Reader::from_reader(LineTrackingReader::new(input));
+    let mut buf = Vec::new();
+    let mut prolog_comments = Vec::new();
+    let mut stack: Vec<XmlElement> = Vec::new();
+    let mut root = None;
+
+    loop {
+        let event_start = reader.buffer_position();
+        reader.get_mut().mark(event_start);
+        let event = match reader.read_event_into(&mut buf) {
+            Err(e) => {
+                return Err(from_reader_error(
+                    reader.get_ref(),
+                    reader.error_position(),
+                    e,
+                ));
+            }
+            Ok(Event::Eof) if stack.is_empty() => break,
+            Ok(Event::Eof) => {
+                return Err(reader
+                    .get_ref()
+                    .xml_error(event_start, "Unexpected EOF while parsing GPX"));
+            }
+            Ok(event) => event,
+        };
+
+        let node = node_from_event(event, &mut stack)
+            .map_err(|message| reader.get_ref().xml_error(event_start, message))?;
+
+        if let Some(node) = node {
+            match stack.last_mut() {
+                Some(parent) => push_node(&mut parent.children, node),
+                None => match node {
+                    XmlNode::Element(element) if root.is_none() => root = Some(element),
+                    XmlNode::Element(_) => {
+                        return Err(reader
+                            .get_ref()
+                            .xml_error(event_start, "Multiple root elements"));
+                    }
+                    XmlNode::Comment(comment) => prolog_comments.push(comment),
+                    _ => {}
+                },
+            }
+        }
+
+        buf.clear();
+    }
+
+    let root = root.ok_or_else(|| GpxWrenchError::InvalidGpx("Missing root element".into()))?;
+    Ok((prolog_comments, root))
+}
+
+/// Converts a reader event into a tree node. Opening tags are pushed onto `stack` and only
+/// returned as a node once they are closed.
+fn node_from_event(
+    event: Event<'_>,
+    stack: &mut Vec<XmlElement>,
+) -> Result<Option<XmlNode>, String> {
+    let node = match event {
+        Event::Start(e) => {
+            stack.push(element_from_start(&e)?);
+            return Ok(None);
+        }
+        Event::End(_) => {
+            let mut element = stack.pop().ok_or("Unexpected closing XML element")?;
+            element.children.retain(|child| match child {
+                XmlNode::Text(text) => !text.trim().is_empty(),
+                _ => true,
+            });
+            XmlNode::Element(element)
+        }
+        Event::Empty(e) => XmlNode::Element(element_from_start(&e)?),
+        Event::Text(e) => XmlNode::Text(e.xml_content().map_err(|e| e.to_string())?.into_owned()),
+        Event::GeneralRef(e) => {
+            let resolved = match e.resolve_char_ref().map_err(|e| e.to_string())? {
+                Some(ch) => ch.to_string(),
+                None => {
+                    let name = e.decode().map_err(|e| e.to_string())?;
+                    resolve_predefined_entity(&name)
+                        .ok_or_else(|| format!("Unknown XML entity &{name};"))?
+                        .to_string()
+                }
+            };
+            XmlNode::Text(resolved)
+        }
+        Event::CData(e) => XmlNode::CData(e.decode().map_err(|e| e.to_string())?.into_owned()),
+        Event::Comment(e) => XmlNode::Comment(e.decode().map_err(|e| e.to_string())?.into_owned()),
+        _ => return Ok(None),
+    };
+    Ok(Some(node))
+}
+
+fn element_from_start(start: &BytesStart<'_>) -> Result<XmlElement, String> {
+    let name = String::from_utf8(start.name().as_ref().to_vec()).map_err(|e| e.to_string())?;
+    let mut element = XmlElement::new(name);
+    for attr in start.attributes() {
+        let attr = attr.map_err(|e| e.to_string())?;
+        element.attributes.push((
+            String::from_utf8(attr.key.as_ref().to_vec()).map_err(|e| e.to_string())?,
+            attr.unescape_value()
+                .map_err(|e| e.to_string())?
+                .into_owned(),
+        ));
+    }
+    Ok(element)
+}
+
+/// Appends a node, merging adjacent text so that text split by entity references stays whole.
+fn push_node(children: &mut Vec<XmlNode>, node: XmlNode) {
+    if let (Some(XmlNode::Text(existing)), XmlNode::Text(text)) = (children.last_mut(), &node) {
+        existing.push_str(text);
+    } else {
+        children.push(node);
+    }
+}
+
+fn write_text_element<W: Write, T: Display>(
+    writer: &mut Writer<W>,
+    name: &str,
+    value: Option<T>,
+) -> Result<(), GpxWrenchError> {
+    if let Some(value) = value {
+        writer
+            .create_element(name)
+            .write_text_content(BytesText::new(&value.to_string()))?;
+    }
+    Ok(())
+}
+
+fn write_time_element<W: Write>(
+    writer: &mut Writer<W>,
+    time: Option<OffsetDateTime>,
+) -> Result<(), GpxWrenchError> {
+    let time = time
+        .map(|time| time.format(&Rfc3339))
+        .transpose()
+        .map_err(|e| GpxWrenchError::TimestampOverflow(format!("Cannot format time: {e}")))?;
+    write_text_element(writer, "time", time.as_ref())
+}
+
+fn write_comments<W: Write>(
+    writer: &mut Writer<W>,
+    comments: &[String],
+) -> Result<(), GpxWrenchError> {
+    for comment in comments {
+        writer.write_event(Event::Comment(BytesText::from_escaped(comment.as_str())))?;
+    }
+    Ok(())
+}
+
+fn write_extensions<W: Write>(
+    writer: &mut Writer<W>,
+    extensions: &[XmlNode],
+) -> Result<(), GpxWrenchError> {
+    if extensions.is_empty() {
+        return Ok(());
+    }
+    writer.write_event(Event::Start(BytesStart::new("extensions")))?;
+    write_nodes(writer, extensions)?;
+    writer.write_event(Event::End(BytesEnd::new("extensions")))?;
+    Ok(())
+}
+
+fn write_nodes<W: Write>(writer: &mut Writer<W>, nodes: &[XmlNode]) -> Result<(), GpxWrenchError> {
+    for node in nodes {
+        match node {
+            XmlNode::Element(element) => {
+                let mut start = BytesStart::new(element.name.as_str());
+                for (key, value) in &element.attributes {
+                    start.push_attribute((key.as_str(), value.as_str()));
+                }
+                if element.children.is_empty() {
+                    writer.write_event(Event::Empty(start))?;
+                } else {
+                    writer.write_event(Event::Start(start))?;
+                    write_nodes(writer, &element.children)?;
+                    writer.write_event(Event::End(BytesEnd::new(element.name.as_str())))?;
+                }
+            }
+            XmlNode::Text(text) => writer.write_event(Event::Text(BytesText::new(text)))?,
+            XmlNode::CData(text) => writer.write_event(Event::CData(BytesCData::new(text)))?,
+            XmlNode::Comment(comment) => {
+                writer.write_event(Event::Comment(BytesText::from_escaped(comment.as_str())))?
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_GPX: &str = include_str!("../samples/activity.gpx");
+
+    fn round_trip(document: &GpxDocument) -> (String, GpxDocument) {
+        let mut output = Vec::new();
+        document.write_to(&mut output).unwrap();
+        let reparsed = GpxDocument::parse(output.as_slice()).unwrap();
+        (String::from_utf8(output).unwrap(), reparsed)
+    }
+
+    #[test]
+    fn test_parse_sample_document() {
+        let document = GpxDocument::parse(SAMPLE_GPX.as_bytes()).unwrap();
+
+        assert_eq!(document.creator, "GPX Wrench Sample");
+        let metadata = document.metadata.as_ref().unwrap();
+        assert_eq!(metadata.name.as_deref(), Some("Sample Activity Track"));
+        assert_eq!(document.tracks.len(), 1);
+        let track = &document.tracks[0];
+        assert_eq!(track.name.as_deref(), Some("Cycling Activity"));
+        assert_eq!(track.segments.len(), 1);
+        let first = &track.segments[0].points[0];
+        assert_eq!(first.lat, 37.7749);
+        assert_eq!(first.lon, -122.4194);
+        assert_eq!(first.ele, Some(50.0));
+        assert_eq!(
+            first.comments,
+            [" Idle time: stationary at start ".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_round_trip_preserves_document() {
+        let document = GpxDocument::parse(SAMPLE_GPX.as_bytes()).unwrap();
+
+        let (output, reparsed) = round_trip(&document);
+
+        assert_eq!(reparsed, document);
+        assert!(output.contains("<!-- Activity starts: cycling begins -->"));
+        let gpx: gpx::Gpx = gpx::read(output.as_bytes()).unwrap();
+        assert_eq!(
+            gpx.tracks[0].segments[0].points.len(),
+            document.tracks[0].segments[0].points.len()
+        );
+    }
+
+    #[test]
+    fn test_round_trip_preserves_unknown_extensions() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- exported by a watch -->
+<gpx version="1.1" creator="test" xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v1">
+  <metadata>
+    <author>
+      <name>Sam</name>
+      <email id="sam" domain="example.com"/>
+      <x:extra xmlns:x="https://example.com/x">kept</x:extra>
+    </author>
+    <copyright author="Sam"><year>2023</year><x:holder xmlns:x="https://example.com/x"/></copyright>
+    <link href="https://example.com"><text>Home</text><x:foo xmlns:x="https://example.com/x"/></link>
+    <bounds minlat="37.1" minlon="-122.5" maxlat="37.9" maxlon="-122.1"/>
+  </metadata>
+  <wpt lat="37.5" lon="-122.2">
+    <name>Caf&#233; &amp; bakery</name>
+    <fix>3d</fix>
+  </wpt>
+  <rte>
+    <name>Route</name>
+    <rtept lat="37.5" lon="-122.2"/>
+  </rte>
+  <trk>
+    <trkseg>
+      <trkpt lat="37.7749" lon="-122.4194">
+        <time>2023-01-01T10:00:00.5+02:00</time>
+        <extensions>
+          <gpxtpx:TrackPointExtension>
+            <gpxtpx:hr>150</gpxtpx:hr>
+          </gpxtpx:TrackPointExtension>
+          <vendor:note xmlns:vendor="https://example.com/vendor"><![CDATA[a < b]]></vendor:note>
+        </extensions>
+        <vendor:custom xmlns:vendor="https://example.com/vendor" level="2"/>
+      </trkpt>
+      <!-- end of segment -->
+    </trkseg>
+  </trk>
+</gpx>"#;
+
+        let document = GpxDocument::parse(input.as_bytes()).unwrap();
+        let (output, reparsed) = round_trip(&document);
+
+        assert_eq!(reparsed, document);
+        assert_eq!(document.comments, [" exported by a watch ".to_string()]);
+        let metadata = document.metadata.as_ref().unwrap();
+        assert_eq!(
+            metadata.author.as_ref().unwrap().email.as_deref(),
+            Some("sam@example.com")
+        );
+        assert_eq!(document.waypoints[0].name.as_deref(), Some("Café & bakery"));
+        assert_eq!(document.waypoints[0].fix, Some(Fix::ThreeD));
+        assert_eq!(document.routes[0].points.len(), 1);
+        let point = &document.tracks[0].segments[0].points[0];
+        assert_eq!(point.unknown.len(), 1);
+        assert!(output.contains("<gpxtpx:hr>150</gpxtpx:hr>"));
+        assert!(output.contains("<![CDATA[a < b]]>"));
+        assert!(
+            output.contains(
+                r#"<vendor:custom xmlns:vendor="https://example.com/vendor" level="2"/>"#
+            )
+        );
+        assert!(output.contains("<!-- end of segment -->"));
+        assert!(output.contains("<time>2023-01-01T10:00:00.5+02:00</time>"));
+        assert!(output.contains("xmlns:gpxtpx="));
+        assert!(output.contains(r#"<x:extra xmlns:x="https://example.com/x">kept</x:extra>"#));
+        assert!(output.contains(r#"<x:holder xmlns:x="https://example.com/x"/>"#));
+        assert!(output.contains(r#"<x:foo xmlns:x="https://example.com/x"/>"#));
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_values() {
+        let invalid_ele = r#"<gpx version="1.1" creator="test">
+  <wpt lat="1" lon="2"><ele>high</ele></wpt>
+</gpx>"#;
+        let missing_lat = r#"<gpx version="1.1" creator="test"><wpt lon="2"/></gpx>"#;
+        let not_gpx = r#"<kml></kml>"#;
+
+        assert!(GpxDocument::parse(invalid_ele.as_bytes()).is_err());
+        for non_finite in [
+            r#"<gpx version="1.1" creator="test"><wpt lat="NaN" lon="2"/></gpx>"#,
+            r#"<gpx version="1.1" creator="test"><wpt lat="1" lon="2"><ele>NaN</ele></wpt></gpx>"#,
+            r#"<gpx version="1.1" creator="test"><wpt lat="1" lon="2"><hdop>inf</hdop></wpt></gpx>"#,
+        ] {
+            assert!(
+                GpxDocument::parse(non_finite.as_bytes()).is_err(),
+                "{non_finite}"
+            );
+        }
+        assert!(GpxDocument::parse(missing_lat.as_bytes()).is_err());
+        assert!(GpxDocument::parse(not_gpx.as_bytes()).is_err());
+        assert!(GpxDocument::parse(&b"<gpx><trk>"[..]).is_err());
+    }
+
+    #[test]
+    fn test_write_new_document() {
+        let mut point = Waypoint::new(37.7749, -122.4194);
+        point.ele = Some(12.5);
+        let document = GpxDocument {
+            creator: "gpxwrench".to_string(),
+            tracks: vec![Track {
+                segments: vec![TrackSegment {
+                    points: vec![point],
+                    ..TrackSegment::default()
+                }],
+                ..Track::default()
+            }],
+            ..GpxDocument::default()
+        };
+
+        let (output, reparsed) = round_trip(&document);
+
+        assert_eq!(reparsed, document);
+        assert!(output.contains(
+            r#"<gpx version="1.1" creator="gpxwrench" xmlns="http://www.topografix.com/GPX/1/1">"#
+        ));
+        assert!(output.contains(r#"<trkpt lat="37.7749" lon="-122.4194">"#));
+        assert!(output.contains("<ele>12.5</ele>"));
+    }
+}
@@ -0,0 +1,247 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Read};
+use std::path::PathBuf;
+
+/// Errors returned by gpxwrench.
+///
+/// Each variant describes a class of failure callers can act on without inspecting the message.
+#[derive(Debug)]
+pub enum GpxWrenchError {
+    /// The input is not well-formed XML. `line` and `column` are 1-based; `column` counts bytes.
+    Xml {
+        offset: u64,
+        line: u64,
+        column: u64,
+        message: String,
+    },
+    /// The input is well-formed XML but not a usable GPX document, e.g. a missing `lat`
+    /// attribute or an unparseable `<ele>` value.
+    InvalidGpx(String),
+    /// The input exceeds a size or track point limit.
+    LimitExceeded(String),
+    /// A trim range, duration or timestamp could not be parsed.
+    InvalidRange(String),
+    /// A computed timestamp falls outside the supported date range.
+    TimestampOverflow(String),
+    /// The input does not contain enough data for the requested analysis.
+    InsufficientData(String),
+    /// An option value or combination of options is not valid.
+    InvalidArgument(String),
+    /// Some inputs of a multi-file run failed; each failure has already been reported.
+    Failures {
+        failed: usize,
+        total: usize,
+    },
+    Io(io::Error),
+    /// An error that occurred while processing the input or output file at `path`.
+    File {
+        path: PathBuf,
+        source: Box<GpxWrenchError>,
+    },
+}
+
+impl GpxWrenchError {
+    /// Attaches the path of the file being processed to this error.
+    pub fn for_path(self, path: impl Into<PathBuf>) -> Self {
+        GpxWrenchError::File {
+            path: path.into(),
+            source: Box::new(self),
+        }
+    }
+
+    /// Returns the underlying error, skipping any file context.
+    pub fn root(&self) -> &GpxWrenchError {
+        match self {
+            GpxWrenchError::File { source, .. } => source.root(),
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for GpxWrenchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GpxWrenchError::Xml {
+                offset,
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "XML error at line {line}, column {column} (byte {offset}): {message}"
+            ),
+            GpxWrenchError::InvalidGpx(message)
+            | GpxWrenchError::LimitExceeded(message)
+            | GpxWrenchError::InvalidRange(message)
+            | GpxWrenchError::TimestampOverflow(message)
+            | GpxWrenchError::InsufficientData(message)
+            | GpxWrenchError::InvalidArgument(message) => f.write_str(message),
+            GpxWrenchError::Failures { failed, total } => {
+                write!(f, "{failed} of {total} files failed")
+            }
+            GpxWrenchError::Io(e) => e.fmt(f),
+            GpxWrenchError::File { path, source } => write!(f, "{}: {source}", path.display()),
+        }
+    }
+}
+
+impl Error for GpxWrenchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GpxWrenchError::Io(e) => Some(e),
+            GpxWrenchError::File { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for GpxWrenchError {
+    fn from(e: io::Error) -> Self {
+        GpxWrenchError::Io(e)
+    }
+}
+
+/// Wraps the reader given to quick-xml and remembers where lines start, so that byte offsets
+/// reported by quick-xml can be turned into line and column numbers.
+///
+/// Only line starts after the last [`LineTrackingReader::mark`] are stored; earlier lines are
+/// just counted, which keeps memory bounded by the size of a single XML event.
+pub(crate) struct LineTrackingReader<R> {
+    inner: R,
+    consumed: u64,
+    lines_before_mark: u64,
+    line_start_before_mark: u64,
+    newlines: Vec<u64>,
+}
+
+impl<R: BufRead> LineTrackingReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        LineTrackingReader {
+            inner,
+            consumed: 0,
+            lines_before_mark: 0,
+            line_start_before_mark: 0,
+            newlines: Vec::new(),
+        }
+    }
+
+    /// Forgets the exact positions of newlines before `offset`. Errors are never reported
+    /// before the start of the event being read, so callers mark each event start.
+    pub(crate) fn mark(&mut self, offset: u64) {
+        let forgotten = self.newlines.partition_point(|&newline| newline < offset);
+        if let Some(&last) = self.newlines[..forgotten].last() {
+            self.line_start_before_mark = last + 1;
+        }
+        self.lines_before_mark += forgotten as u64;
+        self.newlines.drain(..forgotten);
+    }
+
+    /// Returns the 1-based line and byte column of `offset`.
+    pub(crate) fn line_column(&self, offset: u64) -> (u64, u64) {
+        let newlines = self.newlines.partition_point(|&newline| newline < offset);
+        let line_start = match newlines.checked_sub(1) {
+            Some(last) => self.newlines[last] + 1,
+            None => self.line_start_before_mark,
+        };
+        (
+            self.lines_before_mark + newlines as u64 + 1,
+            offset.saturating_sub(line_start) + 1,
+        )
+    }
+
+    /// Builds an [`GpxWrenchError::Xml`] for an error at `offset`.
+    pub(crate) fn xml_error(&self, offset: u64, message: impl Into<String>) -> GpxWrenchError {
+        let (line, column) = self.line_column(offset);
+        GpxWrenchError::Xml {
+            offset,
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+impl<R: BufRead> Read for LineTrackingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let amt = available.len().min(buf.len());
+        buf[..amt].copy_from_slice(&available[..amt]);
+        self.consume(amt);
+        Ok(amt)
+    }
+}
+
+impl<R: BufRead> BufRead for LineTrackingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // `consume` follows a `fill_buf` call, so this returns the same buffered bytes.
+        if let Ok(buffered) = self.inner.fill_buf() {
+            let start = self.consumed;
+            self.newlines.extend(
+                buffered[..amt.min(buffered.len())]
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &byte)| byte == b'\n')
+                    .map(|(index, _)| start + index as u64),
+            );
+        }
+        self.consumed += amt as u64;
+        self.inner.consume(amt);
+    }
+}
+
+/// Converts an error returned by quick-xml's reader, keeping I/O errors as [`GpxWrenchError::Io`].
+pub(crate) fn from_reader_error<R: BufRead>(
+    tracker: &LineTrackingReader<R>,
+    offset: u64,
+    error: quick_xml::Error,
+) -> GpxWrenchError {
+    match error {
+        quick_xml::Error::Io(e) => GpxWrenchError::Io(io::Error::new(e.kind(), e.to_string())),
+        other => tracker.xml_error(offset, other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn consume_all<R: BufRead>(reader: &mut LineTrackingReader<R>) {
+        let mut sink = Vec::new();
+        reader.read_to_end(&mut sink).unwrap();
+    }
+
+    #[test]
+    fn test_line_column() {
+        let mut reader = LineTrackingReader::new("ab\ncd\n\nefg".as_bytes());
+        consume_all(&mut reader);
+
+        assert_eq!(reader.line_column(0), (1, 1));
+        assert_eq!(reader.line_column(1), (1, 2));
+        assert_eq!(reader.line_column(3), (2, 1));
+        assert_eq!(reader.line_column(6), (3, 1));
+        assert_eq!(reader.line_column(9), (4, 3));
+    }
+
+    #[test]
+    fn test_line_column_after_mark() {
+        let mut reader = LineTrackingReader::new("ab\ncd\n\nefg".as_bytes());
+        consume_all(&mut reader);
+        reader.mark(7);
+
+        assert!(reader.newlines.is_empty());
+        assert_eq!(reader.line_column(7), (4, 1));
+        assert_eq!(reader.line_column(9), (4, 3));
+    }
+
+    #[test]
+    fn test_file_context_keeps_root_error() {
+        let error = GpxWrenchError::InsufficientData("Not enough points".into()).for_path("a.gpx");
+        assert!(matches!(error.root(), GpxWrenchError::InsufficientData(_)));
+        assert_eq!(error.to_string(), "a.gpx: Not enough points");
+    }
+}
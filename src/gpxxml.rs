@@ -1,495 +1,1633 @@
-use gpxwrench::{MAX_TRACK_POINTS, TrackPoint};
-use quick_xml::events::Event;
+use crate::error::{GpxWrenchError, LineTrackingReader, from_reader_error};
+use crate::sensors::SensorData;
+use crate::{MAX_TRACK_POINTS, TrackPoint, haversine_distance, parse_finite};
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
 use quick_xml::name::QName;
 use quick_xml::{Reader, Writer};
-use std::error::Error;
-use std::io::Write;
-use time::OffsetDateTime;
+use std::io::{BufRead, Write};
+use time::{Duration, OffsetDateTime, UtcOffset};
 
 fn is_element_name(name: QName<'_>, expected_local_name: &[u8]) -> bool {
     name.local_name().as_ref() == expected_local_name
 }
 
-pub fn find_minimum_time(input: &[u8]) -> Result<Option<OffsetDateTime>, Box<dyn Error>> {
-    let mut reader = Reader::from_reader(input);
+/// Callbacks for the track structure of a GPX document, driven by [`visit_track_points`].
+///
+/// Only `trk`, `trkseg` and `trkpt` elements in their GPX positions are reported; elements with
+/// the same local name elsewhere (for example inside extensions) are not. Every XML event is
+/// additionally passed to [`TrackPointVisitor::event`] in document order. Callbacks for opening
+/// elements run before the corresponding `event` call and callbacks for closing elements run
+/// after it, so a visitor that buffers events sees the whole element between the two.
+pub trait TrackPointVisitor {
+    fn gpx_start(&mut self, _element: &BytesStart<'_>) -> Result<(), GpxWrenchError> {
+        Ok(())
+    }
+
+    fn gpx_end(&mut self) -> Result<(), GpxWrenchError> {
+        Ok(())
+    }
+
+    fn track_start(&mut self, _element: &BytesStart<'_>) -> Result<(), GpxWrenchError> {
+        Ok(())
+    }
+
+    fn track_end(&mut self) -> Result<(), GpxWrenchError> {
+        Ok(())
+    }
+
+    fn segment_start(&mut self, _element: &BytesStart<'_>) -> Result<(), GpxWrenchError> {
+        Ok(())
+    }
+
+    fn segment_end(&mut self) -> Result<(), GpxWrenchError> {
+        Ok(())
+    }
+
+    /// Called for `<trkpt>` as well as for self-closing `<trkpt/>` elements.
+    fn track_point_start(&mut self, _element: &BytesStart<'_>) -> Result<(), GpxWrenchError> {
+        Ok(())
+    }
+
+    /// Called with the local name and text content of each direct child element of the current
+    /// track point, such as `<ele>` or `<time>`. `<extensions>` is not reported here.
+    fn track_point_field(&mut self, _name: &str, _text: &str) -> Result<(), GpxWrenchError> {
+        Ok(())
+    }
+
+    /// Called with the parsed `<time>` child of the current track point, after
+    /// [`TrackPointVisitor::track_point_field`]. Unparseable times are not reported.
+    fn track_point_time(&mut self, _time: OffsetDateTime) -> Result<(), GpxWrenchError> {
+        Ok(())
+    }
+
+    /// Called when the `<extensions>` child of the current track point opens.
+    fn extensions_start(&mut self) -> Result<(), GpxWrenchError> {
+        Ok(())
+    }
+
+    /// Called with the local name and text content of each element inside the current track
+    /// point's `<extensions>` that has no child elements, e.g. `hr` for `<gpxtpx:hr>`.
+    fn extension_field(&mut self, _name: &str, _text: &str) -> Result<(), GpxWrenchError> {
+        Ok(())
+    }
+
+    fn extensions_end(&mut self) -> Result<(), GpxWrenchError> {
+        Ok(())
+    }
+
+    fn track_point_end(&mut self) -> Result<(), GpxWrenchError> {
+        Ok(())
+    }
+
+    fn event(&mut self, _event: &Event<'_>) -> Result<(), GpxWrenchError> {
+        Ok(())
+    }
+}
+
+/// Parses `input` and reports its track structure to `visitor`.
+pub fn visit_track_points<R: BufRead, V: TrackPointVisitor + ?Sized>(
+    input: R,
+    visitor: &mut V,
+) -> Result<(), GpxWrenchError> {
+    let mut reader = Reader::from_reader(LineTrackingReader::new(input));
     let mut buf = Vec::new();
-    let mut min_time: Option<OffsetDateTime> = None;
 
-    let mut in_trkpt = false;
-    let mut in_time_element = false;
-    let mut time_text = String::new();
+    let mut field_text = String::new();
     let mut element_depth = 0usize;
     let mut gpx_depth: Option<usize> = None;
     let mut track_depth: Option<usize> = None;
     let mut trkseg_depth: Option<usize> = None;
     let mut trkpt_depth: Option<usize> = None;
-    let mut time_element_depth: Option<usize> = None;
+    let mut field_depth: Option<usize> = None;
+    let mut extensions_depth: Option<usize> = None;
+    let mut extension_leaf_depth: Option<usize> = None;
 
     loop {
+        let event_start = reader.buffer_position();
+        reader.get_mut().mark(event_start);
         let event = match reader.read_event_into(&mut buf) {
             Err(e) => {
-                return Err(
-                    format!("Error at position {}: {:?}", reader.buffer_position(), e).into(),
-                );
+                return Err(from_reader_error(
+                    reader.get_ref(),
+                    reader.error_position(),
+                    e,
+                ));
             }
             Ok(Event::Eof) if element_depth == 0 => break,
-            Ok(Event::Eof) => return Err("Unexpected EOF while parsing GPX".into()),
-            Ok(event) => event.into_owned(),
+            Ok(Event::Eof) => {
+                return Err(reader
+                    .get_ref()
+                    .xml_error(event_start, "Unexpected EOF while parsing GPX"));
+            }
+            Ok(event) => event,
         };
 
-        match event {
-            Event::Start(ref e) => {
+        match &event {
+            Event::Start(e) => {
+                let parent_depth = Some(element_depth);
                 element_depth += 1;
                 if gpx_depth.is_none() && is_element_name(e.name(), b"gpx") {
                     gpx_depth = Some(element_depth);
-                } else if gpx_depth.is_some_and(|depth| element_depth == depth + 1)
+                    visitor.gpx_start(e)?;
+                } else if gpx_depth == parent_depth
                     && track_depth.is_none()
                     && is_element_name(e.name(), b"trk")
                 {
                     track_depth = Some(element_depth);
-                } else if track_depth.is_some_and(|depth| element_depth == depth + 1)
+                    visitor.track_start(e)?;
+                } else if track_depth == parent_depth
                     && trkseg_depth.is_none()
                     && is_element_name(e.name(), b"trkseg")
                 {
                     trkseg_depth = Some(element_depth);
-                } else if trkseg_depth.is_some_and(|depth| element_depth == depth + 1)
+                    visitor.segment_start(e)?;
+                } else if trkseg_depth == parent_depth
                     && trkpt_depth.is_none()
                     && is_element_name(e.name(), b"trkpt")
                 {
-                    in_trkpt = true;
                     trkpt_depth = Some(element_depth);
-                } else if in_trkpt
-                    && trkpt_depth.is_some_and(|depth| element_depth == depth + 1)
-                    && is_element_name(e.name(), b"time")
-                {
-                    in_time_element = true;
-                    time_element_depth = Some(element_depth);
-                    time_text.clear();
+                    visitor.track_point_start(e)?;
+                } else if trkpt_depth == parent_depth && is_element_name(e.name(), b"extensions") {
+                    extensions_depth = Some(element_depth);
+                    visitor.extensions_start()?;
+                } else if trkpt_depth == parent_depth {
+                    field_depth = Some(element_depth);
+                    field_text.clear();
+                } else if extensions_depth.is_some_and(|depth| element_depth > depth) {
+                    // Replaced by any child element, so only leaves are reported.
+                    extension_leaf_depth = Some(element_depth);
+                    field_text.clear();
                 }
+                visitor.event(&event)?;
             }
 
-            Event::End(ref e) => {
+            Event::End(e) => {
+                visitor.event(&event)?;
                 if trkpt_depth == Some(element_depth) && is_element_name(e.name(), b"trkpt") {
-                    in_trkpt = false;
                     trkpt_depth = None;
-                } else if in_trkpt
-                    && in_time_element
-                    && time_element_depth == Some(element_depth)
-                    && is_element_name(e.name(), b"time")
-                {
-                    in_time_element = false;
-                    // Parse the collected time text
-                    if let Ok(parsed_time) = OffsetDateTime::parse(
-                        &time_text,
-                        &time::format_description::well_known::Iso8601::DEFAULT,
-                    ) && min_time.is_none_or(|time| parsed_time < time)
+                    visitor.track_point_end()?;
+                } else if field_depth == Some(element_depth) {
+                    field_depth = None;
+                    let name =
+                        std::str::from_utf8(e.name().local_name().into_inner()).unwrap_or_default();
+                    visitor.track_point_field(name, &field_text)?;
+                    if name == "time"
+                        && let Ok(parsed_time) = OffsetDateTime::parse(
+                            &field_text,
+                            &time::format_description::well_known::Iso8601::DEFAULT,
+                        )
                     {
-                        min_time = Some(parsed_time);
+                        visitor.track_point_time(parsed_time)?;
                     }
-                    time_element_depth = None;
+                } else if extension_leaf_depth == Some(element_depth) {
+                    extension_leaf_depth = None;
+                    let name =
+                        std::str::from_utf8(e.name().local_name().into_inner()).unwrap_or_default();
+                    visitor.extension_field(name, &field_text)?;
+                } else if extensions_depth == Some(element_depth)
+                    && is_element_name(e.name(), b"extensions")
+                {
+                    extensions_depth = None;
+                    visitor.extensions_end()?;
                 } else if trkseg_depth == Some(element_depth)
                     && is_element_name(e.name(), b"trkseg")
                 {
                     trkseg_depth = None;
+                    visitor.segment_end()?;
                 } else if track_depth == Some(element_depth) && is_element_name(e.name(), b"trk") {
                     track_depth = None;
+                    visitor.track_end()?;
                 } else if gpx_depth == Some(element_depth) && is_element_name(e.name(), b"gpx") {
                     gpx_depth = None;
+                    visitor.gpx_end()?;
                 }
-                element_depth = element_depth
-                    .checked_sub(1)
-                    .ok_or("Unexpected closing XML element")?;
+                element_depth = element_depth.checked_sub(1).ok_or_else(|| {
+                    reader
+                        .get_ref()
+                        .xml_error(event_start, "Unexpected closing XML element")
+                })?;
+            }
+
+            Event::Empty(e)
+                if trkseg_depth == Some(element_depth)
+                    && trkpt_depth.is_none()
+                    && is_element_name(e.name(), b"trkpt") =>
+            {
+                visitor.track_point_start(e)?;
+                visitor.event(&event)?;
+                visitor.track_point_end()?;
             }
 
-            Event::Text(ref e) => {
-                if in_trkpt
-                    && in_time_element
+            Event::Text(e) => {
+                if (field_depth == Some(element_depth)
+                    || extension_leaf_depth == Some(element_depth))
                     && let Ok(text) = std::str::from_utf8(e)
                 {
-                    time_text.push_str(text);
+                    field_text.push_str(text);
                 }
+                visitor.event(&event)?;
             }
 
-            _ => {}
+            Event::GeneralRef(e) => {
+                if field_depth == Some(element_depth) || extension_leaf_depth == Some(element_depth)
+                {
+                    if let Ok(Some(ch)) = e.resolve_char_ref() {
+                        field_text.push(ch);
+                    } else if let Some(resolved) = std::str::from_utf8(e)
+                        .ok()
+                        .and_then(resolve_predefined_entity)
+                    {
+                        field_text.push_str(resolved);
+                    }
+                }
+                visitor.event(&event)?;
+            }
+
+            _ => visitor.event(&event)?,
         }
 
         buf.clear();
     }
 
-    Ok(min_time)
+    Ok(())
+}
+
+struct TimeBoundsVisitor {
+    bounds: Option<(OffsetDateTime, OffsetDateTime)>,
+}
+
+impl TrackPointVisitor for TimeBoundsVisitor {
+    fn track_point_time(&mut self, time: OffsetDateTime) -> Result<(), GpxWrenchError> {
+        let (min_time, max_time) = self.bounds.get_or_insert((time, time));
+        if time < *min_time {
+            *min_time = time;
+        }
+        if time > *max_time {
+            *max_time = time;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the earliest and latest track point times in one pass, or `None` if no track point
+/// has a valid time.
+pub fn find_time_bounds<R: BufRead>(
+    input: R,
+) -> Result<Option<(OffsetDateTime, OffsetDateTime)>, GpxWrenchError> {
+    let mut visitor = TimeBoundsVisitor { bounds: None };
+    visit_track_points(input, &mut visitor)?;
+    Ok(visitor.bounds)
+}
+
+pub fn find_minimum_time<R: BufRead>(input: R) -> Result<Option<OffsetDateTime>, GpxWrenchError> {
+    Ok(find_time_bounds(input)?.map(|(min_time, _)| min_time))
+}
+
+pub fn find_maximum_time<R: BufRead>(input: R) -> Result<Option<OffsetDateTime>, GpxWrenchError> {
+    Ok(find_time_bounds(input)?.map(|(_, max_time)| max_time))
 }
 
-pub fn filter_xml_by_time_range(
-    input: &[u8],
+pub fn filter_xml_by_time_range<R: BufRead, W: Write>(
+    input: R,
     start_threshold: OffsetDateTime,
     end_threshold: OffsetDateTime,
-) -> Result<(), Box<dyn Error>> {
-    filter_xml_by_time_to_writer(
-        input,
-        start_threshold,
-        Some(end_threshold),
-        std::io::stdout(),
-    )
+    output: W,
+) -> Result<(), GpxWrenchError> {
+    filter_xml_by_time_to_writer(input, start_threshold, Some(end_threshold), output)
 }
 
-pub fn filter_xml_by_time_range_inclusive_end(
-    input: &[u8],
+pub fn filter_xml_by_time_range_inclusive_end<R: BufRead, W: Write>(
+    input: R,
     start_threshold: OffsetDateTime,
     end_threshold: OffsetDateTime,
-) -> Result<(), Box<dyn Error>> {
+    output: W,
+) -> Result<(), GpxWrenchError> {
     filter_xml_by_time_to_writer_with_end_mode(
         input,
-        start_threshold,
+        Some(start_threshold),
         Some(end_threshold),
         true,
-        std::io::stdout(),
+        output,
     )
 }
 
-pub fn filter_xml_by_time_to_writer<W: Write>(
-    input: &[u8],
-    start_threshold: OffsetDateTime,
+/// Keeps track points with `start <= time < end`. A `None` bound leaves that side open.
+pub fn filter_xml_by_time_bounds<R: BufRead, W: Write>(
+    input: R,
+    start_threshold: Option<OffsetDateTime>,
     end_threshold: Option<OffsetDateTime>,
     output: W,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<(), GpxWrenchError> {
     filter_xml_by_time_to_writer_with_end_mode(input, start_threshold, end_threshold, false, output)
 }
 
-fn filter_xml_by_time_to_writer_with_end_mode<W: Write>(
-    input: &[u8],
+/// Keeps track points in `[start_threshold, end_threshold)`, or with a time up to and including
+/// `start_threshold` when there is no end threshold.
+pub fn filter_xml_by_time_to_writer<R: BufRead, W: Write>(
+    input: R,
     start_threshold: OffsetDateTime,
     end_threshold: Option<OffsetDateTime>,
+    output: W,
+) -> Result<(), GpxWrenchError> {
+    match end_threshold {
+        Some(end_threshold) => filter_xml_by_time_to_writer_with_end_mode(
+            input,
+            Some(start_threshold),
+            Some(end_threshold),
+            false,
+            output,
+        ),
+        None => filter_xml_by_time_to_writer_with_end_mode(
+            input,
+            None,
+            Some(start_threshold),
+            true,
+            output,
+        ),
+    }
+}
+
+fn filter_xml_by_time_to_writer_with_end_mode<R: BufRead, W: Write>(
+    input: R,
+    start_threshold: Option<OffsetDateTime>,
+    end_threshold: Option<OffsetDateTime>,
     include_end_threshold: bool,
     output: W,
-) -> Result<(), Box<dyn Error>> {
-    let mut reader = Reader::from_reader(input);
-    let mut writer = Writer::new(output);
-    let mut buf = Vec::new();
+) -> Result<(), GpxWrenchError> {
+    let mut selector = TimeIntervalSelector {
+        intervals: IntervalSet {
+            intervals: vec![Interval::new(start_threshold, end_threshold)],
+        },
+        include_end: include_end_threshold,
+    };
+    filter_xml_by_selector(input, &mut selector, GroupSplit::Segment, output)
+}
 
-    let mut in_trkpt = false;
-    let mut trkpt_buffer = Vec::new();
-    let mut trkpt_time: Option<OffsetDateTime> = None;
-    let mut in_time_element = false;
-    let mut time_text = String::new();
-    let mut in_trkseg = false;
-    let mut just_filtered_trkpt = false;
-    let mut element_depth = 0usize;
-    let mut gpx_depth: Option<usize> = None;
-    let mut track_depth: Option<usize> = None;
-    let mut trkseg_depth: Option<usize> = None;
-    let mut trkpt_depth: Option<usize> = None;
-    let mut time_element_depth: Option<usize> = None;
+/// An interval `[start, end)`. A `None` bound leaves that side open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval<T> {
+    pub start: Option<T>,
+    pub end: Option<T>,
+}
 
-    loop {
-        let event = match reader.read_event_into(&mut buf) {
-            Err(e) => {
-                return Err(
-                    format!("Error at position {}: {:?}", reader.buffer_position(), e).into(),
-                );
-            }
-            Ok(Event::Eof) if element_depth == 0 => break,
-            Ok(Event::Eof) => return Err("Unexpected EOF while parsing GPX".into()),
-            Ok(event) => event.into_owned(),
-        };
+pub type TimeInterval = Interval<OffsetDateTime>;
 
-        match event {
-            Event::Start(ref e) => {
-                element_depth += 1;
-                if gpx_depth.is_none() && is_element_name(e.name(), b"gpx") {
-                    gpx_depth = Some(element_depth);
-                } else if gpx_depth.is_some_and(|depth| element_depth == depth + 1)
-                    && track_depth.is_none()
-                    && is_element_name(e.name(), b"trk")
-                {
-                    track_depth = Some(element_depth);
-                } else if track_depth.is_some_and(|depth| element_depth == depth + 1)
-                    && trkseg_depth.is_none()
-                    && is_element_name(e.name(), b"trkseg")
-                {
-                    in_trkseg = true;
-                    trkseg_depth = Some(element_depth);
-                    just_filtered_trkpt = false;
-                } else if trkseg_depth.is_some_and(|depth| element_depth == depth + 1)
-                    && trkpt_depth.is_none()
-                    && is_element_name(e.name(), b"trkpt")
-                {
-                    in_trkpt = true;
-                    trkpt_depth = Some(element_depth);
-                    trkpt_buffer.clear();
-                    trkpt_time = None;
-                    time_text.clear();
-                    just_filtered_trkpt = false;
-                }
+impl<T: PartialOrd + Copy> Interval<T> {
+    pub fn new(start: Option<T>, end: Option<T>) -> Self {
+        Interval { start, end }
+    }
 
-                if in_trkpt {
-                    if trkpt_depth.is_some_and(|depth| element_depth == depth + 1)
-                        && is_element_name(e.name(), b"time")
-                    {
-                        in_time_element = true;
-                        time_element_depth = Some(element_depth);
-                        time_text.clear();
-                    }
-                    trkpt_buffer.push(event.clone());
-                } else {
-                    writer.write_event(event.clone())?;
+    fn is_empty(&self) -> bool {
+        matches!((self.start, self.end), (Some(start), Some(end)) if end <= start)
+    }
+}
+
+/// Disjoint intervals sorted by start.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntervalSet<T> {
+    intervals: Vec<Interval<T>>,
+}
+
+pub type TimeIntervalSet = IntervalSet<OffsetDateTime>;
+
+impl<T: PartialOrd + Copy> IntervalSet<T> {
+    /// Sorts `intervals`, dropping empty ones and merging those that overlap.
+    pub fn new(intervals: impl IntoIterator<Item = Interval<T>>) -> Self {
+        let mut sorted: Vec<Interval<T>> = intervals
+            .into_iter()
+            .filter(|interval| !interval.is_empty())
+            .collect();
+        // `None` sorts first, so open starts come before every other interval.
+        sorted.sort_by(|a, b| {
+            a.start
+                .partial_cmp(&b.start)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let mut merged: Vec<Interval<T>> = Vec::with_capacity(sorted.len());
+        for interval in sorted {
+            match merged.last_mut() {
+                Some(last)
+                    if last
+                        .end
+                        .is_none_or(|end| interval.start.is_none_or(|start| start < end)) =>
+                {
+                    last.end = last
+                        .end
+                        .zip(interval.end)
+                        .map(|(a, b)| if b > a { b } else { a });
                 }
+                _ => merged.push(interval),
             }
+        }
+        IntervalSet { intervals: merged }
+    }
 
-            Event::End(ref e) => {
-                if trkseg_depth == Some(element_depth) && is_element_name(e.name(), b"trkseg") {
-                    in_trkseg = false;
-                    trkseg_depth = None;
-                    just_filtered_trkpt = false;
-                    writer.write_event(event.clone())?;
-                } else if trkpt_depth == Some(element_depth) && is_element_name(e.name(), b"trkpt")
-                {
-                    // Decide whether to include this trkpt based on time range
-                    let include_point = trkpt_time.is_some_and(|point_time| {
-                        if let Some(end_thresh) = end_threshold {
-                            point_time >= start_threshold
-                                && if include_end_threshold {
-                                    point_time <= end_thresh
-                                } else {
-                                    point_time < end_thresh
-                                }
-                        } else {
-                            point_time <= start_threshold
-                        }
-                    });
-
-                    if include_point {
-                        // Write all buffered events for this trkpt
-                        for buffered_event in &trkpt_buffer {
-                            writer.write_event(buffered_event.clone())?;
-                        }
-                        writer.write_event(event.clone())?;
-                        just_filtered_trkpt = false;
-                    } else {
-                        just_filtered_trkpt = true;
-                    }
+    pub fn intervals(&self) -> &[Interval<T>] {
+        &self.intervals
+    }
 
-                    in_trkpt = false;
-                    trkpt_depth = None;
-                    trkpt_buffer.clear();
-                } else if in_trkpt {
-                    if in_time_element
-                        && time_element_depth == Some(element_depth)
-                        && is_element_name(e.name(), b"time")
-                    {
-                        in_time_element = false;
-                        // Parse the collected time text
-                        if let Ok(parsed_time) = OffsetDateTime::parse(
-                            &time_text,
-                            &time::format_description::well_known::Iso8601::DEFAULT,
-                        ) {
-                            trkpt_time = Some(parsed_time);
-                        }
-                        time_element_depth = None;
-                    }
-                    trkpt_buffer.push(event.clone());
+    /// Returns the index of the interval containing `value`.
+    fn position(&self, value: T, include_end: bool) -> Option<usize> {
+        let index = self
+            .intervals
+            .partition_point(|interval| interval.start.is_none_or(|start| start <= value))
+            .checked_sub(1)?;
+        self.intervals[index]
+            .end
+            .is_none_or(|end| {
+                if include_end {
+                    value <= end
                 } else {
-                    writer.write_event(event.clone())?;
-                    if track_depth == Some(element_depth) && is_element_name(e.name(), b"trk") {
-                        track_depth = None;
-                    } else if gpx_depth == Some(element_depth) && is_element_name(e.name(), b"gpx")
-                    {
-                        gpx_depth = None;
-                    }
+                    value < end
                 }
-                element_depth = element_depth
-                    .checked_sub(1)
-                    .ok_or("Unexpected closing XML element")?;
+            })
+            .then_some(index)
+    }
+}
+
+/// What a [`PointSelector`] knows about a track point when deciding whether to keep it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointContext {
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+    pub time: Option<OffsetDateTime>,
+}
+
+/// Decides which track points [`filter_xml_by_selector`] keeps.
+pub trait PointSelector {
+    /// Called when a `<trk>` of the input starts.
+    fn track_start(&mut self) {}
+
+    /// Called when a `<trkseg>` of the input starts.
+    fn segment_start(&mut self) {}
+
+    /// Returns the group of a track point, or `None` to drop it. Kept points of different
+    /// groups are written to separate track segments.
+    fn select(&mut self, point: &PointContext) -> Option<usize>;
+
+    /// Returns the coordinates the point last passed to [`PointSelector::select`] is written
+    /// with, or `None` to keep its own.
+    fn position(&mut self) -> Option<(f64, f64)> {
+        None
+    }
+}
+
+/// How [`filter_xml_by_selector`] separates kept points of different groups.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GroupSplit {
+    /// Start a new `<trkseg>` within the same track.
+    #[default]
+    Segment,
+    /// Start a new `<trk>`, so that every group becomes its own track.
+    Track,
+}
+
+/// Keeps the points whose time falls into one of `intervals`; the index of the interval is the
+/// group. Points without a time are dropped.
+#[derive(Debug, Clone)]
+pub struct TimeIntervalSelector {
+    pub intervals: TimeIntervalSet,
+    /// Whether a point exactly at the end of an interval is kept.
+    pub include_end: bool,
+}
+
+impl PointSelector for TimeIntervalSelector {
+    fn select(&mut self, point: &PointContext) -> Option<usize> {
+        point
+            .time
+            .and_then(|time| self.intervals.position(time, self.include_end))
+    }
+}
+
+/// Applies per-point decisions made on the points returned by [`extract_track_segments`].
+///
+/// `groups[s][i]` is the group of the `i`-th point with a valid position and time in the `s`-th
+/// `<trkseg>`, or `None` to drop it. Points without a valid position or time cannot be judged
+/// and share the decision for the preceding point, so they are dropped along with it; at the
+/// start of a segment they are kept. With [`SegmentPlanSelector::including_untimed`], the plan
+/// also covers points without a time, as returned by [`extract_positioned_segments`].
+#[derive(Debug, Clone)]
+pub struct SegmentPlanSelector {
+    groups: Vec<Vec<Option<usize>>>,
+    positions: Vec<Vec<(f64, f64)>>,
+    require_time: bool,
+    segment: Option<usize>,
+    point: usize,
+    /// The decision for the last judged point of the current segment.
+    decision: Option<usize>,
+    moved_to: Option<(f64, f64)>,
+}
+
+impl SegmentPlanSelector {
+    pub fn new(groups: Vec<Vec<Option<usize>>>) -> Self {
+        SegmentPlanSelector {
+            groups,
+            positions: Vec::new(),
+            require_time: true,
+            segment: None,
+            point: 0,
+            decision: Some(0),
+            moved_to: None,
+        }
+    }
+
+    /// Moves the points to new coordinates, `positions[s][i]` being the `(lat, lon)` of the
+    /// same point as `groups[s][i]`.
+    pub fn with_positions(mut self, positions: Vec<Vec<(f64, f64)>>) -> Self {
+        self.positions = positions;
+        self
+    }
+
+    /// Judges every point with a valid position, whether or not it has a time.
+    pub fn including_untimed(mut self) -> Self {
+        self.require_time = false;
+        self
+    }
+}
+
+impl PointSelector for SegmentPlanSelector {
+    fn segment_start(&mut self) {
+        self.segment = Some(self.segment.map_or(0, |segment| segment + 1));
+        self.point = 0;
+        self.decision = Some(0);
+    }
+
+    fn select(&mut self, point: &PointContext) -> Option<usize> {
+        self.moved_to = None;
+        if point.lat.is_none() || point.lon.is_none() || (self.require_time && point.time.is_none())
+        {
+            return self.decision;
+        }
+        self.decision = self
+            .segment
+            .and_then(|segment| self.groups.get(segment))
+            .and_then(|groups| groups.get(self.point))
+            .copied()
+            .flatten();
+        self.moved_to = self
+            .segment
+            .and_then(|segment| self.positions.get(segment))
+            .and_then(|positions| positions.get(self.point))
+            .copied();
+        self.point += 1;
+        self.decision
+    }
+
+    fn position(&mut self) -> Option<(f64, f64)> {
+        self.moved_to.take()
+    }
+}
+
+/// Keeps track points whose time falls into one of `intervals`, in a single pass over `input`.
+///
+/// Points from different intervals are written to separate copies of their `<trkseg>`, so every
+/// kept interval becomes its own track segment. Returns the number of kept points.
+pub fn filter_xml_by_time_intervals<R: BufRead, W: Write>(
+    input: R,
+    intervals: &TimeIntervalSet,
+    output: W,
+) -> Result<usize, GpxWrenchError> {
+    let mut selector = CountingSelector::new(TimeIntervalSelector {
+        intervals: intervals.clone(),
+        include_end: false,
+    });
+    filter_xml_by_selector(input, &mut selector, GroupSplit::Segment, output)?;
+    Ok(selector.kept)
+}
+
+/// Counts the points that `inner` keeps.
+struct CountingSelector<S> {
+    inner: S,
+    kept: usize,
+}
+
+impl<S> CountingSelector<S> {
+    fn new(inner: S) -> Self {
+        CountingSelector { inner, kept: 0 }
+    }
+}
+
+impl<S: PointSelector> PointSelector for CountingSelector<S> {
+    fn track_start(&mut self) {
+        self.inner.track_start();
+    }
+
+    fn segment_start(&mut self) {
+        self.inner.segment_start();
+    }
+
+    fn select(&mut self, point: &PointContext) -> Option<usize> {
+        let group = self.inner.select(point);
+        self.kept += usize::from(group.is_some());
+        group
+    }
+
+    fn position(&mut self) -> Option<(f64, f64)> {
+        self.inner.position()
+    }
+}
+
+/// Accumulates the haversine distance along the points of each track segment. Gaps between
+/// segments add no distance, as in [`crate::stats::compute_track_stats`].
+#[derive(Debug, Default)]
+pub(crate) struct DistanceAccumulator {
+    previous: Option<(f64, f64)>,
+    pub(crate) distance: f64,
+}
+
+impl DistanceAccumulator {
+    pub(crate) fn segment_start(&mut self) {
+        self.previous = None;
+    }
+
+    /// Adds a point and returns the distance from the first point, or `None` if the point has
+    /// no valid position.
+    pub(crate) fn add(&mut self, point: &PointContext) -> Option<f64> {
+        let (lat, lon) = (point.lat?, point.lon?);
+        if let Some((previous_lat, previous_lon)) = self.previous {
+            self.distance += haversine_distance(previous_lat, previous_lon, lat, lon);
+        }
+        self.previous = Some((lat, lon));
+        Some(self.distance)
+    }
+}
+
+struct TrackDistanceVisitor {
+    accumulator: DistanceAccumulator,
+    /// The distance along each `<trk>` that has ended so far.
+    tracks: Vec<f64>,
+    track_start_distance: f64,
+}
+
+impl TrackPointVisitor for TrackDistanceVisitor {
+    fn track_start(&mut self, _element: &BytesStart<'_>) -> Result<(), GpxWrenchError> {
+        self.track_start_distance = self.accumulator.distance;
+        Ok(())
+    }
+
+    fn track_end(&mut self) -> Result<(), GpxWrenchError> {
+        self.tracks
+            .push(self.accumulator.distance - self.track_start_distance);
+        Ok(())
+    }
+
+    fn segment_start(&mut self, _element: &BytesStart<'_>) -> Result<(), GpxWrenchError> {
+        self.accumulator.segment_start();
+        Ok(())
+    }
+
+    fn track_point_start(&mut self, element: &BytesStart<'_>) -> Result<(), GpxWrenchError> {
+        let (lat, lon) = track_point_coordinates(element).unwrap_or((None, None));
+        self.accumulator.add(&PointContext {
+            lat,
+            lon,
+            time: None,
+        });
+        Ok(())
+    }
+}
+
+/// Returns the total distance in meters along all track segments of `input`.
+pub fn find_track_distance<R: BufRead>(input: R) -> Result<f64, GpxWrenchError> {
+    let mut visitor = TrackDistanceVisitor {
+        accumulator: DistanceAccumulator::default(),
+        tracks: Vec::new(),
+        track_start_distance: 0.0,
+    };
+    visit_track_points(input, &mut visitor)?;
+    Ok(visitor.accumulator.distance)
+}
+
+/// Returns the distance in meters along each `<trk>` of `input`, in document order.
+pub fn find_track_distances<R: BufRead>(input: R) -> Result<Vec<f64>, GpxWrenchError> {
+    let mut visitor = TrackDistanceVisitor {
+        accumulator: DistanceAccumulator::default(),
+        tracks: Vec::new(),
+        track_start_distance: 0.0,
+    };
+    visit_track_points(input, &mut visitor)?;
+    Ok(visitor.tracks)
+}
+
+/// Keeps the points whose distance from the first point falls into one of the intervals.
+struct DistanceSelector {
+    intervals: IntervalSet<f64>,
+    accumulator: DistanceAccumulator,
+}
+
+impl PointSelector for DistanceSelector {
+    fn segment_start(&mut self) {
+        self.accumulator.segment_start();
+    }
+
+    fn select(&mut self, point: &PointContext) -> Option<usize> {
+        let distance = self.accumulator.add(point)?;
+        self.intervals.position(distance, false)
+    }
+}
+
+/// Keeps track points whose distance along the track, in meters from the first point, falls
+/// into one of `intervals`. Like [`filter_xml_by_time_intervals`], every kept interval becomes
+/// its own track segment. Points without a valid position are dropped. Returns the number of
+/// kept points.
+pub fn filter_xml_by_distance_intervals<R: BufRead, W: Write>(
+    input: R,
+    intervals: &IntervalSet<f64>,
+    output: W,
+) -> Result<usize, GpxWrenchError> {
+    let mut selector = CountingSelector::new(DistanceSelector {
+        intervals: intervals.clone(),
+        accumulator: DistanceAccumulator::default(),
+    });
+    filter_xml_by_selector(input, &mut selector, GroupSplit::Segment, output)?;
+    Ok(selector.kept)
+}
+
+/// When [`SplitSelector`] starts a new group. Every criterion that is set can start one.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SplitCriteria {
+    /// Start a new group when consecutive timed points are more than this far apart.
+    pub max_gap: Option<Duration>,
+    /// Start a new group when the calendar day at this UTC offset changes.
+    pub day_offset: Option<UtcOffset>,
+    /// Start a new group once a group covers this many meters.
+    pub distance: Option<f64>,
+    /// Start a new group once a group has this many points.
+    pub points: Option<usize>,
+}
+
+/// Keeps every track point and numbers consecutive runs of points according to `criteria`,
+/// counting across segments and tracks.
+///
+/// Points without a time never split by gap or day, and points without a valid position add no
+/// distance.
+#[derive(Debug)]
+pub struct SplitSelector {
+    criteria: SplitCriteria,
+    group: Option<usize>,
+    group_points: usize,
+    group_start_distance: f64,
+    previous_time: Option<OffsetDateTime>,
+    accumulator: DistanceAccumulator,
+}
+
+impl SplitSelector {
+    pub fn new(criteria: SplitCriteria) -> Self {
+        SplitSelector {
+            criteria,
+            group: None,
+            group_points: 0,
+            group_start_distance: 0.0,
+            previous_time: None,
+            accumulator: DistanceAccumulator::default(),
+        }
+    }
+
+    /// Returns the number of groups seen so far.
+    pub fn group_count(&self) -> usize {
+        self.group.map_or(0, |group| group + 1)
+    }
+
+    fn starts_group(&self, point: &PointContext, distance: Option<f64>) -> bool {
+        let criteria = &self.criteria;
+        if criteria
+            .points
+            .is_some_and(|points| self.group_points >= points)
+        {
+            return true;
+        }
+        if let (Some(time), Some(previous)) = (point.time, self.previous_time) {
+            if criteria.max_gap.is_some_and(|gap| time - previous > gap) {
+                return true;
+            }
+            if criteria.day_offset.is_some_and(|offset| {
+                time.to_offset(offset).date() != previous.to_offset(offset).date()
+            }) {
+                return true;
             }
+        }
+        distance.is_some_and(|distance| {
+            criteria
+                .distance
+                .is_some_and(|limit| distance - self.group_start_distance >= limit)
+        })
+    }
+}
 
-            Event::Text(ref e) => {
-                if in_trkpt {
-                    if in_time_element && let Ok(text) = std::str::from_utf8(e) {
-                        time_text.push_str(text);
-                    }
-                    trkpt_buffer.push(event.clone());
-                } else {
-                    // Skip whitespace-only text nodes after filtered track points within track segments
-                    let is_whitespace_only = e.iter().all(|&b| b.is_ascii_whitespace());
-                    if in_trkseg && just_filtered_trkpt && is_whitespace_only {
-                        // Skip this whitespace text node
-                    } else {
-                        writer.write_event(event.clone())?;
-                        if !is_whitespace_only {
-                            just_filtered_trkpt = false;
-                        }
-                    }
-                }
+impl PointSelector for SplitSelector {
+    fn segment_start(&mut self) {
+        self.accumulator.segment_start();
+    }
+
+    fn select(&mut self, point: &PointContext) -> Option<usize> {
+        let distance = self.accumulator.add(point);
+        let group = match self.group {
+            Some(group) if self.starts_group(point, distance) => group + 1,
+            Some(group) => group,
+            None => 0,
+        };
+        if self.group != Some(group) {
+            self.group = Some(group);
+            self.group_points = 0;
+            self.group_start_distance = distance.unwrap_or(self.accumulator.distance);
+        }
+        self.group_points += 1;
+        if point.time.is_some() {
+            self.previous_time = point.time;
+        }
+        Some(group)
+    }
+}
+
+/// Copies `input` to `output`, keeping only the track points chosen by `selector` and leaving
+/// every other event untouched.
+///
+/// When consecutive kept points belong to different groups, the current segment (or track, as
+/// chosen by `split`) is closed and a copy of its start tag is written before the next point.
+pub fn filter_xml_by_selector<R: BufRead, W: Write, S: PointSelector + ?Sized>(
+    input: R,
+    selector: &mut S,
+    split: GroupSplit,
+    output: W,
+) -> Result<(), GpxWrenchError> {
+    let mut visitor = SelectionFilterVisitor {
+        writer: Writer::new(output),
+        selector,
+        split,
+        in_trkpt: false,
+        trkpt_buffer: Vec::new(),
+        trkpt: PointContext {
+            lat: None,
+            lon: None,
+            time: None,
+        },
+        in_trkseg: false,
+        last_whitespace: None,
+        pending_whitespace: None,
+        track: None,
+        track_group: None,
+        segment: None,
+        segment_group: None,
+    };
+    visit_track_points(input, &mut visitor)
+}
+
+struct SelectionFilterVisitor<'a, W: Write, S: ?Sized> {
+    writer: Writer<W>,
+    selector: &'a mut S,
+    split: GroupSplit,
+    in_trkpt: bool,
+    trkpt_buffer: Vec<Event<'static>>,
+    trkpt: PointContext,
+    in_trkseg: bool,
+    /// The whitespace-only text written right before the current event, if any.
+    last_whitespace: Option<Event<'static>>,
+    /// Whitespace inside a track segment that is only written once the next kept event is known,
+    /// so that filtered track points do not leave blank lines behind.
+    pending_whitespace: Option<Event<'static>>,
+    /// The current `<trk>` start tag and the whitespace before it, used to start a new track.
+    track: Option<(BytesStart<'static>, Option<Event<'static>>)>,
+    /// The group of the last track point written to the current track.
+    track_group: Option<usize>,
+    /// The current `<trkseg>` start tag and the whitespace before it, used to start a new segment.
+    segment: Option<(BytesStart<'static>, Option<Event<'static>>)>,
+    /// The group of the last track point written to the current segment.
+    segment_group: Option<usize>,
+}
+
+impl<W: Write, S: PointSelector + ?Sized> SelectionFilterVisitor<'_, W, S> {
+    fn flush_pending_whitespace(&mut self) -> Result<(), GpxWrenchError> {
+        if let Some(whitespace) = self.pending_whitespace.take() {
+            self.writer.write_event(whitespace)?;
+        }
+        Ok(())
+    }
+
+    /// Closes the current `<trkseg>`, or `<trk>` with [`GroupSplit::Track`], and opens a copy.
+    fn split(&mut self) -> Result<(), GpxWrenchError> {
+        let Self {
+            writer,
+            track,
+            segment,
+            ..
+        } = self;
+        let enclosing = match self.split {
+            GroupSplit::Segment => vec![segment.as_ref()],
+            GroupSplit::Track => vec![track.as_ref(), segment.as_ref()],
+        };
+
+        for (element, indent) in enclosing.iter().rev().flatten() {
+            if let Some(indent) = indent {
+                writer.write_event(indent.borrow())?;
             }
+            writer.write_event(Event::End(element.to_end()))?;
+        }
+        for (element, indent) in enclosing.iter().flatten() {
+            if let Some(indent) = indent {
+                writer.write_event(indent.borrow())?;
+            }
+            writer.write_event(Event::Start(element.borrow()))?;
+        }
+        Ok(())
+    }
+}
 
-            Event::Empty(ref e)
-                if trkseg_depth == Some(element_depth)
-                    && trkpt_depth.is_none()
-                    && is_element_name(e.name(), b"trkpt") =>
+impl<W: Write, S: PointSelector + ?Sized> TrackPointVisitor for SelectionFilterVisitor<'_, W, S> {
+    fn track_start(&mut self, element: &BytesStart<'_>) -> Result<(), GpxWrenchError> {
+        self.track = Some((element.clone().into_owned(), self.last_whitespace.clone()));
+        self.track_group = None;
+        self.selector.track_start();
+        Ok(())
+    }
+
+    fn track_end(&mut self) -> Result<(), GpxWrenchError> {
+        self.track = None;
+        Ok(())
+    }
+
+    fn segment_start(&mut self, element: &BytesStart<'_>) -> Result<(), GpxWrenchError> {
+        self.in_trkseg = true;
+        self.segment = Some((element.clone().into_owned(), self.last_whitespace.clone()));
+        self.segment_group = None;
+        self.selector.segment_start();
+        Ok(())
+    }
+
+    fn segment_end(&mut self) -> Result<(), GpxWrenchError> {
+        self.in_trkseg = false;
+        self.segment = None;
+        Ok(())
+    }
+
+    fn track_point_start(&mut self, element: &BytesStart<'_>) -> Result<(), GpxWrenchError> {
+        self.in_trkpt = true;
+        self.trkpt_buffer.clear();
+        // Malformed attributes are copied through unchanged, as filtering never rejected them.
+        let (lat, lon) = track_point_coordinates(element).unwrap_or((None, None));
+        self.trkpt = PointContext {
+            lat,
+            lon,
+            time: None,
+        };
+        Ok(())
+    }
+
+    fn track_point_time(&mut self, time: OffsetDateTime) -> Result<(), GpxWrenchError> {
+        self.trkpt.time = Some(time);
+        Ok(())
+    }
+
+    fn track_point_end(&mut self) -> Result<(), GpxWrenchError> {
+        if let Some(group) = self.selector.select(&self.trkpt) {
+            let current_group = match self.split {
+                GroupSplit::Segment => self.segment_group,
+                GroupSplit::Track => self.track_group,
+            };
+            if current_group.is_some_and(|current| current != group) {
+                self.split()?;
+            }
+            self.segment_group = Some(group);
+            self.track_group = Some(group);
+            if let Some((lat, lon)) = self.selector.position()
+                && let Some(start) = self.trkpt_buffer.first_mut()
             {
-                just_filtered_trkpt = true;
+                *start = with_coordinates(start, lat, lon)?;
             }
 
-            event => {
-                if in_trkpt {
-                    trkpt_buffer.push(event.clone());
-                } else {
-                    writer.write_event(event)?;
-                }
+            // Write all buffered events for this trkpt
+            self.flush_pending_whitespace()?;
+            for buffered_event in self.trkpt_buffer.drain(..) {
+                self.writer.write_event(buffered_event)?;
             }
         }
+        self.in_trkpt = false;
+        self.trkpt_buffer.clear();
+        Ok(())
+    }
 
-        buf.clear();
+    fn event(&mut self, event: &Event<'_>) -> Result<(), GpxWrenchError> {
+        if self.in_trkpt {
+            self.trkpt_buffer.push(event.clone().into_owned());
+            return Ok(());
+        }
+
+        let is_whitespace_only =
+            matches!(event, Event::Text(text) if text.iter().all(|b| b.is_ascii_whitespace()));
+        self.last_whitespace = is_whitespace_only.then(|| event.clone().into_owned());
+
+        if self.in_trkseg && is_whitespace_only {
+            // Only the whitespace right before the next kept event is written.
+            self.pending_whitespace = self.last_whitespace.clone();
+            return Ok(());
+        }
+        self.flush_pending_whitespace()?;
+        self.writer.write_event(event.borrow())?;
+        Ok(())
     }
+}
 
-    Ok(())
+/// Writes the track points chosen by `selector` to a separate document per run of points of the
+/// same group, in a single pass over `input`. Each document is created with `open_part`, given
+/// its number counting from zero, and handed to `close_part` once complete. Returns the number
+/// of documents written.
+///
+/// Every document repeats the prolog of `input` up to its first track: the `<gpx>` element,
+/// metadata and comments. Waypoints and routes in that prolog only go to the first document, and
+/// content after the last kept point, such as document extensions, only to the last one. Tracks
+/// and segments are only written to the documents that receive some of their points.
+pub fn split_xml_by_selector<R, S, W, O, C>(
+    input: R,
+    selector: &mut S,
+    open_part: O,
+    close_part: C,
+) -> Result<usize, GpxWrenchError>
+where
+    R: BufRead,
+    S: PointSelector + ?Sized,
+    W: Write,
+    O: FnMut(usize) -> Result<W, GpxWrenchError>,
+    C: FnMut(W) -> Result<(), GpxWrenchError>,
+{
+    let mut visitor = PartSplitVisitor {
+        selector,
+        open_part,
+        close_part,
+        parts: 0,
+        part: None,
+        prolog: Vec::new(),
+        first_only_depth: None,
+        gpx_depth: None,
+        gpx_end: None,
+        depth: 0,
+        skip_start: false,
+        track: None,
+        track_header: Vec::new(),
+        segment_seen: false,
+        segment: None,
+        in_trkpt: false,
+        trkpt_buffer: Vec::new(),
+        trkpt: PointContext {
+            lat: None,
+            lon: None,
+            time: None,
+        },
+        last_whitespace: None,
+        pending_whitespace: None,
+    };
+    visit_track_points(input, &mut visitor)?;
+    visitor.finish_part()?;
+    Ok(visitor.parts)
 }
 
-pub fn extract_track_points(input: &[u8]) -> Result<Vec<TrackPoint>, Box<dyn Error>> {
-    extract_track_points_with_limit(input, MAX_TRACK_POINTS)
+/// A `<trk>` or `<trkseg>` of the input and whether it is open in the current document.
+struct PartContainer {
+    start: BytesStart<'static>,
+    /// The whitespace before the start tag, also written before the end tag.
+    indent: Option<Event<'static>>,
+    depth: usize,
+    written: bool,
 }
 
-fn extract_track_points_with_limit(
-    input: &[u8],
-    max_track_points: usize,
-) -> Result<Vec<TrackPoint>, Box<dyn Error>> {
-    let mut reader = Reader::from_reader(input);
-    let mut buf = Vec::new();
-    let mut track_points = Vec::new();
-
-    let mut in_trkpt = false;
-    let mut current_lat: Option<f64> = None;
-    let mut current_lon: Option<f64> = None;
-    let mut current_time: Option<OffsetDateTime> = None;
-    let mut in_time_element = false;
-    let mut time_text = String::new();
-    let mut element_depth = 0usize;
-    let mut gpx_depth: Option<usize> = None;
-    let mut track_depth: Option<usize> = None;
-    let mut trkseg_depth: Option<usize> = None;
-    let mut trkpt_depth: Option<usize> = None;
-    let mut time_element_depth: Option<usize> = None;
+struct OpenPart<W: Write> {
+    group: usize,
+    writer: Writer<W>,
+}
 
-    loop {
-        let event = match reader.read_event_into(&mut buf) {
-            Err(e) => {
-                return Err(
-                    format!("Error at position {}: {:?}", reader.buffer_position(), e).into(),
-                );
+struct PartSplitVisitor<'a, S: ?Sized, W: Write, O, C> {
+    selector: &'a mut S,
+    open_part: O,
+    close_part: C,
+    parts: usize,
+    part: Option<OpenPart<W>>,
+    /// Events before the first kept point, each with whether it only goes to the first document.
+    prolog: Vec<(Event<'static>, bool)>,
+    /// The depth of the waypoint or route currently added to `prolog`.
+    first_only_depth: Option<usize>,
+    gpx_depth: Option<usize>,
+    /// The end tag of `<gpx>` until the input closes it.
+    gpx_end: Option<BytesEnd<'static>>,
+    depth: usize,
+    /// Whether the next event is the start tag of the track or segment that just started.
+    skip_start: bool,
+    track: Option<PartContainer>,
+    /// Children of the current `<trk>` before its first `<trkseg>`, such as its name.
+    track_header: Vec<Event<'static>>,
+    segment_seen: bool,
+    segment: Option<PartContainer>,
+    in_trkpt: bool,
+    trkpt_buffer: Vec<Event<'static>>,
+    trkpt: PointContext,
+    last_whitespace: Option<Event<'static>>,
+    /// Whitespace that is only written once the next written event is known.
+    pending_whitespace: Option<Event<'static>>,
+}
+
+impl<S, W, O, C> PartSplitVisitor<'_, S, W, O, C>
+where
+    S: PointSelector + ?Sized,
+    W: Write,
+    O: FnMut(usize) -> Result<W, GpxWrenchError>,
+    C: FnMut(W) -> Result<(), GpxWrenchError>,
+{
+    fn start_part(&mut self, group: usize) -> Result<(), GpxWrenchError> {
+        self.finish_part()?;
+        let mut writer = Writer::new((self.open_part)(self.parts)?);
+        for (event, first_only) in &self.prolog {
+            if self.parts == 0 || !first_only {
+                writer.write_event(event.borrow())?;
             }
-            Ok(Event::Eof) if element_depth == 0 => break,
-            Ok(Event::Eof) => return Err("Unexpected EOF while parsing GPX".into()),
-            Ok(event) => event.into_owned(),
-        };
+        }
+        self.parts += 1;
+        self.part = Some(OpenPart { group, writer });
+        Ok(())
+    }
 
-        match event {
-            Event::Start(ref e) => {
-                element_depth += 1;
-                if gpx_depth.is_none() && is_element_name(e.name(), b"gpx") {
-                    gpx_depth = Some(element_depth);
-                } else if gpx_depth.is_some_and(|depth| element_depth == depth + 1)
-                    && track_depth.is_none()
-                    && is_element_name(e.name(), b"trk")
-                {
-                    track_depth = Some(element_depth);
-                } else if track_depth.is_some_and(|depth| element_depth == depth + 1)
-                    && trkseg_depth.is_none()
-                    && is_element_name(e.name(), b"trkseg")
-                {
-                    trkseg_depth = Some(element_depth);
-                } else if trkseg_depth.is_some_and(|depth| element_depth == depth + 1)
-                    && trkpt_depth.is_none()
-                    && is_element_name(e.name(), b"trkpt")
-                {
-                    in_trkpt = true;
-                    trkpt_depth = Some(element_depth);
-                    current_lat = None;
-                    current_lon = None;
-                    current_time = None;
-
-                    for attr in e.attributes() {
-                        let attr = attr?;
-                        match attr.key.as_ref() {
-                            b"lat" => {
-                                if let Ok(lat_str) = std::str::from_utf8(&attr.value) {
-                                    current_lat = lat_str.parse().ok();
-                                }
-                            }
-                            b"lon" => {
-                                if let Ok(lon_str) = std::str::from_utf8(&attr.value) {
-                                    current_lon = lon_str.parse().ok();
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                } else if in_trkpt
-                    && trkpt_depth.is_some_and(|depth| element_depth == depth + 1)
-                    && is_element_name(e.name(), b"time")
-                {
-                    in_time_element = true;
-                    time_element_depth = Some(element_depth);
-                    time_text.clear();
+    /// Closes the elements still open in the current document and hands it to `close_part`.
+    fn finish_part(&mut self) -> Result<(), GpxWrenchError> {
+        let Some(mut part) = self.part.take() else {
+            return Ok(());
+        };
+        let writer = &mut part.writer;
+        for container in [&mut self.segment, &mut self.track].into_iter().flatten() {
+            if container.written {
+                if let Some(indent) = &container.indent {
+                    writer.write_event(indent.borrow())?;
                 }
+                writer.write_event(Event::End(container.start.to_end()))?;
+                container.written = false;
             }
+        }
+        match &self.gpx_end {
+            Some(gpx_end) => {
+                writer.write_event(Event::Text(BytesText::from_escaped("\n")))?;
+                writer.write_event(Event::End(gpx_end.borrow()))?;
+                writer.write_event(Event::Text(BytesText::from_escaped("\n")))?;
+            }
+            // The input is complete, so only whitespace after `</gpx>` can be pending.
+            None => {
+                if let Some(whitespace) = self.pending_whitespace.take() {
+                    writer.write_event(whitespace)?;
+                }
+            }
+        }
+        (self.close_part)(part.writer.into_inner())
+    }
 
-            Event::End(ref e) => {
-                if trkpt_depth == Some(element_depth) && is_element_name(e.name(), b"trkpt") {
-                    if let (Some(lat), Some(lon), Some(time)) =
-                        (current_lat, current_lon, current_time)
-                    {
-                        if track_points.len() == max_track_points {
-                            return Err(format!(
-                                "Input exceeds maximum supported track point count of {max_track_points}"
-                            )
-                            .into());
-                        }
-                        track_points.push(TrackPoint { lat, lon, time });
-                    }
-                    in_trkpt = false;
-                    trkpt_depth = None;
-                } else if in_trkpt
-                    && in_time_element
-                    && time_element_depth == Some(element_depth)
-                    && is_element_name(e.name(), b"time")
-                {
-                    in_time_element = false;
-                    if let Ok(parsed_time) = OffsetDateTime::parse(
-                        &time_text,
-                        &time::format_description::well_known::Iso8601::DEFAULT,
-                    ) {
-                        current_time = Some(parsed_time);
-                    }
-                    time_element_depth = None;
-                } else if trkseg_depth == Some(element_depth)
-                    && is_element_name(e.name(), b"trkseg")
-                {
-                    trkseg_depth = None;
-                } else if track_depth == Some(element_depth) && is_element_name(e.name(), b"trk") {
-                    track_depth = None;
-                } else if gpx_depth == Some(element_depth) && is_element_name(e.name(), b"gpx") {
-                    gpx_depth = None;
+    /// Writes `event` to the current document, after the pending whitespace, if `written`;
+    /// otherwise drops both.
+    fn write_if(&mut self, written: bool, event: Event<'static>) -> Result<(), GpxWrenchError> {
+        let pending = self.pending_whitespace.take();
+        if let (true, Some(part)) = (written, &mut self.part) {
+            if let Some(whitespace) = pending {
+                part.writer.write_event(whitespace)?;
+            }
+            part.writer.write_event(event)?;
+        }
+        Ok(())
+    }
+}
+
+impl<S, W, O, C> TrackPointVisitor for PartSplitVisitor<'_, S, W, O, C>
+where
+    S: PointSelector + ?Sized,
+    W: Write,
+    O: FnMut(usize) -> Result<W, GpxWrenchError>,
+    C: FnMut(W) -> Result<(), GpxWrenchError>,
+{
+    fn gpx_start(&mut self, element: &BytesStart<'_>) -> Result<(), GpxWrenchError> {
+        self.gpx_depth = Some(self.depth + 1);
+        self.gpx_end = Some(element.to_end().into_owned());
+        Ok(())
+    }
+
+    fn gpx_end(&mut self) -> Result<(), GpxWrenchError> {
+        self.gpx_end = None;
+        Ok(())
+    }
+
+    fn track_start(&mut self, element: &BytesStart<'_>) -> Result<(), GpxWrenchError> {
+        // The whitespace before the track is written as its indentation instead.
+        if self.last_whitespace.is_some() && self.part.is_none() {
+            self.prolog.pop();
+        }
+        self.pending_whitespace = None;
+        self.track = Some(PartContainer {
+            start: element.clone().into_owned(),
+            indent: self.last_whitespace.clone(),
+            depth: self.depth + 1,
+            written: false,
+        });
+        self.track_header.clear();
+        self.segment_seen = false;
+        self.skip_start = true;
+        self.selector.track_start();
+        Ok(())
+    }
+
+    fn track_end(&mut self) -> Result<(), GpxWrenchError> {
+        self.track = None;
+        Ok(())
+    }
+
+    fn segment_start(&mut self, element: &BytesStart<'_>) -> Result<(), GpxWrenchError> {
+        if self.last_whitespace.is_some() && !self.segment_seen {
+            self.track_header.pop();
+        }
+        self.pending_whitespace = None;
+        self.segment = Some(PartContainer {
+            start: element.clone().into_owned(),
+            indent: self.last_whitespace.clone(),
+            depth: self.depth + 1,
+            written: false,
+        });
+        self.segment_seen = true;
+        self.skip_start = true;
+        self.selector.segment_start();
+        Ok(())
+    }
+
+    fn segment_end(&mut self) -> Result<(), GpxWrenchError> {
+        self.segment = None;
+        Ok(())
+    }
+
+    fn track_point_start(&mut self, element: &BytesStart<'_>) -> Result<(), GpxWrenchError> {
+        self.in_trkpt = true;
+        self.trkpt_buffer.clear();
+        let (lat, lon) = track_point_coordinates(element).unwrap_or((None, None));
+        self.trkpt = PointContext {
+            lat,
+            lon,
+            time: None,
+        };
+        Ok(())
+    }
+
+    fn track_point_time(&mut self, time: OffsetDateTime) -> Result<(), GpxWrenchError> {
+        self.trkpt.time = Some(time);
+        Ok(())
+    }
+
+    fn track_point_end(&mut self) -> Result<(), GpxWrenchError> {
+        self.in_trkpt = false;
+        let mut buffer = std::mem::take(&mut self.trkpt_buffer);
+        let pending = self.pending_whitespace.take();
+        let Some(group) = self.selector.select(&self.trkpt) else {
+            return Ok(());
+        };
+        if self.part.as_ref().is_none_or(|part| part.group != group) {
+            self.start_part(group)?;
+        }
+        let Some(part) = &mut self.part else {
+            return Ok(());
+        };
+
+        if let Some(track) = &mut self.track
+            && !track.written
+        {
+            if let Some(indent) = &track.indent {
+                part.writer.write_event(indent.borrow())?;
+            }
+            part.writer
+                .write_event(Event::Start(track.start.borrow()))?;
+            for event in &self.track_header {
+                part.writer.write_event(event.borrow())?;
+            }
+            track.written = true;
+        }
+        if let Some(segment) = &mut self.segment
+            && !segment.written
+        {
+            if let Some(indent) = &segment.indent {
+                part.writer.write_event(indent.borrow())?;
+            }
+            part.writer
+                .write_event(Event::Start(segment.start.borrow()))?;
+            segment.written = true;
+        }
+        if let Some(whitespace) = pending {
+            part.writer.write_event(whitespace)?;
+        }
+        if let Some((lat, lon)) = self.selector.position()
+            && let Some(start) = buffer.first_mut()
+        {
+            *start = with_coordinates(start, lat, lon)?;
+        }
+        for event in buffer {
+            part.writer.write_event(event)?;
+        }
+        Ok(())
+    }
+
+    fn event(&mut self, event: &Event<'_>) -> Result<(), GpxWrenchError> {
+        if self.in_trkpt {
+            self.trkpt_buffer.push(event.clone().into_owned());
+            return Ok(());
+        }
+        if std::mem::take(&mut self.skip_start) {
+            // Tracks and segments are only started along with their first kept point.
+            self.depth += 1;
+            return Ok(());
+        }
+
+        let event = event.clone().into_owned();
+        let is_whitespace_only =
+            matches!(&event, Event::Text(text) if text.iter().all(|b| b.is_ascii_whitespace()));
+        self.last_whitespace = is_whitespace_only.then(|| event.clone());
+        // The depth of the element containing the event, or of the element an end tag closes.
+        let depth = self.depth;
+        match &event {
+            Event::Start(_) => self.depth += 1,
+            Event::End(_) => self.depth = self.depth.saturating_sub(1),
+            _ => {}
+        }
+        let is_empty_container = matches!(&event, Event::Empty(e)
+            if is_element_name(e.name(), b"trk") || is_element_name(e.name(), b"trkseg"));
+
+        if let Event::End(_) = &event {
+            let closed = [&mut self.segment, &mut self.track]
+                .into_iter()
+                .flatten()
+                .find(|container| container.depth == depth)
+                .map(|container| std::mem::replace(&mut container.written, false));
+            if let Some(written) = closed {
+                return self.write_if(written, event);
+            }
+        }
+        if let Some(segment) = &self.segment {
+            if is_whitespace_only {
+                self.pending_whitespace = Some(event);
+                return Ok(());
+            }
+            let written = segment.written;
+            return self.write_if(written, event);
+        }
+        if let Some(track) = &self.track {
+            if !self.segment_seen {
+                if !is_empty_container {
+                    self.track_header.push(event);
                 }
-                element_depth = element_depth
-                    .checked_sub(1)
-                    .ok_or("Unexpected closing XML element")?;
+                return Ok(());
             }
+            if is_whitespace_only {
+                self.pending_whitespace = Some(event);
+                return Ok(());
+            }
+            let written = track.written && !is_empty_container;
+            return self.write_if(written, event);
+        }
+        if is_empty_container {
+            return Ok(());
+        }
 
-            Event::Text(ref e) => {
-                if in_trkpt
-                    && in_time_element
-                    && let Ok(text) = std::str::from_utf8(e)
+        if self.part.is_none() {
+            let starts_first_only = self.first_only_depth.is_none()
+                && Some(depth) == self.gpx_depth
+                && matches!(&event, Event::Start(e) | Event::Empty(e)
+                    if is_element_name(e.name(), b"wpt") || is_element_name(e.name(), b"rte"));
+            if starts_first_only {
+                // The indentation of a waypoint or route goes with it.
+                if let Some((last, first_only)) = self.prolog.last_mut()
+                    && matches!(last, Event::Text(text) if text.iter().all(|b| b.is_ascii_whitespace()))
                 {
-                    time_text.push_str(text);
+                    *first_only = true;
                 }
+                if let Event::Start(_) = &event {
+                    self.first_only_depth = Some(depth + 1);
+                }
+            }
+            let first_only = starts_first_only || self.first_only_depth.is_some();
+            if matches!(&event, Event::End(_)) && self.first_only_depth == Some(depth) {
+                self.first_only_depth = None;
             }
+            self.prolog.push((event, first_only));
+            return Ok(());
+        }
+        if is_whitespace_only {
+            self.pending_whitespace = Some(event);
+            return Ok(());
+        }
+        self.write_if(true, event)
+    }
+}
+
+/// Returns a copy of the `<trkpt>` start tag `event` with its `lat` and `lon` attributes
+/// replaced, rounded to seven decimals (about a centimeter).
+fn with_coordinates(
+    event: &Event<'_>,
+    lat: f64,
+    lon: f64,
+) -> Result<Event<'static>, GpxWrenchError> {
+    let (Event::Start(element) | Event::Empty(element)) = event else {
+        return Ok(event.clone().into_owned());
+    };
+    let format = |value: f64| ((value * 1e7).round() / 1e7).to_string();
+    let mut moved = BytesStart::new(String::from_utf8_lossy(element.name().as_ref()).into_owned());
+    for attr in element.attributes() {
+        let attr = attr
+            .map_err(|e| GpxWrenchError::InvalidGpx(format!("Invalid <trkpt> attribute: {e}")))?;
+        match attr.key.as_ref() {
+            b"lat" => moved.push_attribute(("lat", format(lat).as_str())),
+            b"lon" => moved.push_attribute(("lon", format(lon).as_str())),
+            _ => moved.push_attribute(attr),
+        }
+    }
+    Ok(match event {
+        Event::Empty(_) => Event::Empty(moved),
+        _ => Event::Start(moved),
+    })
+}
 
+/// Reads the `lat` and `lon` attributes of a `<trkpt>`; unparseable and non-finite values are
+/// `None`.
+fn track_point_coordinates(
+    element: &BytesStart<'_>,
+) -> Result<(Option<f64>, Option<f64>), GpxWrenchError> {
+    let mut coordinates = (None, None);
+    for attr in element.attributes() {
+        let attr = attr
+            .map_err(|e| GpxWrenchError::InvalidGpx(format!("Invalid <trkpt> attribute: {e}")))?;
+        let value = std::str::from_utf8(&attr.value).ok().and_then(parse_finite);
+        match attr.key.as_ref() {
+            b"lat" => coordinates.0 = value,
+            b"lon" => coordinates.1 = value,
             _ => {}
         }
+    }
+    Ok(coordinates)
+}
 
-        buf.clear();
+pub fn extract_track_points<R: BufRead>(input: R) -> Result<Vec<TrackPoint>, GpxWrenchError> {
+    extract_track_points_with_limit(input, MAX_TRACK_POINTS)
+}
+
+/// Like [`extract_track_points`], but keeps the points of each `<trkseg>` separate. Segments
+/// without valid points are returned as empty vectors.
+pub fn extract_track_segments<R: BufRead>(
+    input: R,
+) -> Result<Vec<Vec<TrackPoint>>, GpxWrenchError> {
+    collect_track_segments(input, MAX_TRACK_POINTS)
+}
+
+/// A track point returned by [`extract_positioned_segments`], which may lack a time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PositionedPoint {
+    pub lat: f64,
+    pub lon: f64,
+    pub ele: Option<f64>,
+    /// The valid `<time>` of the point, if it has one.
+    pub time: Option<OffsetDateTime>,
+}
+
+/// Like [`extract_track_segments`], but also returns the points with a valid position and no
+/// valid time, for decisions that only depend on positions. Use
+/// [`SegmentPlanSelector::including_untimed`] to apply such decisions.
+pub fn extract_positioned_segments<R: BufRead>(
+    input: R,
+) -> Result<Vec<Vec<PositionedPoint>>, GpxWrenchError> {
+    collect_track_segments(input, MAX_TRACK_POINTS)
+}
+
+fn extract_track_points_with_limit<R: BufRead>(
+    input: R,
+    max_track_points: usize,
+) -> Result<Vec<TrackPoint>, GpxWrenchError> {
+    let segments: Vec<Vec<TrackPoint>> = collect_track_segments(input, max_track_points)?;
+    Ok(segments.into_iter().flatten().collect())
+}
+
+/// A point that [`collect_track_segments`] builds from each `<trkpt>` with a valid position.
+trait CollectedPoint: Sized {
+    /// Builds the point from `point`, which holds the position and children of the `<trkpt>`
+    /// but not its time, or returns `None` to skip it.
+    fn collect(point: TrackPoint, time: Option<OffsetDateTime>) -> Option<Self>;
+}
+
+impl CollectedPoint for TrackPoint {
+    fn collect(mut point: TrackPoint, time: Option<OffsetDateTime>) -> Option<Self> {
+        point.time = time?;
+        Some(point)
+    }
+}
+
+impl CollectedPoint for PositionedPoint {
+    fn collect(point: TrackPoint, time: Option<OffsetDateTime>) -> Option<Self> {
+        Some(PositionedPoint {
+            lat: point.lat,
+            lon: point.lon,
+            ele: point.ele,
+            time,
+        })
+    }
+}
+
+fn collect_track_segments<R: BufRead, P: CollectedPoint>(
+    input: R,
+    max_track_points: usize,
+) -> Result<Vec<Vec<P>>, GpxWrenchError> {
+    let mut visitor = TrackPointCollector {
+        max_track_points,
+        point_count: 0,
+        segments: Vec::new(),
+        current_lat: None,
+        current_lon: None,
+        current_time: None,
+        current_point: empty_track_point(),
+    };
+    visit_track_points(input, &mut visitor)?;
+    Ok(visitor.segments)
+}
+
+struct TrackPointCollector<P> {
+    max_track_points: usize,
+    point_count: usize,
+    segments: Vec<Vec<P>>,
+    current_lat: Option<f64>,
+    current_lon: Option<f64>,
+    current_time: Option<OffsetDateTime>,
+    /// Collects the optional children of the current track point; position and time are
+    /// filled in once the point is complete.
+    current_point: TrackPoint,
+}
+
+fn empty_track_point() -> TrackPoint {
+    TrackPoint::new(0.0, 0.0, OffsetDateTime::UNIX_EPOCH)
+}
+
+impl<P: CollectedPoint> TrackPointVisitor for TrackPointCollector<P> {
+    fn segment_start(&mut self, _element: &BytesStart<'_>) -> Result<(), GpxWrenchError> {
+        self.segments.push(Vec::new());
+        Ok(())
+    }
+
+    fn track_point_start(&mut self, element: &BytesStart<'_>) -> Result<(), GpxWrenchError> {
+        self.current_time = None;
+        self.current_point = empty_track_point();
+        (self.current_lat, self.current_lon) = track_point_coordinates(element)?;
+        Ok(())
+    }
+
+    fn track_point_field(&mut self, name: &str, text: &str) -> Result<(), GpxWrenchError> {
+        let point = &mut self.current_point;
+        let value = text.trim();
+        match name {
+            "ele" => point.ele = parse_finite(value),
+            "magvar" => point.magvar = parse_finite(value),
+            "geoidheight" => point.geoid_height = parse_finite(value),
+            "name" => point.name = Some(text.to_string()),
+            "cmt" => point.cmt = Some(text.to_string()),
+            "desc" => point.desc = Some(text.to_string()),
+            "sym" => point.sym = Some(text.to_string()),
+            "fix" => point.fix = value.parse().ok(),
+            "sat" => point.sat = value.parse().ok(),
+            "hdop" => point.hdop = parse_finite(value),
+            "vdop" => point.vdop = parse_finite(value),
+            "pdop" => point.pdop = parse_finite(value),
+            "ageofdgpsdata" => point.age_of_dgps_data = parse_finite(value),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn track_point_time(&mut self, time: OffsetDateTime) -> Result<(), GpxWrenchError> {
+        self.current_time = Some(time);
+        Ok(())
+    }
+
+    fn extension_field(&mut self, name: &str, text: &str) -> Result<(), GpxWrenchError> {
+        self.current_point
+            .sensors
+            .get_or_insert_with(SensorData::default)
+            .set_field(name, text);
+        Ok(())
     }
 
-    Ok(track_points)
+    fn track_point_end(&mut self) -> Result<(), GpxWrenchError> {
+        if let (Some(lat), Some(lon)) = (self.current_lat, self.current_lon) {
+            let mut point = std::mem::replace(&mut self.current_point, empty_track_point());
+            point.lat = lat;
+            point.lon = lon;
+            if point.sensors.as_ref().is_some_and(SensorData::is_empty) {
+                point.sensors = None;
+            }
+            let Some(point) = P::collect(point, self.current_time) else {
+                return Ok(());
+            };
+            if self.point_count == self.max_track_points {
+                return Err(GpxWrenchError::LimitExceeded(format!(
+                    "Input exceeds maximum supported track point count of {}",
+                    self.max_track_points
+                )));
+            }
+            self.point_count += 1;
+            if let Some(segment) = self.segments.last_mut() {
+                segment.push(point);
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -537,6 +1675,132 @@ mod tests {
   </trk>
 </gpx>"#;
 
+    #[derive(Default)]
+    struct RecordingVisitor {
+        calls: Vec<String>,
+    }
+
+    impl TrackPointVisitor for RecordingVisitor {
+        fn gpx_start(&mut self, _element: &BytesStart<'_>) -> Result<(), GpxWrenchError> {
+            self.calls.push("gpx".into());
+            Ok(())
+        }
+
+        fn gpx_end(&mut self) -> Result<(), GpxWrenchError> {
+            self.calls.push("/gpx".into());
+            Ok(())
+        }
+
+        fn track_start(&mut self, _element: &BytesStart<'_>) -> Result<(), GpxWrenchError> {
+            self.calls.push("trk".into());
+            Ok(())
+        }
+
+        fn track_end(&mut self) -> Result<(), GpxWrenchError> {
+            self.calls.push("/trk".into());
+            Ok(())
+        }
+
+        fn segment_start(&mut self, _element: &BytesStart<'_>) -> Result<(), GpxWrenchError> {
+            self.calls.push("trkseg".into());
+            Ok(())
+        }
+
+        fn segment_end(&mut self) -> Result<(), GpxWrenchError> {
+            self.calls.push("/trkseg".into());
+            Ok(())
+        }
+
+        fn track_point_start(&mut self, _element: &BytesStart<'_>) -> Result<(), GpxWrenchError> {
+            self.calls.push("trkpt".into());
+            Ok(())
+        }
+
+        fn track_point_time(&mut self, time: OffsetDateTime) -> Result<(), GpxWrenchError> {
+            self.calls.push(format!("time {}", time.second()));
+            Ok(())
+        }
+
+        fn extensions_start(&mut self) -> Result<(), GpxWrenchError> {
+            self.calls.push("extensions".into());
+            Ok(())
+        }
+
+        fn extensions_end(&mut self) -> Result<(), GpxWrenchError> {
+            self.calls.push("/extensions".into());
+            Ok(())
+        }
+
+        fn track_point_end(&mut self) -> Result<(), GpxWrenchError> {
+            self.calls.push("/trkpt".into());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_visit_track_points_reports_track_structure() {
+        let gpx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="test">
+  <metadata>
+    <extensions>
+      <vendor:trkpt xmlns:vendor="https://example.com/vendor"/>
+    </extensions>
+  </metadata>
+  <trk>
+    <trkseg>
+      <trkpt lat="37.7749" lon="-122.4194">
+        <time>2023-01-01T10:00:01Z</time>
+        <extensions>
+          <vendor:trkpt xmlns:vendor="https://example.com/vendor">
+            <vendor:time>2023-01-01T11:00:00Z</vendor:time>
+          </vendor:trkpt>
+        </extensions>
+      </trkpt>
+      <trkpt lat="37.7750" lon="-122.4195"/>
+    </trkseg>
+  </trk>
+</gpx>"#;
+
+        let mut visitor = RecordingVisitor::default();
+        visit_track_points(gpx.as_bytes(), &mut visitor).unwrap();
+
+        assert_eq!(
+            visitor.calls,
+            [
+                "gpx",
+                "trk",
+                "trkseg",
+                "trkpt",
+                "time 1",
+                "extensions",
+                "/extensions",
+                "/trkpt",
+                "trkpt",
+                "/trkpt",
+                "/trkseg",
+                "/trk",
+                "/gpx"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_visit_track_points_passes_every_event_through() {
+        struct EventCopier(Writer<Vec<u8>>);
+
+        impl TrackPointVisitor for EventCopier {
+            fn event(&mut self, event: &Event<'_>) -> Result<(), GpxWrenchError> {
+                self.0.write_event(event.borrow())?;
+                Ok(())
+            }
+        }
+
+        let mut visitor = EventCopier(Writer::new(Vec::new()));
+        visit_track_points(SAMPLE_GPX.as_bytes(), &mut visitor).unwrap();
+
+        assert_eq!(visitor.0.into_inner(), SAMPLE_GPX.as_bytes());
+    }
+
     /// Tests that find_minimum_time correctly identifies the earliest timestamp from GPX track points.
     #[test]
     fn test_find_minimum_time_with_valid_data() {
@@ -565,10 +1829,27 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[test]
+    fn test_find_time_bounds() {
+        let bounds = find_time_bounds(SAMPLE_GPX.as_bytes()).unwrap();
+        assert_eq!(
+            bounds,
+            Some((
+                parse_timestamp("2023-01-01T10:00:00Z"),
+                parse_timestamp("2023-01-01T10:00:10Z")
+            ))
+        );
+        assert_eq!(
+            find_maximum_time(SAMPLE_GPX.as_bytes()).unwrap(),
+            Some(parse_timestamp("2023-01-01T10:00:10Z"))
+        );
+        assert_eq!(find_maximum_time(&b""[..]).unwrap(), None);
+    }
+
     /// Tests that find_minimum_time handles completely empty input gracefully.
     #[test]
     fn test_find_minimum_time_with_empty_input() {
-        let result = find_minimum_time(b"").unwrap();
+        let result = find_minimum_time(&b""[..]).unwrap();
         assert!(result.is_none());
     }
 
@@ -618,6 +1899,26 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_xml_errors_report_line_and_column() {
+        let mismatched_gpx =
+            "<gpx>\n  <trk>\n    <trkseg>\n      <trkpt lat=\"1\" lon=\"2\"></trk>\n";
+
+        let result = find_minimum_time(mismatched_gpx.as_bytes());
+
+        let Err(GpxWrenchError::Xml {
+            offset,
+            line,
+            column,
+            ..
+        }) = result
+        else {
+            panic!("Expected XML error, got {result:?}");
+        };
+        assert_eq!((line, column), (4, 30));
+        assert_eq!(offset, 56);
+    }
+
     /// Tests that find_minimum_time ignores malformed timestamps and finds valid ones.
     #[test]
     fn test_find_minimum_time_with_malformed_time() {
@@ -1004,7 +2305,7 @@ mod tests {
         let mut output = Vec::new();
         filter_xml_by_time_to_writer_with_end_mode(
             SAMPLE_GPX.as_bytes(),
-            start_threshold,
+            Some(start_threshold),
             Some(end_threshold),
             true,
             &mut output,
@@ -1015,6 +2316,284 @@ mod tests {
         assert_eq!(gpx.tracks[0].segments[0].points.len(), 2);
     }
 
+    #[test]
+    fn test_filter_xml_by_time_bounds_with_open_sides() {
+        use gpx::{Gpx, read};
+
+        let count = |start, end| {
+            let mut output = Vec::new();
+            filter_xml_by_time_bounds(SAMPLE_GPX.as_bytes(), start, end, &mut output).unwrap();
+            let gpx: Gpx = read(output.as_slice()).unwrap();
+            gpx.tracks[0].segments[0].points.len()
+        };
+        let threshold = Some(parse_timestamp("2023-01-01T10:00:02Z"));
+
+        assert_eq!(count(threshold, None), 2);
+        assert_eq!(count(None, threshold), 1);
+        assert_eq!(count(None, None), 3);
+    }
+
+    #[test]
+    fn test_time_interval_set_sorts_and_merges() {
+        let t = |seconds: i64| {
+            Some(parse_timestamp("2023-01-01T10:00:00Z") + time::Duration::seconds(seconds))
+        };
+        let set = TimeIntervalSet::new([
+            TimeInterval::new(t(50), t(60)),
+            TimeInterval::new(t(0), t(10)),
+            TimeInterval::new(t(5), t(20)),
+            TimeInterval::new(t(30), t(30)),
+            TimeInterval::new(t(55), None),
+        ]);
+
+        assert_eq!(
+            set.intervals(),
+            [
+                TimeInterval::new(t(0), t(20)),
+                TimeInterval::new(t(50), None)
+            ]
+        );
+        assert_eq!(set.position(t(0).unwrap(), false), Some(0));
+        assert_eq!(set.position(t(20).unwrap(), false), None);
+        assert_eq!(set.position(t(20).unwrap(), true), Some(0));
+        assert_eq!(set.position(t(1000).unwrap(), false), Some(1));
+    }
+
+    #[test]
+    fn test_filter_xml_by_time_intervals_splits_segments() {
+        use gpx::{Gpx, read};
+
+        let t = |seconds: i64| {
+            Some(parse_timestamp("2023-01-01T10:00:00Z") + time::Duration::seconds(seconds))
+        };
+        let intervals = TimeIntervalSet::new([
+            TimeInterval::new(t(10), None),
+            TimeInterval::new(None, t(1)),
+        ]);
+
+        let mut output = Vec::new();
+        filter_xml_by_time_intervals(SAMPLE_GPX.as_bytes(), &intervals, &mut output).unwrap();
+        let output_text = std::str::from_utf8(&output).unwrap();
+        assert!(
+            output_text
+                .contains("</trkpt>\n    </trkseg>\n    <trkseg>\n      <trkpt lat=\"37.7751\"")
+        );
+        assert!(!output_text.contains("<ns3:hr>155</ns3:hr>"));
+
+        let gpx: Gpx = read(output.as_slice()).unwrap();
+        let segments = &gpx.tracks[0].segments;
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].points.len(), 1);
+        assert_eq!(segments[1].points.len(), 1);
+    }
+
+    #[test]
+    fn test_filter_xml_by_distance_intervals() {
+        use gpx::{Gpx, read};
+
+        // The three sample points are about 14 m apart.
+        let total = find_track_distance(SAMPLE_GPX.as_bytes()).unwrap();
+        let expected = haversine_distance(37.7749, -122.4194, 37.7750, -122.4195)
+            + haversine_distance(37.7750, -122.4195, 37.7751, -122.4196);
+        assert!((total - expected).abs() < 1e-9);
+        let tracks = find_track_distances(SAMPLE_GPX.as_bytes()).unwrap();
+        assert_eq!(tracks.len(), 1);
+        assert!((tracks[0] - expected).abs() < 1e-9);
+
+        let count = |start: Option<f64>, end: Option<f64>| {
+            let intervals = IntervalSet::new([Interval::new(start, end)]);
+            let mut output = Vec::new();
+            filter_xml_by_distance_intervals(SAMPLE_GPX.as_bytes(), &intervals, &mut output)
+                .unwrap();
+            let output_text = String::from_utf8(output).unwrap();
+            let gpx: Gpx = read(output_text.as_bytes()).unwrap();
+            (gpx.tracks[0].segments[0].points.len(), output_text)
+        };
+
+        assert_eq!(count(None, Some(1.0)).0, 1);
+        let (points, output_text) = count(Some(10.0), None);
+        assert_eq!(points, 2);
+        assert!(output_text.contains("<ns3:hr>160</ns3:hr>"));
+        assert!(!output_text.contains("<ns3:hr>150</ns3:hr>"));
+        assert_eq!(count(Some(total), None).0, 1);
+    }
+
+    #[test]
+    fn test_segment_plan_selector() {
+        use gpx::{Gpx, read};
+
+        let gpx_text = r#"<gpx version="1.1" creator="test">
+  <trk>
+    <trkseg>
+      <trkpt lat="1.0" lon="1.0"><time>2023-01-01T10:00:00Z</time></trkpt>
+      <trkpt lat="1.1" lon="1.0"><time>2023-01-01T10:00:01Z</time></trkpt>
+      <trkpt lat="1.2" lon="1.0"><name>no time</name></trkpt>
+      <trkpt lat="1.3" lon="1.0"><time>2023-01-01T10:00:03Z</time></trkpt>
+    </trkseg>
+    <trkseg>
+      <trkpt lat="2.0" lon="2.0"><time>2023-01-01T11:00:00Z</time></trkpt>
+    </trkseg>
+  </trk>
+</gpx>"#;
+        let mut selector =
+            SegmentPlanSelector::new(vec![vec![Some(0), Some(1), Some(1)], vec![None]]);
+        let mut output = Vec::new();
+        filter_xml_by_selector(
+            gpx_text.as_bytes(),
+            &mut selector,
+            GroupSplit::Segment,
+            &mut output,
+        )
+        .unwrap();
+
+        let gpx: Gpx = read(output.as_slice()).unwrap();
+        let segments: Vec<Vec<f64>> = gpx.tracks[0]
+            .segments
+            .iter()
+            .map(|segment| segment.points.iter().map(|p| p.point().y()).collect())
+            .collect();
+        assert_eq!(segments, vec![vec![1.0], vec![1.1, 1.2, 1.3], vec![]]);
+
+        // The point without a time is dropped along with the point before it.
+        let mut selector =
+            SegmentPlanSelector::new(vec![vec![Some(0), None, Some(1)], vec![Some(0)]]);
+        let mut output = Vec::new();
+        filter_xml_by_selector(
+            gpx_text.as_bytes(),
+            &mut selector,
+            GroupSplit::Segment,
+            &mut output,
+        )
+        .unwrap();
+        let gpx: Gpx = read(output.as_slice()).unwrap();
+        let segments: Vec<Vec<f64>> = gpx.tracks[0]
+            .segments
+            .iter()
+            .map(|segment| segment.points.iter().map(|p| p.point().y()).collect())
+            .collect();
+        assert_eq!(segments, vec![vec![1.0], vec![1.3], vec![2.0]]);
+
+        let mut selector = SegmentPlanSelector::new(vec![vec![Some(0); 3], vec![Some(0)]])
+            .with_positions(vec![vec![(1.05, 1.0), (1.15, 1.01)]]);
+        let mut output = Vec::new();
+        filter_xml_by_selector(
+            gpx_text.as_bytes(),
+            &mut selector,
+            GroupSplit::Segment,
+            &mut output,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(r#"<trkpt lat="1.05" lon="1">"#));
+        assert!(output.contains(r#"<trkpt lat="1.15" lon="1.01">"#));
+        assert!(output.contains(r#"<trkpt lat="1.2" lon="1.0">"#));
+        assert!(output.contains(r#"<trkpt lat="1.3" lon="1.0">"#));
+        assert!(output.contains(r#"<trkpt lat="2.0" lon="2.0">"#));
+    }
+
+    #[test]
+    fn test_split_selector() {
+        let point = |lat: f64, time: &str| PointContext {
+            lat: Some(lat),
+            lon: Some(0.0),
+            time: Some(parse_timestamp(time)),
+        };
+        let points = [
+            point(0.0, "2023-01-01T22:00:00Z"),
+            point(0.01, "2023-01-01T22:10:00Z"),
+            point(0.02, "2023-01-01T23:50:00Z"),
+            point(0.03, "2023-01-02T00:10:00Z"),
+            point(0.04, "2023-01-02T00:20:00Z"),
+        ];
+        let groups = |criteria: SplitCriteria| {
+            let mut selector = SplitSelector::new(criteria);
+            selector.segment_start();
+            let groups: Vec<Option<usize>> = points.iter().map(|p| selector.select(p)).collect();
+            assert_eq!(selector.group_count(), groups[4].unwrap() + 1);
+            groups
+        };
+
+        let gap = groups(SplitCriteria {
+            max_gap: Some(Duration::hours(1)),
+            ..SplitCriteria::default()
+        });
+        assert_eq!(gap, [Some(0), Some(0), Some(1), Some(1), Some(1)]);
+
+        let day = groups(SplitCriteria {
+            day_offset: Some(UtcOffset::UTC),
+            ..SplitCriteria::default()
+        });
+        assert_eq!(day, [Some(0), Some(0), Some(0), Some(1), Some(1)]);
+        let local_day = groups(SplitCriteria {
+            day_offset: Some(UtcOffset::from_hms(1, 0, 0).unwrap()),
+            ..SplitCriteria::default()
+        });
+        assert_eq!(local_day, [Some(0), Some(0), Some(1), Some(1), Some(1)]);
+
+        // Consecutive points are about 1.1 km apart.
+        let distance = groups(SplitCriteria {
+            distance: Some(2000.0),
+            ..SplitCriteria::default()
+        });
+        assert_eq!(distance, [Some(0), Some(0), Some(1), Some(1), Some(2)]);
+
+        let count = groups(SplitCriteria {
+            points: Some(2),
+            max_gap: Some(Duration::hours(1)),
+            ..SplitCriteria::default()
+        });
+        assert_eq!(count, [Some(0), Some(0), Some(1), Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn test_split_xml_by_selector() {
+        let input = r#"<gpx version="1.1" creator="test">
+  <metadata><name>Trip</name></metadata>
+  <wpt lat="1" lon="1"><name>Camp</name></wpt>
+  <trk>
+    <name>Day</name>
+    <trkseg>
+      <trkpt lat="1" lon="1"><time>2023-01-01T00:00:00Z</time></trkpt>
+      <trkpt lat="2" lon="1"><time>2023-01-01T00:01:00Z</time></trkpt>
+    </trkseg>
+    <trkseg>
+      <trkpt lat="3" lon="1"><time>2023-01-01T00:02:00Z</time></trkpt>
+    </trkseg>
+  </trk>
+  <extensions><note/></extensions>
+</gpx>
+"#;
+        let mut parts = Vec::new();
+        let mut selector = SplitSelector::new(SplitCriteria {
+            points: Some(1),
+            ..SplitCriteria::default()
+        });
+        let count = split_xml_by_selector(
+            input.as_bytes(),
+            &mut selector,
+            |_| Ok(Vec::new()),
+            |part| {
+                parts.push(String::from_utf8(part).unwrap());
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(count, 3);
+        assert_eq!(parts.len(), 3);
+        for (idx, part) in parts.iter().enumerate() {
+            let gpx = gpx::read(part.as_bytes()).unwrap();
+            assert_eq!(gpx.tracks.len(), 1, "{part}");
+            assert_eq!(gpx.tracks[0].name.as_deref(), Some("Day"));
+            assert_eq!(gpx.tracks[0].segments.len(), 1, "{part}");
+            assert_eq!(gpx.tracks[0].segments[0].points.len(), 1, "{part}");
+            assert_eq!(gpx.waypoints.len(), usize::from(idx == 0), "{part}");
+            assert!(part.contains("<name>Trip</name>"));
+            assert_eq!(part.contains("<note/>"), idx == 2, "{part}");
+        }
+        assert!(parts[1].contains(r#"<trkpt lat="2""#));
+    }
+
     #[test]
     fn test_extract_track_points() {
         let sample_gpx_with_movement = r#"<?xml version="1.0" encoding="UTF-8"?>
@@ -1061,7 +2640,142 @@ mod tests {
 
         let result = extract_track_points_with_limit(sample_gpx_with_two_points.as_bytes(), 1);
 
-        assert!(result.is_err());
+        assert!(matches!(result, Err(GpxWrenchError::LimitExceeded(_))));
+    }
+
+    #[test]
+    fn test_extract_track_segments_keeps_segments_and_elevation() {
+        let gpx = r#"<gpx version="1.1" creator="test">
+  <trk>
+    <trkseg>
+      <trkpt lat="1" lon="2"><ele> 12.5 </ele><time>2023-01-01T10:00:00Z</time></trkpt>
+      <trkpt lat="1" lon="3"><time>2023-01-01T10:00:05Z</time></trkpt>
+    </trkseg>
+    <trkseg/>
+    <trkseg>
+      <trkpt lat="1" lon="4"><ele>bad</ele><time>2023-01-01T10:01:00Z</time></trkpt>
+    </trkseg>
+  </trk>
+</gpx>"#;
+
+        let segments = extract_track_segments(gpx.as_bytes()).unwrap();
+
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].len(), 2);
+        assert_eq!(segments[0][0].ele, Some(12.5));
+        assert_eq!(segments[0][1].ele, None);
+        assert_eq!(segments[1][0].lon, 4.0);
+        assert_eq!(segments[1][0].ele, None);
+    }
+
+    #[test]
+    fn test_extract_track_points_reads_standard_children() {
+        let gpx = r#"<gpx version="1.1" creator="test">
+  <trk>
+    <trkseg>
+      <trkpt lat="37.7749" lon="-122.4194">
+        <ele>12.5</ele>
+        <time>2023-01-01T10:00:00Z</time>
+        <magvar>13.2</magvar>
+        <geoidheight>-32.1</geoidheight>
+        <name>Start &amp; finish</name>
+        <cmt>Gate</cmt>
+        <desc>Parking lot</desc>
+        <sym>Flag</sym>
+        <fix>3d</fix>
+        <sat>9</sat>
+        <hdop>0.8</hdop>
+        <vdop>1.1</vdop>
+        <pdop>1.4</pdop>
+        <ageofdgpsdata>2</ageofdgpsdata>
+      </trkpt>
+      <trkpt lat="37.7750" lon="-122.4195">
+        <time>2023-01-01T10:00:05Z</time>
+        <ele>NaN</ele>
+        <fix>4d</fix>
+        <sat>many</sat>
+        <hdop>inf</hdop>
+      </trkpt>
+    </trkseg>
+  </trk>
+</gpx>"#;
+
+        let points = extract_track_points(gpx.as_bytes()).unwrap();
+
+        let first = &points[0];
+        assert_eq!(first.ele, Some(12.5));
+        assert_eq!(first.magvar, Some(13.2));
+        assert_eq!(first.geoid_height, Some(-32.1));
+        assert_eq!(first.name.as_deref(), Some("Start & finish"));
+        assert_eq!(first.cmt.as_deref(), Some("Gate"));
+        assert_eq!(first.desc.as_deref(), Some("Parking lot"));
+        assert_eq!(first.sym.as_deref(), Some("Flag"));
+        assert_eq!(first.fix, Some(crate::Fix::ThreeD));
+        assert_eq!(first.sat, Some(9));
+        assert_eq!(first.hdop, Some(0.8));
+        assert_eq!(first.vdop, Some(1.1));
+        assert_eq!(first.pdop, Some(1.4));
+        assert_eq!(first.age_of_dgps_data, Some(2.0));
+
+        let second = &points[1];
+        assert_eq!(second.ele, None);
+        assert_eq!(second.name, None);
+        assert_eq!(second.fix, None);
+        assert_eq!(second.sat, None);
+        assert_eq!(second.hdop, None);
+    }
+
+    #[test]
+    fn test_extract_track_points_reads_sensor_extensions() {
+        let gpx = r#"<gpx version="1.1" creator="test"
+  xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v2"
+  xmlns:pwr="http://www.garmin.com/xmlschemas/PowerExtension/v1"
+  xmlns:gpxdata="http://www.cluetrust.com/XML/GPXDATA/1/0">
+  <trk>
+    <trkseg>
+      <trkpt lat="1" lon="2">
+        <time>2023-01-01T10:00:00Z</time>
+        <extensions>
+          <power>250</power>
+          <gpxtpx:TrackPointExtension>
+            <gpxtpx:atemp>21.5</gpxtpx:atemp>
+            <gpxtpx:hr>150</gpxtpx:hr>
+            <gpxtpx:cad>88</gpxtpx:cad>
+            <gpxtpx:speed>5.2</gpxtpx:speed>
+          </gpxtpx:TrackPointExtension>
+        </extensions>
+      </trkpt>
+      <trkpt lat="1" lon="3">
+        <time>2023-01-01T10:00:01Z</time>
+        <extensions>
+          <pwr:PowerExtension><pwr:PowerInWatts>310</pwr:PowerInWatts></pwr:PowerExtension>
+          <gpxdata:hr>152</gpxdata:hr>
+          <gpxdata:cadence>90</gpxdata:cadence>
+          <gpxdata:temp>22</gpxdata:temp>
+        </extensions>
+      </trkpt>
+      <trkpt lat="1" lon="4">
+        <time>2023-01-01T10:00:02Z</time>
+        <extensions><vendor:note xmlns:vendor="https://example.com">hi</vendor:note></extensions>
+      </trkpt>
+    </trkseg>
+  </trk>
+</gpx>"#;
+
+        let points = extract_track_points(gpx.as_bytes()).unwrap();
+
+        let first = points[0].sensors.as_ref().unwrap();
+        assert_eq!(first.heart_rate, Some(150));
+        assert_eq!(first.cadence, Some(88));
+        assert_eq!(first.power, Some(250));
+        assert_eq!(first.temperature, Some(21.5));
+        assert_eq!(first.speed, Some(5.2));
+        let second = points[1].sensors.as_ref().unwrap();
+        assert_eq!(second.heart_rate, Some(152));
+        assert_eq!(second.cadence, Some(90));
+        assert_eq!(second.power, Some(310));
+        assert_eq!(second.temperature, Some(22.0));
+        assert_eq!(points[2].sensors, None);
     }
 
     #[test]
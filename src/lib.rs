@@ -1,104 +1,659 @@
-use std::error::Error;
-use std::io::Read;
-use time::{Duration, OffsetDateTime};
+pub mod clean;
+pub mod document;
+pub mod error;
+pub mod gpxxml;
+pub mod merge;
+pub mod pauses;
+pub mod privacy;
+pub mod resample;
+pub mod sensors;
+pub mod simplify;
+pub mod smooth;
+pub mod stats;
+
+pub use document::Fix;
+pub use error::GpxWrenchError;
+pub use sensors::SensorData;
+
+use gpxxml::{Interval, TimeInterval};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use time::format_description::well_known::{Iso8601, Rfc3339};
+use time::{Duration, OffsetDateTime, PrimitiveDateTime, UtcOffset};
 
 pub const MAX_INPUT_BYTES: u64 = 100 * 1024 * 1024;
 pub const MAX_TRACK_POINTS: usize = 1_000_000;
 
+/// A timed track point together with the standard `wptType` children of its `<trkpt>`.
+///
+/// Optional values are `None` when the element is missing or cannot be parsed.
 #[derive(Debug, Clone)]
 pub struct TrackPoint {
     pub lat: f64,
     pub lon: f64,
     pub time: OffsetDateTime,
+    /// Elevation in meters.
+    pub ele: Option<f64>,
+    /// Magnetic variation in degrees.
+    pub magvar: Option<f64>,
+    /// Height of the geoid above the WGS84 ellipsoid in meters.
+    pub geoid_height: Option<f64>,
+    pub name: Option<String>,
+    pub cmt: Option<String>,
+    pub desc: Option<String>,
+    pub sym: Option<String>,
+    pub fix: Option<Fix>,
+    /// Number of satellites used for the fix.
+    pub sat: Option<u32>,
+    pub hdop: Option<f64>,
+    pub vdop: Option<f64>,
+    pub pdop: Option<f64>,
+    /// Seconds since the last DGPS update.
+    pub age_of_dgps_data: Option<f64>,
+    /// Heart rate, power and other sensor readings from the point's extensions.
+    pub sensors: Option<SensorData>,
 }
 
-#[derive(Debug)]
-pub enum TrimRange {
-    Duration { start: Duration, end: Duration },
-    Timestamp { start: Duration, end: Duration },
+impl TrackPoint {
+    pub fn new(lat: f64, lon: f64, time: OffsetDateTime) -> Self {
+        TrackPoint {
+            lat,
+            lon,
+            time,
+            ele: None,
+            magvar: None,
+            geoid_height: None,
+            name: None,
+            cmt: None,
+            desc: None,
+            sym: None,
+            fix: None,
+            sat: None,
+            hdop: None,
+            vdop: None,
+            pdop: None,
+            age_of_dgps_data: None,
+            sensors: None,
+        }
+    }
 }
 
-pub fn read_to_end_limited<R: Read>(reader: R, max_bytes: u64) -> Result<Vec<u8>, Box<dyn Error>> {
-    let read_limit = max_bytes
-        .checked_add(1)
-        .ok_or("Maximum input size is too large to enforce")?;
-    let mut input = Vec::new();
-    let mut limited_reader = reader.take(read_limit);
-    limited_reader.read_to_end(&mut input)?;
+/// One side of a [`TrimRange`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeBound {
+    /// No bound on this side.
+    Open,
+    /// An offset from the earliest track time.
+    FromStart(Duration),
+    /// An offset back from the latest track time.
+    FromEnd(Duration),
+    /// A wall-clock time, independent of the times in the track.
+    Absolute(OffsetDateTime),
+}
 
-    if input.len() as u64 > max_bytes {
-        return Err(format!("Input exceeds maximum supported size of {max_bytes} bytes").into());
+impl RangeBound {
+    fn is_relative(self) -> bool {
+        matches!(self, RangeBound::FromStart(_) | RangeBound::FromEnd(_))
     }
 
+    fn resolve(
+        self,
+        bounds: Option<(OffsetDateTime, OffsetDateTime)>,
+        side: &str,
+    ) -> Result<Option<OffsetDateTime>, GpxWrenchError> {
+        let overflow = || {
+            GpxWrenchError::TimestampOverflow(format!(
+                "Trim {side} exceeds supported timestamp range"
+            ))
+        };
+        let time = match (self, bounds) {
+            (RangeBound::Open, _) => return Ok(None),
+            (RangeBound::Absolute(time), _) => time,
+            // Without track times there is nothing to keep; any threshold will do.
+            (_, None) => OffsetDateTime::UNIX_EPOCH,
+            (RangeBound::FromStart(offset), Some((min_time, _))) => {
+                min_time.checked_add(offset).ok_or_else(overflow)?
+            }
+            (RangeBound::FromEnd(offset), Some((_, max_time))) => {
+                max_time.checked_sub(offset).ok_or_else(overflow)?
+            }
+        };
+        Ok(Some(time))
+    }
+}
+
+/// A time range `[start, end)` to keep, as parsed by [`parse_range`].
+///
+/// Before 0.2 this was an enum with `Duration`, `Timestamp` and `Absolute` variants; both of
+/// the relative ones are now [`RangeBound::FromStart`] on each side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrimRange {
+    pub start: RangeBound,
+    pub end: RangeBound,
+}
+
+impl TrimRange {
+    /// Whether resolving this range needs the earliest and latest track times.
+    pub fn needs_track_times(&self) -> bool {
+        self.start.is_relative() || self.end.is_relative()
+    }
+
+    /// Converts the range into an absolute interval given the earliest and latest track times.
+    pub fn resolve(
+        &self,
+        track_times: Option<(OffsetDateTime, OffsetDateTime)>,
+    ) -> Result<TimeInterval, GpxWrenchError> {
+        Ok(TimeInterval::new(
+            self.start.resolve(track_times, "start")?,
+            self.end.resolve(track_times, "end")?,
+        ))
+    }
+}
+
+pub fn read_to_end_limited<R: Read>(reader: R, max_bytes: u64) -> Result<Vec<u8>, GpxWrenchError> {
+    let mut input = Vec::new();
+    copy_limited(reader, &mut input, Some(max_bytes))?;
     Ok(input)
 }
 
-pub fn parse_duration(s: &str) -> Result<Duration, Box<dyn Error>> {
+/// Copies `reader` into `writer`, failing once more than `max_bytes` bytes have been read.
+pub fn copy_limited<R: Read, W: Write>(
+    mut reader: R,
+    writer: &mut W,
+    max_bytes: Option<u64>,
+) -> Result<u64, GpxWrenchError> {
+    let Some(max_bytes) = max_bytes else {
+        return Ok(io::copy(&mut reader, writer)?);
+    };
+
+    let read_limit = max_bytes.checked_add(1).ok_or_else(|| {
+        GpxWrenchError::InvalidArgument("Maximum input size is too large to enforce".into())
+    })?;
+    let copied = io::copy(&mut reader.take(read_limit), writer)?;
+
+    if copied > max_bytes {
+        return Err(input_too_large(max_bytes));
+    }
+
+    Ok(copied)
+}
+
+fn input_too_large(max_bytes: u64) -> GpxWrenchError {
+    GpxWrenchError::LimitExceeded(format!(
+        "Input exceeds maximum supported size of {max_bytes} bytes"
+    ))
+}
+
+/// Parses a number, rejecting `NaN` and infinities, which `f64::from_str` accepts.
+pub(crate) fn parse_finite(value: &str) -> Option<f64> {
+    value.parse().ok().filter(|value: &f64| value.is_finite())
+}
+
+/// GPX input that can be read from the beginning more than once.
+///
+/// Commands often need two passes over their input, e.g. one to find the earliest timestamp and
+/// one to write the filtered document. Buffered input keeps the whole document in memory, while
+/// file-backed input re-reads a seekable file (or a temporary spool file for non-seekable input
+/// such as stdin), so memory use stays bounded regardless of the input size.
+#[derive(Debug)]
+pub struct GpxInput {
+    storage: InputStorage,
+    path: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+enum InputStorage {
+    Memory(Vec<u8>),
+    File(File),
+    Spooled { file: File, path: PathBuf },
+}
+
+impl GpxInput {
+    /// Reads `reader` into memory, failing if it is larger than `max_bytes`.
+    pub fn buffered<R: Read>(reader: R, max_bytes: u64) -> Result<Self, GpxWrenchError> {
+        Ok(GpxInput {
+            storage: InputStorage::Memory(read_to_end_limited(reader, max_bytes)?),
+            path: None,
+        })
+    }
+
+    /// Uses a seekable file directly, failing if it is larger than `max_bytes`.
+    pub fn from_file(file: File, max_bytes: Option<u64>) -> Result<Self, GpxWrenchError> {
+        if let Some(max_bytes) = max_bytes
+            && file.metadata()?.len() > max_bytes
+        {
+            return Err(input_too_large(max_bytes));
+        }
+        Ok(GpxInput {
+            storage: InputStorage::File(file),
+            path: None,
+        })
+    }
+
+    /// Copies `reader` into a temporary file so it can be read more than once without holding
+    /// it in memory. The temporary file is removed when the input is dropped.
+    pub fn spooled<R: Read>(reader: R, max_bytes: Option<u64>) -> Result<Self, GpxWrenchError> {
+        Self::spooled_with(|mut file| copy_limited(reader, &mut file, max_bytes).map(drop))
+    }
+
+    /// Like [`GpxInput::spooled`], but fills the temporary file with `write`, e.g. to keep the
+    /// output of one pass over a document as the input of the next.
+    pub fn spooled_with<F>(write: F) -> Result<Self, GpxWrenchError>
+    where
+        F: FnOnce(&mut dyn Write) -> Result<(), GpxWrenchError>,
+    {
+        static SPOOL_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "gpxwrench-{}-{}.spool",
+            std::process::id(),
+            SPOOL_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        let mut writer = BufWriter::new(&mut file);
+        let result = write(&mut writer).and_then(|()| Ok(writer.flush()?));
+        drop(writer);
+        if let Err(e) = result {
+            let _ = fs::remove_file(&path);
+            return Err(e);
+        }
+
+        Ok(GpxInput {
+            storage: InputStorage::Spooled { file, path },
+            path: None,
+        })
+    }
+
+    /// Records the file the input was read from, for naming it in messages.
+    pub fn with_path(mut self, path: &Path) -> Self {
+        self.path = Some(path.to_path_buf());
+        self
+    }
+
+    /// Returns the file the input was read from, if it came from a named file.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Returns a reader positioned at the start of the input.
+    pub fn reader(&mut self) -> Result<Box<dyn BufRead + '_>, GpxWrenchError> {
+        match &mut self.storage {
+            InputStorage::Memory(bytes) => Ok(Box::new(bytes.as_slice())),
+            InputStorage::File(file) | InputStorage::Spooled { file, .. } => {
+                file.seek(SeekFrom::Start(0))?;
+                Ok(Box::new(BufReader::new(file)))
+            }
+        }
+    }
+}
+
+impl Drop for GpxInput {
+    fn drop(&mut self) {
+        if let InputStorage::Spooled { path, .. } = &self.storage {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+const NANOS_PER_SECOND: i128 = 1_000_000_000;
+
+/// Units accepted by [`parse_duration`], from largest to smallest.
+const DURATION_UNITS: [(&str, i128); 5] = [
+    ("d", 24 * 60 * 60 * NANOS_PER_SECOND),
+    ("h", 60 * 60 * NANOS_PER_SECOND),
+    ("m", 60 * NANOS_PER_SECOND),
+    ("s", NANOS_PER_SECOND),
+    ("ms", NANOS_PER_SECOND / 1000),
+];
+
+/// Parses a duration made of one or more `<number><unit>` components in decreasing unit order,
+/// e.g. `90s`, `1.5s`, `250ms` or `1h30m15s`. Units are `d`, `h`, `m`, `s` and `ms`; numbers may
+/// have a fractional part and a leading `-` negates the whole duration.
+pub fn parse_duration(s: &str) -> Result<Duration, GpxWrenchError> {
     if s.is_empty() {
-        return Err("Empty duration".into());
+        return Err(GpxWrenchError::InvalidRange("Empty duration".into()));
     }
+    let invalid =
+        |reason: &str| GpxWrenchError::InvalidRange(format!("Invalid duration {s:?}: {reason}"));
 
-    let (num_str, unit) = s.split_at(s.len() - 1);
-    let num: i64 = num_str.parse()?;
+    let (negative, mut rest) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    if rest.is_empty() {
+        return Err(invalid("missing value"));
+    }
+
+    let mut total: i128 = 0;
+    let mut previous_unit = None;
+    while !rest.is_empty() {
+        let num_len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .ok_or_else(|| invalid("missing unit"))?;
+        let unit_len = rest[num_len..]
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len() - num_len);
+        let (num_str, unit) = (&rest[..num_len], &rest[num_len..num_len + unit_len]);
+
+        let (rank, &(_, unit_nanos)) = DURATION_UNITS
+            .iter()
+            .enumerate()
+            .find(|(_, (name, _))| *name == unit)
+            .ok_or_else(|| {
+                GpxWrenchError::InvalidRange(format!("Invalid duration unit: {unit}"))
+            })?;
+        if previous_unit.is_some_and(|previous| rank <= previous) {
+            return Err(invalid("units must be given once each, largest first"));
+        }
+        previous_unit = Some(rank);
+
+        let nanos = parse_scaled(num_str, unit_nanos).ok_or_else(|| invalid("invalid number"))?;
+        total = total.checked_add(nanos).ok_or_else(duration_out_of_range)?;
+        rest = &rest[num_len + unit_len..];
+    }
+
+    duration_from_nanos(if negative { -total } else { total })
+}
+
+/// Parses `MM:SS` or `HH:MM:SS`, where the seconds may have a fractional part
+/// (e.g. `01:02:03.250`). A leading `-` negates the whole timestamp.
+pub fn parse_timestamp(s: &str) -> Result<Duration, GpxWrenchError> {
+    let (negative, unsigned) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let parts: Vec<&str> = unsigned.split(':').collect();
+    let field = |part: &str, unit_nanos: i128| {
+        if part.contains('.') && unit_nanos != NANOS_PER_SECOND {
+            return None;
+        }
+        parse_scaled(part, unit_nanos)
+    };
 
-    let seconds = match unit {
-        "s" => Some(num),
-        "m" => num.checked_mul(60),
-        "h" => num.checked_mul(60 * 60),
-        _ => return Err(format!("Invalid duration unit: {unit}").into()),
+    let fields = match parts.len() {
+        2 => [("0", 0), (parts[0], 60), (parts[1], 1)],
+        3 => [(parts[0], 60 * 60), (parts[1], 60), (parts[2], 1)],
+        _ => {
+            return Err(GpxWrenchError::InvalidRange(
+                "Invalid timestamp format".into(),
+            ));
+        }
+    };
+
+    let mut total: i128 = 0;
+    for (part, seconds) in fields {
+        let nanos = field(part, seconds * NANOS_PER_SECOND)
+            .ok_or_else(|| GpxWrenchError::InvalidRange(format!("Invalid timestamp {s:?}")))?;
+        total = total.checked_add(nanos).ok_or_else(duration_out_of_range)?;
     }
-    .ok_or("Duration exceeds supported range")?;
 
-    Ok(Duration::seconds(seconds))
+    duration_from_nanos(if negative { -total } else { total })
 }
 
-pub fn parse_timestamp(s: &str) -> Result<Duration, Box<dyn Error>> {
-    let parts: Vec<&str> = s.split(':').collect();
+/// Parses an unsigned decimal number such as `12` or `1.25` and multiplies it by `unit_nanos`,
+/// truncating below one nanosecond. Returns `None` for malformed numbers or on overflow.
+fn parse_scaled(num_str: &str, unit_nanos: i128) -> Option<i128> {
+    let (int_str, frac_str) = num_str.split_once('.').unwrap_or((num_str, ""));
+    if (int_str.is_empty() && frac_str.is_empty())
+        || !int_str
+            .bytes()
+            .chain(frac_str.bytes())
+            .all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
 
-    let (hours, minutes, seconds) = match parts.len() {
-        2 => (0, parts[0].parse::<i64>()?, parts[1].parse::<i64>()?),
-        3 => (
-            parts[0].parse::<i64>()?,
-            parts[1].parse::<i64>()?,
-            parts[2].parse::<i64>()?,
-        ),
-        _ => return Err("Invalid timestamp format".into()),
+    let int_nanos = if int_str.is_empty() {
+        0
+    } else {
+        int_str.parse::<i128>().ok()?.checked_mul(unit_nanos)?
     };
+    // Digits past the 18th cannot change the result for any supported unit.
+    let frac_str = &frac_str[..frac_str.len().min(18)];
+    let frac_nanos = if frac_str.is_empty() {
+        0
+    } else {
+        frac_str.parse::<i128>().ok()? * unit_nanos / 10i128.pow(frac_str.len() as u32)
+    };
+    int_nanos.checked_add(frac_nanos)
+}
 
-    let hours = hours
-        .checked_mul(60 * 60)
-        .ok_or("Timestamp hour value exceeds supported duration range")?;
-    let minutes = minutes
-        .checked_mul(60)
-        .ok_or("Timestamp minute value exceeds supported duration range")?;
-    let total_seconds = hours
-        .checked_add(minutes)
-        .and_then(|total| total.checked_add(seconds))
-        .ok_or("Timestamp exceeds supported duration range")?;
+fn duration_from_nanos(nanos: i128) -> Result<Duration, GpxWrenchError> {
+    let seconds = i64::try_from(nanos / NANOS_PER_SECOND).map_err(|_| duration_out_of_range())?;
+    Ok(Duration::new(seconds, (nanos % NANOS_PER_SECOND) as i32))
+}
+
+fn duration_out_of_range() -> GpxWrenchError {
+    GpxWrenchError::InvalidRange("Duration exceeds supported range".into())
+}
 
-    Ok(Duration::seconds(total_seconds))
+pub fn parse_range(range_str: &str) -> Result<TrimRange, GpxWrenchError> {
+    parse_range_with_tz(range_str, None)
 }
 
-pub fn parse_range(range_str: &str) -> Result<TrimRange, Box<dyn Error>> {
+/// Parses a trim range like [`parse_range`], using `tz` as the offset of absolute datetimes
+/// that do not specify one.
+pub fn parse_range_with_tz(
+    range_str: &str,
+    tz: Option<UtcOffset>,
+) -> Result<TrimRange, GpxWrenchError> {
+    let (start, end) = split_range(range_str)?;
+    Ok(TrimRange {
+        start: parse_range_bound(start, tz)?,
+        end: parse_range_bound(end, tz)?,
+    })
+}
+
+/// Splits `START,END` into its trimmed sides, at most one of which may be empty.
+fn split_range(range_str: &str) -> Result<(&str, &str), GpxWrenchError> {
     let parts: Vec<&str> = range_str.split(',').collect();
     if parts.len() != 2 {
-        return Err("Range must have exactly two parts separated by comma".into());
+        return Err(GpxWrenchError::InvalidRange(
+            "Range must have exactly two parts separated by comma".into(),
+        ));
+    }
+    let (start, end) = (parts[0].trim(), parts[1].trim());
+    if start.is_empty() && end.is_empty() {
+        return Err(GpxWrenchError::InvalidRange(
+            "Range needs at least one bound".into(),
+        ));
     }
+    Ok((start, end))
+}
+
+/// Parses one or more trim ranges separated by `;`, such as `0s,30s;5m,6m`, using `tz` like
+/// [`parse_range_with_tz`].
+pub fn parse_ranges_with_tz(
+    ranges_str: &str,
+    tz: Option<UtcOffset>,
+) -> Result<Vec<TrimRange>, GpxWrenchError> {
+    ranges_str
+        .split(';')
+        .map(|range_str| parse_range_with_tz(range_str, tz))
+        .collect()
+}
 
-    let start_str = parts[0].trim();
-    let end_str = parts[1].trim();
+/// Parses one side of a range: empty, a datetime, or an offset (`-` counts from the end).
+fn parse_range_bound(s: &str, tz: Option<UtcOffset>) -> Result<RangeBound, GpxWrenchError> {
+    if s.is_empty() {
+        return Ok(RangeBound::Open);
+    }
+    if is_datetime(s) {
+        return parse_datetime(s, tz).map(RangeBound::Absolute);
+    }
 
-    if start_str.contains(':') || end_str.contains(':') {
-        let start = parse_timestamp(start_str)?;
-        let end = parse_timestamp(end_str)?;
-        Ok(TrimRange::Timestamp { start, end })
+    let (from_end, offset_str) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let offset = if offset_str.contains(':') {
+        parse_timestamp(offset_str)?
     } else {
-        let start = parse_duration(start_str)?;
-        let end = parse_duration(end_str)?;
-        Ok(TrimRange::Duration { start, end })
+        parse_duration(offset_str)?
+    };
+    if from_end && offset.is_negative() {
+        return Err(GpxWrenchError::InvalidRange(format!(
+            "Invalid end-relative offset {s:?}"
+        )));
     }
+    Ok(if from_end {
+        RangeBound::FromEnd(offset)
+    } else {
+        RangeBound::FromStart(offset)
+    })
 }
 
+/// One side of a [`DistanceRange`], in meters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DistanceBound {
+    Open,
+    /// A distance from the first track point.
+    FromStart(f64),
+    /// A distance back from the last track point.
+    FromEnd(f64),
+}
+
+impl DistanceBound {
+    fn resolve(self, total_distance: f64) -> Option<f64> {
+        match self {
+            DistanceBound::Open => None,
+            DistanceBound::FromStart(distance) => Some(distance),
+            DistanceBound::FromEnd(distance) => Some(total_distance - distance),
+        }
+    }
+}
+
+/// A distance range `[start, end)` along the track, as parsed by [`parse_distance_ranges`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DistanceRange {
+    pub start: DistanceBound,
+    pub end: DistanceBound,
+}
+
+impl DistanceRange {
+    /// Whether resolving this range needs the total track distance.
+    pub fn needs_total_distance(&self) -> bool {
+        matches!(self.start, DistanceBound::FromEnd(_))
+            || matches!(self.end, DistanceBound::FromEnd(_))
+    }
+
+    /// Converts the range into distances from the first track point.
+    pub fn resolve(&self, total_distance: f64) -> Interval<f64> {
+        Interval::new(
+            self.start.resolve(total_distance),
+            self.end.resolve(total_distance),
+        )
+    }
+}
+
+/// Parses a distance such as `500m`, `12km`, `1.5km` or `3mi` into meters.
+pub fn parse_distance(s: &str) -> Result<f64, GpxWrenchError> {
+    let invalid = || GpxWrenchError::InvalidRange(format!("Invalid distance {s:?}"));
+    let unit_start = s
+        .find(|c: char| c.is_ascii_alphabetic())
+        .ok_or_else(invalid)?;
+    let (num_str, unit) = s.split_at(unit_start);
+    if !num_str.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+        return Err(invalid());
+    }
+    let num: f64 = num_str.parse().map_err(|_| invalid())?;
+
+    let meters_per_unit = match unit {
+        "m" => 1.0,
+        "km" => 1000.0,
+        "mi" => 1609.344,
+        _ => {
+            return Err(GpxWrenchError::InvalidRange(format!(
+                "Invalid distance unit: {unit}"
+            )));
+        }
+    };
+    Ok(num * meters_per_unit)
+}
+
+/// Parses one or more distance ranges separated by `;`, such as `500m,12km` or `0m,-1km`.
+/// Like time ranges, either side may be empty and a leading `-` counts back from the end.
+pub fn parse_distance_ranges(ranges_str: &str) -> Result<Vec<DistanceRange>, GpxWrenchError> {
+    let parse_bound = |s: &str| -> Result<DistanceBound, GpxWrenchError> {
+        if s.is_empty() {
+            return Ok(DistanceBound::Open);
+        }
+        Ok(match s.strip_prefix('-') {
+            Some(rest) => DistanceBound::FromEnd(parse_distance(rest)?),
+            None => DistanceBound::FromStart(parse_distance(s)?),
+        })
+    };
+
+    ranges_str
+        .split(';')
+        .map(|range_str| {
+            let (start, end) = split_range(range_str)?;
+            Ok(DistanceRange {
+                start: parse_bound(start)?,
+                end: parse_bound(end)?,
+            })
+        })
+        .collect()
+}
+
+/// Returns whether `s` looks like a calendar datetime (`YYYY-MM-DD...`) rather than an offset.
+fn is_datetime(s: &str) -> bool {
+    let bytes = s.as_bytes();
+    bytes.len() >= 10 && bytes[..4].iter().all(u8::is_ascii_digit) && bytes[4] == b'-'
+}
+
+/// Parses an RFC 3339 / ISO 8601 datetime. Datetimes without a UTC offset use `tz`.
+pub fn parse_datetime(s: &str, tz: Option<UtcOffset>) -> Result<OffsetDateTime, GpxWrenchError> {
+    if let Ok(datetime) = OffsetDateTime::parse(s, &Rfc3339) {
+        return Ok(datetime);
+    }
+    if let Ok(datetime) = OffsetDateTime::parse(s, &Iso8601::DEFAULT) {
+        return Ok(datetime);
+    }
+
+    let local = PrimitiveDateTime::parse(s, &Iso8601::DEFAULT)
+        .map_err(|e| GpxWrenchError::InvalidRange(format!("Invalid datetime {s:?}: {e}")))?;
+    let tz = tz.ok_or_else(|| {
+        GpxWrenchError::InvalidRange(format!(
+            "Datetime {s:?} has no UTC offset; add one (e.g. Z or +02:00) or pass --tz"
+        ))
+    })?;
+    Ok(local.assume_offset(tz))
+}
+
+/// Parses a UTC offset such as `+02:00`, `-0530`, `+02`, `Z` or `UTC`.
+pub fn parse_utc_offset(s: &str) -> Result<UtcOffset, GpxWrenchError> {
+    let invalid = || GpxWrenchError::InvalidArgument(format!("Invalid UTC offset: {s:?}"));
+    if s.eq_ignore_ascii_case("z") || s.eq_ignore_ascii_case("utc") {
+        return Ok(UtcOffset::UTC);
+    }
+
+    let (sign, rest) = match s.as_bytes().first() {
+        Some(b'+') => (1, &s[1..]),
+        Some(b'-') => (-1, &s[1..]),
+        _ => return Err(invalid()),
+    };
+    let digits = rest.replace(':', "");
+    if !matches!(digits.len(), 2 | 4) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let hours: i8 = digits[..2].parse().map_err(|_| invalid())?;
+    let minutes: i8 = match digits.len() {
+        4 => digits[2..].parse().map_err(|_| invalid())?,
+        _ => 0,
+    };
+    UtcOffset::from_hms(sign * hours, sign * minutes, 0).map_err(|_| invalid())
+}
+
+/// Mean Earth radius in meters (WGS84: 6371008.8m).
+pub const EARTH_RADIUS: f64 = 6371000.0;
+
 /// Calculates the great circle distance between two GPS coordinates using the haversine formula.
 ///
 /// This is the standard method for calculating distances on a sphere and is appropriate for
@@ -116,8 +671,6 @@ pub fn parse_range(range_str: &str) -> Result<TrimRange, Box<dyn Error>> {
 /// - https://en.wikipedia.org/wiki/Haversine_formula
 /// - https://www.movable-type.co.uk/scripts/latlong.html
 pub fn haversine_distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
-    const EARTH_RADIUS: f64 = 6371000.0; // Mean Earth radius in meters (WGS84: 6371008.8m)
-
     // Convert latitude and longitude differences to radians
     let lat1_rad = lat1.to_radians();
     let lat2_rad = lat2.to_radians();
@@ -146,52 +699,297 @@ pub fn calculate_speed(p1: &TrackPoint, p2: &TrackPoint) -> f64 {
     }
 }
 
+/// How point-to-point speeds are smoothed before activity detection. Window sizes count speed
+/// intervals and are centered on the interval being smoothed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpeedFilter {
+    None,
+    /// Rolling mean over the given number of intervals.
+    Mean(usize),
+    /// Rolling median over the given number of intervals, which ignores isolated GPS jumps.
+    Median(usize),
+}
+
+/// Parameters of activity detection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ActivityOptions {
+    /// Speed in m/s at or above which idle time turns into activity.
+    pub start_threshold: f64,
+    /// Speed in m/s below which a confirmed activity ends. Setting it below `start_threshold`
+    /// keeps short slowdowns from ending an activity.
+    pub stop_threshold: f64,
+    /// Number of consecutive intervals at or above `start_threshold` that confirm an activity.
+    pub min_run: usize,
+    pub speed_filter: SpeedFilter,
+    /// Seconds added before and after each detected activity.
+    pub buffer_seconds: u64,
+}
+
+impl ActivityOptions {
+    /// Options with a single threshold, runs of 3 intervals and no smoothing, as used by
+    /// [`detect_activity_bounds`].
+    pub fn new(speed_threshold: f64, buffer_seconds: u64) -> Self {
+        ActivityOptions {
+            start_threshold: speed_threshold,
+            stop_threshold: speed_threshold,
+            min_run: 3,
+            speed_filter: SpeedFilter::None,
+            buffer_seconds,
+        }
+    }
+}
+
 pub fn detect_activity_bounds(
     track_points: &[TrackPoint],
     speed_threshold: f64,
     buffer_seconds: u64,
-) -> Result<(OffsetDateTime, OffsetDateTime), Box<dyn Error>> {
+) -> Result<(OffsetDateTime, OffsetDateTime), GpxWrenchError> {
+    detect_activity_bounds_with_options(
+        track_points,
+        &ActivityOptions::new(speed_threshold, buffer_seconds),
+    )
+}
+
+/// Returns the period from the start of the first to the end of the last detected activity,
+/// widened by the buffer. Without any activity the whole track is returned.
+pub fn detect_activity_bounds_with_options(
+    track_points: &[TrackPoint],
+    options: &ActivityOptions,
+) -> Result<(OffsetDateTime, OffsetDateTime), GpxWrenchError> {
+    let analysis = analyze_activity(track_points, options)?;
+    let bounds = analysis.activity_bounds(track_points, None, options.buffer_seconds)?;
+    Ok(bounds[0])
+}
+
+/// Detects every period of activity, like [`detect_activity_bounds`] but without bridging
+/// long stops.
+///
+/// Active runs separated by an idle gap of at most `max_pause_seconds` belong to the same
+/// activity. Each activity is returned with `buffer_seconds` added on both sides, in
+/// chronological order; buffered activities may overlap. Without any activity the whole track
+/// is returned as a single interval.
+pub fn detect_activity_intervals(
+    track_points: &[TrackPoint],
+    speed_threshold: f64,
+    buffer_seconds: u64,
+    max_pause_seconds: u64,
+) -> Result<Vec<(OffsetDateTime, OffsetDateTime)>, GpxWrenchError> {
+    detect_activity_intervals_with_options(
+        track_points,
+        &ActivityOptions::new(speed_threshold, buffer_seconds),
+        max_pause_seconds,
+    )
+}
+
+/// Like [`detect_activity_intervals`], with the detection parameters given by `options`.
+pub fn detect_activity_intervals_with_options(
+    track_points: &[TrackPoint],
+    options: &ActivityOptions,
+    max_pause_seconds: u64,
+) -> Result<Vec<(OffsetDateTime, OffsetDateTime)>, GpxWrenchError> {
+    let analysis = analyze_activity(track_points, options)?;
+    analysis.activity_bounds(
+        track_points,
+        Some(max_pause_seconds),
+        options.buffer_seconds,
+    )
+}
+
+/// The intermediate results of activity detection, for explaining why a track was trimmed
+/// the way it was.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActivityAnalysis {
+    /// Smoothed speed in m/s between point `i` and point `i + 1`.
+    pub speeds: Vec<f64>,
+    /// Indices of the first and last point of every confirmed active run.
+    pub runs: Vec<(usize, usize)>,
+}
+
+/// Computes the speeds and active runs that activity detection is based on.
+pub fn analyze_activity(
+    track_points: &[TrackPoint],
+    options: &ActivityOptions,
+) -> Result<ActivityAnalysis, GpxWrenchError> {
+    check_activity_input(track_points)?;
+
+    let speeds = activity_speeds(track_points, options.speed_filter);
+    let runs = active_runs(&speeds, options);
+    Ok(ActivityAnalysis { speeds, runs })
+}
+
+impl ActivityAnalysis {
+    /// Whether the point at `point_idx` belongs to an active run.
+    pub fn is_active(&self, point_idx: usize) -> bool {
+        self.runs
+            .iter()
+            .any(|&(start, end)| (start..=end).contains(&point_idx))
+    }
+
+    /// Returns the indices of the first and last point of every activity.
+    ///
+    /// Without `max_pause_seconds` all runs form a single activity; otherwise runs separated by
+    /// an idle gap of at most `max_pause_seconds` are merged. Empty if no activity was detected.
+    pub fn activities(
+        &self,
+        track_points: &[TrackPoint],
+        max_pause_seconds: Option<u64>,
+    ) -> Result<Vec<(usize, usize)>, GpxWrenchError> {
+        let Some(max_pause_seconds) = max_pause_seconds else {
+            return Ok(match (self.runs.first(), self.runs.last()) {
+                (Some(&(start, _)), Some(&(_, end))) => vec![(start, end)],
+                _ => Vec::new(),
+            });
+        };
+
+        let max_pause = Duration::seconds(i64::try_from(max_pause_seconds).map_err(|_| {
+            GpxWrenchError::InvalidArgument("Maximum pause exceeds supported duration range".into())
+        })?);
+        let mut activities: Vec<(usize, usize)> = Vec::new();
+        for &(start, end) in &self.runs {
+            match activities.last_mut() {
+                Some((_, last_end))
+                    if track_points[start].time - track_points[*last_end].time <= max_pause =>
+                {
+                    *last_end = end;
+                }
+                _ => activities.push((start, end)),
+            }
+        }
+        Ok(activities)
+    }
+
+    /// Returns the time period of every activity widened by `buffer_seconds`, or the whole
+    /// track if no activity was detected.
+    pub fn activity_bounds(
+        &self,
+        track_points: &[TrackPoint],
+        max_pause_seconds: Option<u64>,
+        buffer_seconds: u64,
+    ) -> Result<Vec<(OffsetDateTime, OffsetDateTime)>, GpxWrenchError> {
+        let mut activities = self.activities(track_points, max_pause_seconds)?;
+        if activities.is_empty() {
+            activities.push((0, track_points.len() - 1));
+        }
+
+        activities
+            .into_iter()
+            .map(|(start, end)| buffered_bounds(track_points, start, end, buffer_seconds))
+            .collect()
+    }
+}
+
+fn check_activity_input(track_points: &[TrackPoint]) -> Result<(), GpxWrenchError> {
     if track_points.len() < 2 {
-        return Err("Need at least 2 track points for activity detection".into());
+        return Err(GpxWrenchError::InsufficientData(
+            "Need at least 2 track points for activity detection".into(),
+        ));
     }
+    Ok(())
+}
+
+/// Returns the speed of each interval between consecutive points, smoothed by `filter`.
+fn activity_speeds(track_points: &[TrackPoint], filter: SpeedFilter) -> Vec<f64> {
+    let speeds: Vec<f64> = track_points
+        .windows(2)
+        .map(|points| calculate_speed(&points[0], &points[1]))
+        .collect();
+    let window = match filter {
+        SpeedFilter::None => return speeds,
+        SpeedFilter::Mean(window) | SpeedFilter::Median(window) => window.max(1),
+    };
 
-    let min_activity_points = 3;
-    let mut activity_start_idx = None;
-    let mut activity_end_idx = None;
+    (0..speeds.len())
+        .map(|idx| {
+            let start = idx.saturating_sub((window - 1) / 2);
+            let end = (idx + window / 2 + 1).min(speeds.len());
+            let values = &speeds[start..end];
+            match filter {
+                SpeedFilter::Median(_) => median(values),
+                _ => values.iter().sum::<f64>() / values.len() as f64,
+            }
+        })
+        .collect()
+}
 
+fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let middle = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[middle - 1] + sorted[middle]) / 2.0
+    } else {
+        sorted[middle]
+    }
+}
+
+/// Returns the indices of the first and last point of every confirmed active run, where
+/// `speeds[i]` is the speed between points `i` and `i + 1`.
+///
+/// A run is confirmed by `min_run` consecutive intervals at or above the start threshold and
+/// then lasts until an interval drops below the stop threshold.
+fn active_runs(speeds: &[f64], options: &ActivityOptions) -> Vec<(usize, usize)> {
+    let min_run = options.min_run.max(1);
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+
+    let mut active = false;
     let mut consecutive_active = 0;
     let mut current_run_start_idx = 0;
-    for (idx, points) in track_points.windows(2).enumerate() {
+    for (idx, &speed) in speeds.iter().enumerate() {
         let point_idx = idx + 1;
-        let speed = calculate_speed(&points[0], &points[1]);
-        if speed >= speed_threshold {
+        if active {
+            if speed >= options.stop_threshold {
+                if let Some((_, end)) = runs.last_mut() {
+                    *end = point_idx;
+                }
+                continue;
+            }
+            active = false;
+            consecutive_active = 0;
+        }
+
+        if speed >= options.start_threshold {
             if consecutive_active == 0 {
-                current_run_start_idx = point_idx - 1;
+                current_run_start_idx = idx;
             }
             consecutive_active += 1;
-            if consecutive_active >= min_activity_points {
-                activity_start_idx.get_or_insert(current_run_start_idx);
-                activity_end_idx = Some(point_idx);
+            if consecutive_active >= min_run {
+                active = true;
+                runs.push((current_run_start_idx, point_idx));
             }
         } else {
             consecutive_active = 0;
         }
     }
+    runs
+}
 
-    let start_idx = activity_start_idx.unwrap_or(0);
-    let end_idx = activity_end_idx.unwrap_or(track_points.len() - 1);
-
-    let buffer_seconds = i64::try_from(buffer_seconds)
-        .map_err(|_| "Buffer seconds exceed supported duration range")?;
+/// Returns the times of the points at `start_idx` and `end_idx`, widened by `buffer_seconds`.
+fn buffered_bounds(
+    track_points: &[TrackPoint],
+    start_idx: usize,
+    end_idx: usize,
+    buffer_seconds: u64,
+) -> Result<(OffsetDateTime, OffsetDateTime), GpxWrenchError> {
+    let buffer_seconds = i64::try_from(buffer_seconds).map_err(|_| {
+        GpxWrenchError::InvalidArgument("Buffer seconds exceed supported duration range".into())
+    })?;
     let buffer_duration = Duration::seconds(buffer_seconds);
     let start_time = track_points[start_idx]
         .time
         .checked_sub(buffer_duration)
-        .ok_or("Activity start time exceeds supported timestamp range")?;
+        .ok_or_else(|| {
+            GpxWrenchError::TimestampOverflow(
+                "Activity start time exceeds supported timestamp range".into(),
+            )
+        })?;
     let end_time = track_points[end_idx]
         .time
         .checked_add(buffer_duration)
-        .ok_or("Activity end time exceeds supported timestamp range")?;
+        .ok_or_else(|| {
+            GpxWrenchError::TimestampOverflow(
+                "Activity end time exceeds supported timestamp range".into(),
+            )
+        })?;
 
     Ok((start_time, end_time))
 }
@@ -202,15 +1000,15 @@ mod tests {
     use time::OffsetDateTime;
 
     fn make_track_point(lat: f64, lon: f64, time_str: &str) -> TrackPoint {
-        TrackPoint {
+        TrackPoint::new(
             lat,
             lon,
-            time: OffsetDateTime::parse(
+            OffsetDateTime::parse(
                 time_str,
                 &time::format_description::well_known::Iso8601::DEFAULT,
             )
             .unwrap(),
-        }
+        )
     }
 
     #[test]
@@ -222,7 +1020,7 @@ mod tests {
     #[test]
     fn test_read_to_end_limited_rejects_input_past_limit() {
         let result = read_to_end_limited("abcd".as_bytes(), 3);
-        assert!(result.is_err());
+        assert!(matches!(result, Err(GpxWrenchError::LimitExceeded(_))));
     }
 
     #[test]
@@ -231,16 +1029,103 @@ mod tests {
         assert!(result.is_err());
     }
 
+    fn read_all(input: &mut GpxInput) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        input.reader().unwrap().read_to_end(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_buffered_input_can_be_read_twice() {
+        let mut input = GpxInput::buffered("abc".as_bytes(), 3).unwrap();
+        assert_eq!(read_all(&mut input), b"abc");
+        assert_eq!(read_all(&mut input), b"abc");
+        assert!(GpxInput::buffered("abcd".as_bytes(), 3).is_err());
+    }
+
+    #[test]
+    fn test_spooled_input_can_be_read_twice_and_is_removed_on_drop() {
+        let mut input = GpxInput::spooled("abcd".as_bytes(), None).unwrap();
+        assert_eq!(read_all(&mut input), b"abcd");
+        assert_eq!(read_all(&mut input), b"abcd");
+
+        let InputStorage::Spooled { path, .. } = &input.storage else {
+            panic!("Expected spooled storage");
+        };
+        let path = path.clone();
+        assert!(path.exists());
+        drop(input);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_spooled_input_enforces_optional_limit() {
+        assert!(GpxInput::spooled("abc".as_bytes(), Some(3)).is_ok());
+        assert!(GpxInput::spooled("abcd".as_bytes(), Some(3)).is_err());
+    }
+
+    #[test]
+    fn test_spooled_with_keeps_written_output() {
+        let mut input = GpxInput::spooled_with(|writer| Ok(writer.write_all(b"abc")?)).unwrap();
+        assert_eq!(read_all(&mut input), b"abc");
+        assert!(
+            GpxInput::spooled_with(|_| Err(GpxWrenchError::InvalidGpx("broken".into()))).is_err()
+        );
+    }
+
     #[test]
     fn test_parse_duration() {
         assert_eq!(parse_duration("5s").unwrap(), Duration::seconds(5));
         assert_eq!(parse_duration("10m").unwrap(), Duration::minutes(10));
         assert_eq!(parse_duration("2h").unwrap(), Duration::hours(2));
-        assert!(parse_duration("5x").is_err());
+        assert!(matches!(
+            parse_duration("5x"),
+            Err(GpxWrenchError::InvalidRange(_))
+        ));
         assert!(parse_duration("").is_err());
         assert!(parse_duration("9223372036854775807h").is_err());
     }
 
+    #[test]
+    fn test_parse_compound_and_fractional_duration() {
+        assert_eq!(
+            parse_duration("1h30m15s").unwrap(),
+            Duration::hours(1) + Duration::minutes(30) + Duration::seconds(15)
+        );
+        assert_eq!(
+            parse_duration("2d12h").unwrap(),
+            Duration::days(2) + Duration::hours(12)
+        );
+        assert_eq!(
+            parse_duration("1.5s").unwrap(),
+            Duration::milliseconds(1500)
+        );
+        assert_eq!(
+            parse_duration("250ms").unwrap(),
+            Duration::milliseconds(250)
+        );
+        assert_eq!(parse_duration("0.25h").unwrap(), Duration::minutes(15));
+        assert_eq!(parse_duration(".5m").unwrap(), Duration::seconds(30));
+        assert_eq!(
+            parse_duration("1m0.1s").unwrap(),
+            Duration::milliseconds(60_100)
+        );
+        assert_eq!(parse_duration("-1m30s").unwrap(), Duration::seconds(-90));
+
+        assert!(parse_duration("30s1m").is_err()); // Units out of order
+        assert!(parse_duration("1m1m").is_err()); // Repeated unit
+        assert!(parse_duration("1.2.3s").is_err());
+        assert!(parse_duration("1h30").is_err()); // Missing unit
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("1 h").is_err());
+        assert!(parse_duration("-").is_err());
+        assert!(matches!(
+            parse_duration("106751991167301d"),
+            Err(GpxWrenchError::InvalidRange(_))
+        ));
+        assert!(parse_duration("1000000000000000000000000000000000000d").is_err());
+    }
+
     #[test]
     fn test_parse_timestamp() {
         assert_eq!(
@@ -258,30 +1143,193 @@ mod tests {
         assert!(parse_timestamp("2562047788015215:31:00").is_err());
     }
 
+    #[test]
+    fn test_parse_fractional_timestamp() {
+        assert_eq!(
+            parse_timestamp("01:02:03.250").unwrap(),
+            Duration::hours(1) + Duration::minutes(2) + Duration::milliseconds(3250)
+        );
+        assert_eq!(
+            parse_timestamp("00:01.5").unwrap(),
+            Duration::milliseconds(1500)
+        );
+        assert!(parse_timestamp("01.5:00").is_err());
+        assert!(parse_timestamp("00:-5").is_err());
+    }
+
     #[test]
     fn test_parse_range() {
         let range = parse_range("5s,10s").unwrap();
-        match range {
-            TrimRange::Duration { start, end } => {
-                assert_eq!(start, Duration::seconds(5));
-                assert_eq!(end, Duration::seconds(10));
-            }
-            _ => panic!("Expected Duration variant"),
-        }
+        assert_eq!(range.start, RangeBound::FromStart(Duration::seconds(5)));
+        assert_eq!(range.end, RangeBound::FromStart(Duration::seconds(10)));
 
         let range = parse_range("00:05,01:30").unwrap();
-        match range {
-            TrimRange::Timestamp { start, end } => {
-                assert_eq!(start, Duration::seconds(5));
-                assert_eq!(end, Duration::minutes(1) + Duration::seconds(30));
-            }
-            _ => panic!("Expected Timestamp variant"),
-        }
+        assert_eq!(range.start, RangeBound::FromStart(Duration::seconds(5)));
+        assert_eq!(
+            range.end,
+            RangeBound::FromStart(Duration::minutes(1) + Duration::seconds(30))
+        );
 
         assert!(parse_range("5s").is_err()); // Missing comma
         assert!(parse_range("5s,10s,15s").is_err()); // Too many parts
     }
 
+    #[test]
+    fn test_parse_range_open_and_end_relative() {
+        let range = parse_range("5m,").unwrap();
+        assert_eq!(range.start, RangeBound::FromStart(Duration::minutes(5)));
+        assert_eq!(range.end, RangeBound::Open);
+
+        let range = parse_range(",1h").unwrap();
+        assert_eq!(range.start, RangeBound::Open);
+        assert_eq!(range.end, RangeBound::FromStart(Duration::hours(1)));
+
+        let range = parse_range("0s,-2m").unwrap();
+        assert_eq!(range.start, RangeBound::FromStart(Duration::ZERO));
+        assert_eq!(range.end, RangeBound::FromEnd(Duration::minutes(2)));
+
+        let range = parse_range("-01:30,").unwrap();
+        assert_eq!(range.start, RangeBound::FromEnd(Duration::seconds(90)));
+
+        assert!(matches!(
+            parse_range(","),
+            Err(GpxWrenchError::InvalidRange(_))
+        ));
+        assert!(parse_range("--2m,").is_err());
+    }
+
+    #[test]
+    fn test_parse_ranges() {
+        let ranges = parse_ranges_with_tz("0s,30s; 5m,6m;10m,", None).unwrap();
+        assert_eq!(ranges.len(), 3);
+        assert_eq!(ranges[1].start, RangeBound::FromStart(Duration::minutes(5)));
+        assert_eq!(ranges[2].end, RangeBound::Open);
+
+        assert_eq!(parse_ranges_with_tz("5s,10s", None).unwrap().len(), 1);
+        assert!(parse_ranges_with_tz("0s,30s;", None).is_err());
+        assert!(parse_ranges_with_tz("0s,30s;5m", None).is_err());
+    }
+
+    #[test]
+    fn test_parse_distance() {
+        assert_eq!(parse_distance("500m").unwrap(), 500.0);
+        assert_eq!(parse_distance("12km").unwrap(), 12_000.0);
+        assert_eq!(parse_distance("1.5km").unwrap(), 1500.0);
+        assert_eq!(parse_distance("1mi").unwrap(), 1609.344);
+        assert!(matches!(
+            parse_distance("5s"),
+            Err(GpxWrenchError::InvalidRange(_))
+        ));
+        assert!(parse_distance("500").is_err());
+        assert!(parse_distance("-5m").is_err());
+        assert!(parse_distance("km").is_err());
+    }
+
+    #[test]
+    fn test_parse_distance_ranges() {
+        let ranges = parse_distance_ranges("500m,12km;,1km;-2km,").unwrap();
+        assert_eq!(
+            ranges[0],
+            DistanceRange {
+                start: DistanceBound::FromStart(500.0),
+                end: DistanceBound::FromStart(12_000.0)
+            }
+        );
+        assert_eq!(ranges[1].start, DistanceBound::Open);
+        assert_eq!(ranges[2].start, DistanceBound::FromEnd(2000.0));
+        assert!(!ranges[0].needs_total_distance());
+        assert!(ranges[2].needs_total_distance());
+        assert_eq!(ranges[2].resolve(5000.0), Interval::new(Some(3000.0), None));
+
+        assert!(parse_distance_ranges(",").is_err());
+        assert!(parse_distance_ranges("500m").is_err());
+    }
+
+    #[test]
+    fn test_trim_range_resolve() {
+        let min_time = OffsetDateTime::parse("2023-06-15T10:00:00Z", &Rfc3339).unwrap();
+        let max_time = min_time + Duration::minutes(10);
+
+        let range = parse_range("1m,-2m").unwrap();
+        assert!(range.needs_track_times());
+        assert_eq!(
+            range.resolve(Some((min_time, max_time))).unwrap(),
+            TimeInterval::new(
+                Some(min_time + Duration::minutes(1)),
+                Some(min_time + Duration::minutes(8))
+            )
+        );
+
+        let range = parse_range("2023-06-15T10:05:00Z,").unwrap();
+        assert!(!range.needs_track_times());
+        assert_eq!(
+            range.resolve(None).unwrap(),
+            TimeInterval::new(Some(min_time + Duration::minutes(5)), None)
+        );
+
+        let range = parse_range(",-99999999h").unwrap();
+        let early = OffsetDateTime::parse("0001-01-01T00:00:00Z", &Rfc3339).unwrap();
+        assert!(matches!(
+            range.resolve(Some((early, early))),
+            Err(GpxWrenchError::TimestampOverflow(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_range_absolute() {
+        let range = parse_range("2023-06-15T10:00:15Z, 2023-06-15T12:01:40+02:00").unwrap();
+        let (RangeBound::Absolute(start), RangeBound::Absolute(end)) = (range.start, range.end)
+        else {
+            panic!("Expected Absolute bounds");
+        };
+        assert_eq!(
+            start,
+            OffsetDateTime::parse("2023-06-15T10:00:15Z", &Rfc3339).unwrap()
+        );
+        assert_eq!(end - start, Duration::seconds(85));
+
+        let tz = UtcOffset::from_hms(2, 0, 0).unwrap();
+        let range =
+            parse_range_with_tz("2023-06-15T12:00:15,2023-06-15T12:01:40.5", Some(tz)).unwrap();
+        let (RangeBound::Absolute(start), RangeBound::Absolute(end)) = (range.start, range.end)
+        else {
+            panic!("Expected Absolute bounds");
+        };
+        assert_eq!(
+            start,
+            OffsetDateTime::parse("2023-06-15T10:00:15Z", &Rfc3339).unwrap()
+        );
+        assert_eq!(end - start, Duration::milliseconds(85_500));
+
+        assert!(matches!(
+            parse_range("2023-06-15T12:00:15,2023-06-15T12:01:40"),
+            Err(GpxWrenchError::InvalidRange(_))
+        ));
+        let range = parse_range("2023-06-15T10:00:15Z,-10s").unwrap();
+        assert_eq!(range.end, RangeBound::FromEnd(Duration::seconds(10)));
+        assert!(parse_range("2023-13-15T10:00:15Z,2023-06-15T10:01:40Z").is_err());
+    }
+
+    #[test]
+    fn test_parse_utc_offset() {
+        assert_eq!(parse_utc_offset("Z").unwrap(), UtcOffset::UTC);
+        assert_eq!(
+            parse_utc_offset("+02:00").unwrap(),
+            UtcOffset::from_hms(2, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse_utc_offset("-0530").unwrap(),
+            UtcOffset::from_hms(-5, -30, 0).unwrap()
+        );
+        assert_eq!(
+            parse_utc_offset("+09").unwrap(),
+            UtcOffset::from_hms(9, 0, 0).unwrap()
+        );
+        assert!(parse_utc_offset("02:00").is_err());
+        assert!(parse_utc_offset("+2").is_err());
+        assert!(parse_utc_offset("+02:60").is_err());
+    }
+
     #[test]
     fn test_haversine_distance() {
         // Distance between two points in San Francisco (approximately 1km apart)
@@ -349,16 +1397,8 @@ mod tests {
         .unwrap();
         let time2 = time1 + Duration::milliseconds(100);
 
-        let p1 = TrackPoint {
-            lat: 37.7749,
-            lon: -122.4194,
-            time: time1,
-        };
-        let p2 = TrackPoint {
-            lat: 37.7750,
-            lon: -122.4195,
-            time: time2,
-        };
+        let p1 = TrackPoint::new(37.7749, -122.4194, time1);
+        let p2 = TrackPoint::new(37.7750, -122.4195, time2);
 
         let speed = calculate_speed(&p1, &p2);
         assert!(speed > 0.0, "Speed should be positive for small time diff");
@@ -444,6 +1484,181 @@ mod tests {
         );
     }
 
+    fn two_activities_with_lunch_stop() -> Vec<TrackPoint> {
+        let mut points = Vec::new();
+        let mut lat = 37.7749;
+        for (minute, moving) in [
+            (0, false),
+            (1, true),
+            (2, true),
+            (3, true),
+            (4, true),
+            (5, false),
+            (35, false),
+            (36, true),
+            (37, true),
+            (38, true),
+            (39, true),
+            (40, false),
+        ] {
+            if moving {
+                lat += 0.01;
+            }
+            points.push(make_track_point(
+                lat,
+                -122.4194,
+                &format!("2023-01-01T10:{minute:02}:00Z"),
+            ));
+        }
+        points
+    }
+
+    #[test]
+    fn test_detect_activity_intervals_splits_at_long_pauses() {
+        let points = two_activities_with_lunch_stop();
+
+        let intervals = detect_activity_intervals(&points, 5.0, 0, 600).unwrap();
+        assert_eq!(
+            intervals,
+            vec![
+                (points[0].time, points[4].time),
+                (points[6].time, points[10].time)
+            ]
+        );
+
+        // A long enough maximum pause bridges the stop, like detect_activity_bounds.
+        let intervals = detect_activity_intervals(&points, 5.0, 30, 3600).unwrap();
+        assert_eq!(
+            intervals,
+            vec![detect_activity_bounds(&points, 5.0, 30).unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_detect_activity_intervals_without_activity() {
+        let points = vec![
+            make_track_point(37.7749, -122.4194, "2023-01-01T10:00:00Z"),
+            make_track_point(37.7749, -122.4194, "2023-01-01T10:00:05Z"),
+        ];
+
+        let intervals = detect_activity_intervals(&points, 5.0, 0, 60).unwrap();
+        assert_eq!(intervals, vec![(points[0].time, points[1].time)]);
+        assert!(matches!(
+            detect_activity_intervals(&points[..1], 5.0, 0, 60),
+            Err(GpxWrenchError::InsufficientData(_))
+        ));
+    }
+
+    /// Moves 100 m every 5 seconds (about 20 m/s), except where `speeds` gives another speed.
+    fn track_with_speeds(speeds: &[f64]) -> Vec<TrackPoint> {
+        let mut lat = 37.7749;
+        let mut points = vec![make_track_point(lat, -122.4194, "2023-01-01T10:00:00Z")];
+        for (idx, speed) in speeds.iter().enumerate() {
+            // 1 degree of latitude is about 111.2 km.
+            lat += speed * 5.0 / 111_195.0;
+            let seconds = (idx + 1) * 5;
+            points.push(make_track_point(
+                lat,
+                -122.4194,
+                &format!("2023-01-01T10:{:02}:{:02}Z", seconds / 60, seconds % 60),
+            ));
+        }
+        points
+    }
+
+    #[test]
+    fn test_activity_options_new_matches_detect_activity_bounds() {
+        let points = two_activities_with_lunch_stop();
+        assert_eq!(
+            detect_activity_bounds_with_options(&points, &ActivityOptions::new(5.0, 10)).unwrap(),
+            detect_activity_bounds(&points, 5.0, 10).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_detect_activity_min_run() {
+        let points = track_with_speeds(&[0.0, 20.0, 20.0, 0.0, 0.0]);
+
+        let mut options = ActivityOptions::new(5.0, 0);
+        let (start, end) = detect_activity_bounds_with_options(&points, &options).unwrap();
+        // Two active intervals are not enough by default, so the whole track is kept.
+        assert_eq!((start, end), (points[0].time, points[5].time));
+
+        options.min_run = 2;
+        let (start, end) = detect_activity_bounds_with_options(&points, &options).unwrap();
+        assert_eq!((start, end), (points[1].time, points[3].time));
+    }
+
+    #[test]
+    fn test_detect_activity_hysteresis() {
+        // A slowdown to 3 m/s in the middle of the activity.
+        let points = track_with_speeds(&[0.0, 10.0, 10.0, 10.0, 3.0, 3.0, 10.0, 10.0, 10.0, 0.0]);
+
+        let mut options = ActivityOptions::new(5.0, 0);
+        let intervals = detect_activity_intervals_with_options(&points, &options, 0).unwrap();
+        assert_eq!(intervals.len(), 2);
+
+        options.stop_threshold = 2.0;
+        let intervals = detect_activity_intervals_with_options(&points, &options, 0).unwrap();
+        assert_eq!(intervals, vec![(points[1].time, points[9].time)]);
+    }
+
+    #[test]
+    fn test_detect_activity_speed_filter() {
+        // A single GPS jump while standing still looks like three fast intervals.
+        let points = track_with_speeds(&[0.0, 0.0, 0.0, 30.0, 30.0, 30.0, 0.0, 0.0, 0.0, 0.0]);
+        let mut options = ActivityOptions::new(5.0, 0);
+        options.min_run = 3;
+        assert_ne!(
+            detect_activity_bounds_with_options(&points, &options).unwrap(),
+            (points[0].time, points[10].time)
+        );
+
+        options.speed_filter = SpeedFilter::Median(9);
+        assert_eq!(
+            detect_activity_bounds_with_options(&points, &options).unwrap(),
+            (points[0].time, points[10].time)
+        );
+    }
+
+    #[test]
+    fn test_activity_speeds_smoothing() {
+        let points = track_with_speeds(&[0.0, 10.0, 20.0, 90.0]);
+        let mean = activity_speeds(&points, SpeedFilter::Mean(3));
+        let median = activity_speeds(&points, SpeedFilter::Median(3));
+
+        assert_eq!(mean.len(), 4);
+        assert!((mean[1] - 10.0).abs() < 0.1, "{mean:?}");
+        assert!((mean[3] - 55.0).abs() < 0.1, "{mean:?}");
+        assert!((median[2] - 20.0).abs() < 0.1, "{median:?}");
+        assert!((median[0] - 5.0).abs() < 0.1, "{median:?}");
+    }
+
+    #[test]
+    fn test_analyze_activity() {
+        let points = track_with_speeds(&[0.0, 10.0, 10.0, 10.0, 0.0, 10.0, 10.0, 10.0, 0.0]);
+        let analysis = analyze_activity(&points, &ActivityOptions::new(5.0, 0)).unwrap();
+
+        assert_eq!(analysis.speeds.len(), 9);
+        assert_eq!(analysis.runs, vec![(1, 4), (5, 8)]);
+        assert!(!analysis.is_active(0));
+        assert!(analysis.is_active(4));
+        assert!(analysis.is_active(5));
+        assert!(!analysis.is_active(9));
+        assert_eq!(analysis.activities(&points, None).unwrap(), vec![(1, 8)]);
+        assert_eq!(
+            analysis.activities(&points, Some(0)).unwrap(),
+            vec![(1, 4), (5, 8)]
+        );
+
+        let idle = analyze_activity(
+            &track_with_speeds(&[0.0, 0.0]),
+            &ActivityOptions::new(5.0, 0),
+        )
+        .unwrap();
+        assert!(idle.activities(&points, None).unwrap().is_empty());
+    }
+
     #[test]
     fn test_detect_activity_bounds_keeps_long_active_tail() {
         let points = vec![
@@ -486,7 +1701,7 @@ mod tests {
         let points = vec![make_track_point(37.7749, -122.4194, "2023-01-01T10:00:00Z")];
 
         let result = detect_activity_bounds(&points, 5.0, 0);
-        assert!(result.is_err());
+        assert!(matches!(result, Err(GpxWrenchError::InsufficientData(_))));
         assert!(result.unwrap_err().to_string().contains("at least 2"));
     }
 
@@ -611,7 +1826,7 @@ mod tests {
 
         let result = detect_activity_bounds(&points, 5.0, i64::MAX as u64);
 
-        assert!(result.is_err());
+        assert!(matches!(result, Err(GpxWrenchError::TimestampOverflow(_))));
     }
 
     #[test]
@@ -622,26 +1837,10 @@ mod tests {
         )
         .unwrap();
         let points = vec![
-            TrackPoint {
-                lat: 37.7749,
-                lon: -122.4194,
-                time: final_time - Duration::seconds(15),
-            },
-            TrackPoint {
-                lat: 37.7759,
-                lon: -122.4194,
-                time: final_time - Duration::seconds(10),
-            },
-            TrackPoint {
-                lat: 37.7769,
-                lon: -122.4194,
-                time: final_time - Duration::seconds(5),
-            },
-            TrackPoint {
-                lat: 37.7779,
-                lon: -122.4194,
-                time: final_time,
-            },
+            TrackPoint::new(37.7749, -122.4194, final_time - Duration::seconds(15)),
+            TrackPoint::new(37.7759, -122.4194, final_time - Duration::seconds(10)),
+            TrackPoint::new(37.7769, -122.4194, final_time - Duration::seconds(5)),
+            TrackPoint::new(37.7779, -122.4194, final_time),
         ];
 
         let result = detect_activity_bounds(&points, 5.0, 0);
@@ -657,26 +1856,10 @@ mod tests {
         )
         .unwrap();
         let points = vec![
-            TrackPoint {
-                lat: 37.7749,
-                lon: -122.4194,
-                time: final_time - Duration::seconds(15),
-            },
-            TrackPoint {
-                lat: 37.7759,
-                lon: -122.4194,
-                time: final_time - Duration::seconds(10),
-            },
-            TrackPoint {
-                lat: 37.7769,
-                lon: -122.4194,
-                time: final_time - Duration::seconds(5),
-            },
-            TrackPoint {
-                lat: 37.7779,
-                lon: -122.4194,
-                time: final_time,
-            },
+            TrackPoint::new(37.7749, -122.4194, final_time - Duration::seconds(15)),
+            TrackPoint::new(37.7759, -122.4194, final_time - Duration::seconds(10)),
+            TrackPoint::new(37.7769, -122.4194, final_time - Duration::seconds(5)),
+            TrackPoint::new(37.7779, -122.4194, final_time),
         ];
 
         let result = detect_activity_bounds(&points, 5.0, 1);
@@ -1,10 +1,22 @@
 mod commands;
-mod gpxxml;
 
-use clap::{Parser, Subcommand};
-use commands::trim::trim_command;
-use commands::trim_to_activity::trim_to_activity_command;
-use std::error::Error;
+use clap::{ArgGroup, Parser, Subcommand};
+use commands::clean::clean_command;
+use commands::io_args::IoArgs;
+use commands::merge::{MergeInto, merge_command};
+use commands::privacy::privacy_command;
+use commands::remove_pauses::remove_pauses_command;
+use commands::resample::resample_command;
+use commands::simplify::{SimplifyMethod, simplify_command};
+use commands::smooth::{SmoothMethod, smooth_command};
+use commands::split::{SplitInto, split_command};
+use commands::stats::stats_command;
+use commands::trim::{TrimBy, trim_command};
+use commands::trim_to_activity::{
+    ActivityProfile, ActivityReport, ActivitySplit, DetectionArgs, trim_to_activity_command,
+};
+use gpxwrench::GpxWrenchError;
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(name = "gpxwrench", about = "A CLI tool for processing GPX files")]
@@ -15,27 +27,321 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    #[command(about = "Trim GPX track points using duration or timestamp ranges")]
+    #[command(about = "Trim GPX track points using time or distance ranges")]
     Trim {
-        #[arg(help = "Range specification: DUR1,DUR2 (e.g. 5s,10s) or TS1,TS2 (e.g. 00:05,01:30)")]
+        #[arg(
+            allow_hyphen_values = true,
+            help = "Range specification: DUR1,DUR2 (e.g. 5s,10s), TS1,TS2 (e.g. 00:05,01:30) or DATETIME1,DATETIME2 (e.g. 2023-06-15T10:00:15Z,2023-06-15T10:01:40Z); either side may be empty, and a leading - counts back from the last point (e.g. 0s,-2m). Separate several ranges with ; (e.g. '0s,30s;5m,6m'). With --by distance, sides are distances in m, km or mi (e.g. 500m,12km)"
+        )]
         range: String,
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = TrimBy::Time,
+            help = "Whether the range measures time or distance along the track"
+        )]
+        by: TrimBy,
+        #[arg(
+            long,
+            value_name = "OFFSET",
+            help = "UTC offset for range datetimes written without one, e.g. +02:00"
+        )]
+        tz: Option<String>,
+        #[arg(
+            long,
+            conflicts_with = "in_place",
+            help = "Write each kept range to its own file, numbered after the output file (e.g. out-1.gpx, out-2.gpx)"
+        )]
+        split_output: bool,
+        #[command(flatten)]
+        io: IoArgs,
     },
     #[command(about = "Trim GPX to detected activity period based on speed analysis")]
     TrimToActivity {
+        #[command(flatten)]
+        detection: DetectionArgs,
+        #[arg(
+            long,
+            value_name = "SECONDS",
+            help = "Keep every activity separately, splitting at idle gaps longer than SECONDS instead of keeping everything from the first to the last activity"
+        )]
+        max_pause: Option<u64>,
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = ActivitySplit::Segment,
+            requires = "max_pause",
+            help = "How separate activities are written with --max-pause"
+        )]
+        split: ActivitySplit,
+        #[arg(
+            long,
+            conflicts_with_all = ["explain", "in_place"],
+            help = "Print the detected activity and what would be removed instead of writing GPX"
+        )]
+        dry_run: bool,
+        #[arg(
+            long,
+            conflicts_with = "in_place",
+            help = "Write a CSV line per point with its speed and active/idle classification instead of GPX"
+        )]
+        explain: bool,
+        #[command(flatten)]
+        io: IoArgs,
+    },
+    #[command(
+        about = "Remove stationary stretches inside track segments, splitting them at each pause"
+    )]
+    RemovePauses {
         #[arg(
             long,
             short,
             default_value = "1.0",
-            help = "Minimum speed (m/s) to consider as activity"
+            help = "Speed (m/s) below which the track counts as stationary"
         )]
         speed_threshold: f64,
         #[arg(
             long,
-            short,
+            value_name = "SECONDS",
             default_value = "30",
-            help = "Buffer time (seconds) to add before/after detected activity"
+            help = "Shortest stationary stretch that is removed"
+        )]
+        min_pause: u64,
+        #[command(flatten)]
+        io: IoArgs,
+    },
+    #[command(
+        about = "Split tracks into parts at time gaps, day changes, distances or point counts",
+        group(ArgGroup::new("criteria").required(true).multiple(true))
+    )]
+    Split {
+        #[arg(
+            long,
+            value_name = "DURATION",
+            group = "criteria",
+            help = "Start a new part when consecutive points are more than DURATION apart, e.g. 10m"
+        )]
+        gap: Option<String>,
+        #[arg(
+            long,
+            group = "criteria",
+            help = "Start a new part when the calendar day changes"
+        )]
+        day: bool,
+        #[arg(
+            long,
+            value_name = "OFFSET",
+            requires = "day",
+            help = "UTC offset of the calendar days used by --day, e.g. +02:00 (default: UTC)"
+        )]
+        tz: Option<String>,
+        #[arg(
+            long,
+            value_name = "DISTANCE",
+            group = "criteria",
+            help = "Start a new part every DISTANCE along the track, in m, km or mi, e.g. 100km"
+        )]
+        distance: Option<String>,
+        #[arg(
+            long,
+            value_name = "N",
+            group = "criteria",
+            help = "Start a new part every N points"
+        )]
+        points: Option<usize>,
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = SplitInto::Segment,
+            help = "Whether each part becomes a track segment, a track or a file"
+        )]
+        into: SplitInto,
+        #[command(flatten)]
+        io: IoArgs,
+    },
+    #[command(about = "Combine the tracks of several GPX files in chronological order")]
+    Merge {
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = MergeInto::Segments,
+            help = "Whether tracks are kept, combined into one track with several segments, or into a single segment"
+        )]
+        into: MergeInto,
+        #[command(flatten)]
+        io: IoArgs,
+    },
+    #[command(about = "Reduce the number of track points while keeping the shape of the track")]
+    Simplify {
+        #[arg(
+            long,
+            short,
+            value_name = "METERS",
+            help = "Largest deviation from the original track that is acceptable"
+        )]
+        tolerance: f64,
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = SimplifyMethod::Rdp,
+            help = "Simplification algorithm"
+        )]
+        algorithm: SimplifyMethod,
+        #[arg(
+            long = "3d",
+            help = "Include elevation differences in distances and areas"
+        )]
+        three_d: bool,
+        #[command(flatten)]
+        io: IoArgs,
+    },
+    #[command(
+        about = "Remove GPS spikes, duplicates and points with invalid coordinates, reporting each to stderr"
+    )]
+    Clean {
+        #[arg(
+            long,
+            value_enum,
+            help = "Speed and acceleration limits for the kind of activity [default: drive]"
+        )]
+        profile: Option<ActivityProfile>,
+        #[arg(
+            long,
+            value_name = "M/S",
+            help = "Highest plausible speed, overriding the profile"
         )]
-        buffer: u64,
+        max_speed: Option<f64>,
+        #[arg(
+            long,
+            value_name = "M/S2",
+            help = "Highest plausible acceleration, overriding the profile"
+        )]
+        max_acceleration: Option<f64>,
+        #[command(flatten)]
+        io: IoArgs,
+    },
+    #[command(
+        about = "Hide sensitive locations, dates and device details before sharing a track",
+        group(ArgGroup::new("actions").required(true).multiple(true))
+    )]
+    Privacy {
+        #[arg(
+            long,
+            value_name = "LAT,LON,RADIUS",
+            group = "actions",
+            help = "Remove the track points, route points and waypoints within RADIUS (in m, km or mi) of a position, e.g. 47.3769,8.5417,300m; may be repeated"
+        )]
+        zone: Vec<String>,
+        #[arg(
+            long,
+            value_name = "FILE",
+            group = "actions",
+            help = "Remove the track points, route points and waypoints inside the polygons of FILE, one LAT,LON vertex per line and a blank line between polygons; may be repeated"
+        )]
+        zone_file: Vec<PathBuf>,
+        #[arg(
+            long,
+            value_name = "DISTANCE",
+            group = "actions",
+            help = "Remove the first and last DISTANCE of every track, in m, km or mi, e.g. 500m"
+        )]
+        trim_ends: Option<String>,
+        #[arg(
+            long,
+            value_name = "DATE",
+            group = "actions",
+            help = "Move all timestamps by whole days so that the earliest falls on DATE, e.g. 2000-01-01"
+        )]
+        shift_date: Option<String>,
+        #[arg(
+            long,
+            group = "actions",
+            help = "Remove author, copyright and device names, and replace the creator attribute"
+        )]
+        strip_metadata: bool,
+        #[command(flatten)]
+        io: IoArgs,
+    },
+    #[command(about = "Reduce GPS noise in track positions")]
+    Smooth {
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = SmoothMethod::Kalman,
+            help = "Smoothing method"
+        )]
+        method: SmoothMethod,
+        #[arg(
+            long,
+            value_name = "M/S2",
+            default_value = "1.0",
+            help = "Kalman: expected acceleration (m/s²); higher values follow the recorded positions more closely"
+        )]
+        process_noise: f64,
+        #[arg(
+            long,
+            value_name = "METERS",
+            default_value = "5.0",
+            help = "Kalman: accuracy of a recorded position, multiplied by the point's HDOP when it has one"
+        )]
+        measurement_noise: f64,
+        #[arg(
+            long,
+            help = "Kalman: skip the backward (RTS) pass, so that positions only depend on earlier points"
+        )]
+        forward_only: bool,
+        #[arg(
+            long,
+            value_name = "POINTS",
+            default_value = "5",
+            help = "Moving average: number of points averaged, rounded up to an odd number"
+        )]
+        window: usize,
+        #[command(flatten)]
+        io: IoArgs,
+    },
+    #[command(
+        about = "Interpolate track points at a fixed time or distance step",
+        group(ArgGroup::new("step").required(true))
+    )]
+    Resample {
+        #[arg(
+            long,
+            value_name = "DURATION",
+            group = "step",
+            help = "Place a point every DURATION, e.g. 1s or 250ms"
+        )]
+        every_time: Option<String>,
+        #[arg(
+            long,
+            value_name = "DISTANCE",
+            group = "step",
+            help = "Place a point every DISTANCE along the track, in m, km or mi, e.g. 10m"
+        )]
+        every_distance: Option<String>,
+        #[arg(
+            long,
+            value_name = "DURATION",
+            default_value = "60s",
+            help = "Do not interpolate between points more than DURATION apart; the segment is split there instead"
+        )]
+        max_gap: String,
+        #[command(flatten)]
+        io: IoArgs,
+    },
+    #[command(about = "Print distance, time, speed and elevation statistics")]
+    Stats {
+        #[arg(
+            long,
+            short,
+            default_value = "1.0",
+            help = "Minimum speed (m/s) counted as moving"
+        )]
+        speed_threshold: f64,
+        #[arg(long, help = "Print statistics as JSON")]
+        json: bool,
+        #[command(flatten)]
+        io: IoArgs,
     },
 }
 
@@ -44,16 +350,133 @@ fn main() {
 
     if let Err(e) = run(cli) {
         eprintln!("Error: {e}");
-        std::process::exit(1);
+        std::process::exit(exit_code(&e));
     }
 }
 
-fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+/// Maps an error to the process exit code documented in the README. Usage errors share code 2
+/// with the ones reported by clap.
+fn exit_code(error: &GpxWrenchError) -> i32 {
+    match error {
+        GpxWrenchError::File { source, .. } => exit_code(source),
+        GpxWrenchError::Io(_) => 1,
+        GpxWrenchError::InvalidArgument(_) => 2,
+        GpxWrenchError::Xml { .. } => 3,
+        GpxWrenchError::InvalidGpx(_) => 4,
+        GpxWrenchError::LimitExceeded(_) => 5,
+        GpxWrenchError::InvalidRange(_) => 6,
+        GpxWrenchError::TimestampOverflow(_) => 7,
+        GpxWrenchError::InsufficientData(_) => 8,
+        GpxWrenchError::Failures { .. } => 9,
+    }
+}
+
+fn run(cli: Cli) -> Result<(), GpxWrenchError> {
     match cli.command {
-        Commands::Trim { range } => trim_command(&range),
+        Commands::Trim {
+            range,
+            by,
+            tz,
+            split_output,
+            io,
+        } => trim_command(&range, by, tz.as_deref(), split_output, &io),
         Commands::TrimToActivity {
+            detection,
+            max_pause,
+            split,
+            dry_run,
+            explain,
+            io,
+        } => {
+            let report = if dry_run {
+                ActivityReport::DryRun
+            } else if explain {
+                ActivityReport::Explain
+            } else {
+                ActivityReport::None
+            };
+            trim_to_activity_command(&detection, max_pause, split, report, &io)
+        }
+        Commands::RemovePauses {
+            speed_threshold,
+            min_pause,
+            io,
+        } => remove_pauses_command(speed_threshold, min_pause, &io),
+        Commands::Split {
+            gap,
+            day,
+            tz,
+            distance,
+            points,
+            into,
+            io,
+        } => split_command(
+            gap.as_deref(),
+            day,
+            tz.as_deref(),
+            distance.as_deref(),
+            points,
+            into,
+            &io,
+        ),
+        Commands::Merge { into, io } => merge_command(into, &io),
+        Commands::Simplify {
+            tolerance,
+            algorithm,
+            three_d,
+            io,
+        } => simplify_command(tolerance, algorithm, three_d, &io),
+        Commands::Clean {
+            profile,
+            max_speed,
+            max_acceleration,
+            io,
+        } => clean_command(profile, max_speed, max_acceleration, &io),
+        Commands::Privacy {
+            zone,
+            zone_file,
+            trim_ends,
+            shift_date,
+            strip_metadata,
+            io,
+        } => privacy_command(
+            &zone,
+            &zone_file,
+            trim_ends.as_deref(),
+            shift_date.as_deref(),
+            strip_metadata,
+            &io,
+        ),
+        Commands::Smooth {
+            method,
+            process_noise,
+            measurement_noise,
+            forward_only,
+            window,
+            io,
+        } => smooth_command(
+            method,
+            process_noise,
+            measurement_noise,
+            forward_only,
+            window,
+            &io,
+        ),
+        Commands::Resample {
+            every_time,
+            every_distance,
+            max_gap,
+            io,
+        } => resample_command(
+            every_time.as_deref(),
+            every_distance.as_deref(),
+            &max_gap,
+            &io,
+        ),
+        Commands::Stats {
             speed_threshold,
-            buffer,
-        } => trim_to_activity_command(speed_threshold, buffer),
+            json,
+            io,
+        } => stats_command(speed_threshold, json, &io),
     }
 }
@@ -0,0 +1,441 @@
+//! Combining several GPX documents into one.
+
+use crate::document::{Bounds, GpxDocument, Metadata, Track, Waypoint, XmlElement, XmlNode};
+use time::OffsetDateTime;
+
+/// How [`merge_documents`] combines the tracks of its inputs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MergeMode {
+    /// Keep every track, ordered by start time.
+    Tracks,
+    /// One track containing the segments of every input track, ordered by start time.
+    #[default]
+    Segments,
+    /// One track with a single segment containing the points of every segment, ordered by the
+    /// start time of their segments.
+    Continuous,
+}
+
+/// A period recorded by two inputs, given by their index in the list passed to
+/// [`merge_documents`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeOverlap {
+    pub first: usize,
+    pub second: usize,
+    pub start: OffsetDateTime,
+    pub end: OffsetDateTime,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeResult {
+    pub document: GpxDocument,
+    /// Pairs of inputs whose track points cover overlapping periods.
+    pub overlaps: Vec<TimeOverlap>,
+    /// Number of waypoints dropped because an earlier input had the same one.
+    pub duplicate_waypoints: usize,
+}
+
+/// Combines `documents` into a single document.
+///
+/// Creator, comments and metadata are taken from the first document, with the metadata bounds
+/// recomputed from all points of the result. Attributes of the `<gpx>` elements, such as
+/// namespace prefixes used by extensions, are combined; for attributes that occur in several
+/// documents, the first value wins. A namespace prefix that a later document binds to a
+/// different namespace, e.g. `gpxtpx` for two versions of the Garmin TrackPointExtension, is
+/// declared again on that document's extension elements that use it, so they keep their
+/// namespace. Routes and document extensions are concatenated, and a waypoint is dropped if an
+/// earlier one has the same position and name.
+///
+/// Tracks and segments are ordered by their earliest point time, keeping those without any
+/// times last in input order.
+pub fn merge_documents(documents: Vec<GpxDocument>, mode: MergeMode) -> MergeResult {
+    let overlaps = find_overlaps(&documents);
+    let mut documents = documents.into_iter();
+    let mut merged = documents.next().unwrap_or_default();
+    let mut tracks = std::mem::take(&mut merged.tracks);
+    let first_waypoints = std::mem::take(&mut merged.waypoints);
+    let mut duplicate_waypoints = append_unique_waypoints(&mut merged.waypoints, first_waypoints);
+
+    for mut document in documents {
+        let mut conflicts = Vec::new();
+        for (key, value) in std::mem::take(&mut document.attributes) {
+            match merged
+                .attributes
+                .iter()
+                .find(|(existing, _)| *existing == key)
+            {
+                None => merged.attributes.push((key, value)),
+                Some((_, existing)) if key.starts_with("xmlns:") && *existing != value => {
+                    conflicts.push((key, value));
+                }
+                Some(_) => {}
+            }
+        }
+        if !conflicts.is_empty() {
+            redeclare_namespaces(&mut document, &conflicts);
+        }
+        duplicate_waypoints += append_unique_waypoints(&mut merged.waypoints, document.waypoints);
+        merged.routes.extend(document.routes);
+        tracks.extend(document.tracks);
+        merged.extensions.extend(document.extensions);
+        merged.unknown.extend(document.unknown);
+    }
+
+    sort_chronologically(&mut tracks, |track| {
+        earliest_time(track.segments.iter().flat_map(|segment| &segment.points))
+    });
+    merged.tracks = match mode {
+        MergeMode::Tracks => tracks,
+        MergeMode::Segments | MergeMode::Continuous => {
+            let mut combined: Option<Track> = None;
+            let mut segments = Vec::new();
+            for mut track in tracks {
+                segments.append(&mut track.segments);
+                combined.get_or_insert(track);
+            }
+            sort_chronologically(&mut segments, |segment| earliest_time(&segment.points));
+            if mode == MergeMode::Continuous && !segments.is_empty() {
+                for mut segment in segments.split_off(1) {
+                    segments[0].points.append(&mut segment.points);
+                }
+            }
+            // The combined track keeps the name and other details of the earliest track.
+            combined
+                .map(|track| Track { segments, ..track })
+                .into_iter()
+                .collect()
+        }
+    };
+
+    let bounds = document_bounds(&merged);
+    match (&mut merged.metadata, bounds) {
+        (Some(metadata), bounds) => metadata.bounds = bounds,
+        (None, Some(bounds)) => {
+            merged.metadata = Some(Metadata {
+                bounds: Some(bounds),
+                ..Metadata::default()
+            });
+        }
+        (None, None) => {}
+    }
+
+    MergeResult {
+        document: merged,
+        overlaps,
+        duplicate_waypoints,
+    }
+}
+
+/// Adds the `xmlns:prefix` declarations in `declarations` to the outermost extension and unknown
+/// elements of `document` that use the prefix, so that they no longer depend on the `<gpx>`
+/// element. The metadata is left alone, since only the first document's is kept.
+fn redeclare_namespaces(document: &mut GpxDocument, declarations: &[(String, String)]) {
+    let mut node_lists = vec![&mut document.extensions, &mut document.unknown];
+    node_lists.extend(document.waypoints.iter_mut().flat_map(point_node_lists));
+    for route in &mut document.routes {
+        node_lists.extend([&mut route.extensions, &mut route.unknown]);
+        node_lists.extend(route.links.iter_mut().map(|link| &mut link.unknown));
+        node_lists.extend(route.points.iter_mut().flat_map(point_node_lists));
+    }
+    for track in &mut document.tracks {
+        node_lists.extend([&mut track.extensions, &mut track.unknown]);
+        node_lists.extend(track.links.iter_mut().map(|link| &mut link.unknown));
+        for segment in &mut track.segments {
+            node_lists.extend([&mut segment.extensions, &mut segment.unknown]);
+            node_lists.extend(segment.points.iter_mut().flat_map(point_node_lists));
+        }
+    }
+
+    for node in node_lists.into_iter().flatten() {
+        let XmlNode::Element(element) = node else {
+            continue;
+        };
+        for (key, namespace) in declarations {
+            let prefix = &key["xmlns:".len()..];
+            if element.attribute(key).is_none() && uses_prefix(element, prefix) {
+                element
+                    .attributes
+                    .insert(0, (key.clone(), namespace.clone()));
+            }
+        }
+    }
+}
+
+fn point_node_lists(point: &mut Waypoint) -> impl Iterator<Item = &mut Vec<XmlNode>> {
+    [&mut point.extensions, &mut point.unknown]
+        .into_iter()
+        .chain(point.links.iter_mut().map(|link| &mut link.unknown))
+}
+
+/// Returns whether `element` or one of its descendants has a name or attribute with `prefix`.
+fn uses_prefix(element: &XmlElement, prefix: &str) -> bool {
+    let has_prefix = |name: &str| name.split_once(':').is_some_and(|(p, _)| p == prefix);
+    has_prefix(&element.name)
+        || element.attributes.iter().any(|(key, _)| has_prefix(key))
+        || element
+            .children
+            .iter()
+            .any(|child| matches!(child, XmlNode::Element(child) if uses_prefix(child, prefix)))
+}
+
+/// Appends the waypoints of `new` to `waypoints`, dropping duplicates, and returns how many
+/// were dropped.
+fn append_unique_waypoints(waypoints: &mut Vec<Waypoint>, new: Vec<Waypoint>) -> usize {
+    let mut duplicates = 0;
+    for waypoint in new {
+        let is_duplicate = waypoints.iter().any(|kept| {
+            kept.lat == waypoint.lat && kept.lon == waypoint.lon && kept.name == waypoint.name
+        });
+        if is_duplicate {
+            duplicates += 1;
+        } else {
+            waypoints.push(waypoint);
+        }
+    }
+    duplicates
+}
+
+fn sort_chronologically<T>(items: &mut [T], time: impl Fn(&T) -> Option<OffsetDateTime>) {
+    // `None` sorts before `Some`, so untimed items are moved to the end explicitly.
+    items.sort_by_key(|item| {
+        let time = time(item);
+        (time.is_none(), time)
+    });
+}
+
+fn earliest_time<'a>(points: impl IntoIterator<Item = &'a Waypoint>) -> Option<OffsetDateTime> {
+    points.into_iter().filter_map(|point| point.time).min()
+}
+
+fn track_time_range(document: &GpxDocument) -> Option<(OffsetDateTime, OffsetDateTime)> {
+    let times = document
+        .tracks
+        .iter()
+        .flat_map(|track| &track.segments)
+        .flat_map(|segment| &segment.points)
+        .filter_map(|point| point.time);
+    times.fold(None, |range, time| match range {
+        Some((start, end)) => Some((time.min(start), time.max(end))),
+        None => Some((time, time)),
+    })
+}
+
+fn find_overlaps(documents: &[GpxDocument]) -> Vec<TimeOverlap> {
+    let ranges: Vec<_> = documents.iter().map(track_time_range).collect();
+    let mut overlaps = Vec::new();
+    for (first, first_range) in ranges.iter().enumerate() {
+        for (second, second_range) in ranges.iter().enumerate().skip(first + 1) {
+            if let (Some((start_a, end_a)), Some((start_b, end_b))) = (first_range, second_range) {
+                let start = *start_a.max(start_b);
+                let end = *end_a.min(end_b);
+                if start < end {
+                    overlaps.push(TimeOverlap {
+                        first,
+                        second,
+                        start,
+                        end,
+                    });
+                }
+            }
+        }
+    }
+    overlaps
+}
+
+/// Returns the bounds of all waypoints, route points and track points of `document`.
+pub fn document_bounds(document: &GpxDocument) -> Option<Bounds> {
+    let route_points = document.routes.iter().flat_map(|route| &route.points);
+    let track_points = document
+        .tracks
+        .iter()
+        .flat_map(|track| &track.segments)
+        .flat_map(|segment| &segment.points);
+    document
+        .waypoints
+        .iter()
+        .chain(route_points)
+        .chain(track_points)
+        .fold(None, |bounds, point| {
+            let mut bounds = bounds.unwrap_or(Bounds {
+                min_lat: point.lat,
+                min_lon: point.lon,
+                max_lat: point.lat,
+                max_lon: point.lon,
+            });
+            bounds.min_lat = bounds.min_lat.min(point.lat);
+            bounds.min_lon = bounds.min_lon.min(point.lon);
+            bounds.max_lat = bounds.max_lat.max(point.lat);
+            bounds.max_lon = bounds.max_lon.max(point.lon);
+            Some(bounds)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::TrackSegment;
+
+    fn time(minute: i64) -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(1_686_823_200 + minute * 60).unwrap()
+    }
+
+    fn point(lat: f64, minute: i64) -> Waypoint {
+        Waypoint {
+            time: Some(time(minute)),
+            ..Waypoint::new(lat, 10.0)
+        }
+    }
+
+    fn document(name: &str, minutes: &[&[i64]]) -> GpxDocument {
+        let segments = minutes
+            .iter()
+            .map(|minutes| TrackSegment {
+                points: minutes
+                    .iter()
+                    .map(|&minute| point(minute as f64 / 100.0, minute))
+                    .collect(),
+                ..TrackSegment::default()
+            })
+            .collect();
+        GpxDocument {
+            creator: name.to_string(),
+            tracks: vec![Track {
+                name: Some(name.to_string()),
+                segments,
+                ..Track::default()
+            }],
+            ..GpxDocument::default()
+        }
+    }
+
+    fn segment_starts(document: &GpxDocument) -> Vec<Vec<i64>> {
+        document
+            .tracks
+            .iter()
+            .map(|track| {
+                track
+                    .segments
+                    .iter()
+                    .map(|segment| (segment.points[0].time.unwrap() - time(0)).whole_minutes())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_modes_order_chronologically() {
+        let documents = || {
+            vec![
+                document("later", &[&[30, 31], &[50, 51]]),
+                document("earlier", &[&[0, 1, 2]]),
+            ]
+        };
+
+        let tracks = merge_documents(documents(), MergeMode::Tracks).document;
+        assert_eq!(tracks.creator, "later");
+        assert_eq!(segment_starts(&tracks), vec![vec![0], vec![30, 50]]);
+
+        let segments = merge_documents(documents(), MergeMode::Segments).document;
+        assert_eq!(segment_starts(&segments), vec![vec![0, 30, 50]]);
+        assert_eq!(segments.tracks[0].name.as_deref(), Some("earlier"));
+
+        let continuous = merge_documents(documents(), MergeMode::Continuous).document;
+        assert_eq!(segment_starts(&continuous), vec![vec![0]]);
+        assert_eq!(continuous.tracks[0].segments[0].points.len(), 7);
+    }
+
+    #[test]
+    fn test_merge_reports_overlaps() {
+        let result = merge_documents(
+            vec![
+                document("a", &[&[0, 10]]),
+                document("b", &[&[20, 30]]),
+                document("c", &[&[5, 25]]),
+            ],
+            MergeMode::Segments,
+        );
+        assert_eq!(
+            result.overlaps,
+            vec![
+                TimeOverlap {
+                    first: 0,
+                    second: 2,
+                    start: time(5),
+                    end: time(10),
+                },
+                TimeOverlap {
+                    first: 1,
+                    second: 2,
+                    start: time(20),
+                    end: time(25),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_deduplicates_waypoints_and_recomputes_bounds() {
+        let mut first = document("a", &[&[0, 10]]);
+        first.metadata = Some(Metadata {
+            name: Some("Ride".to_string()),
+            bounds: Some(Bounds::default()),
+            ..Metadata::default()
+        });
+        first.attributes = vec![("xmlns:a".to_string(), "urn:a".to_string())];
+        let summit = Waypoint {
+            name: Some("Summit".to_string()),
+            ..Waypoint::new(1.0, 11.0)
+        };
+        first.waypoints = vec![summit.clone()];
+        let mut second = document("b", &[&[20]]);
+        second.attributes = vec![
+            ("xmlns:a".to_string(), "urn:other".to_string()),
+            ("xmlns:b".to_string(), "urn:b".to_string()),
+        ];
+        // The second document's `a:` extensions belong to its own namespace.
+        let mut extension = XmlElement::new("b:ext");
+        extension
+            .children
+            .push(XmlNode::Element(XmlElement::new("a:hr")));
+        second.tracks[0].segments[0].points[0].extensions = vec![
+            XmlNode::Element(extension),
+            XmlNode::Element(XmlElement::new("b:cad")),
+        ];
+        second.waypoints = vec![
+            summit,
+            Waypoint {
+                name: Some("Hut".to_string()),
+                ..Waypoint::new(1.0, 11.0)
+            },
+        ];
+
+        let result = merge_documents(vec![first, second], MergeMode::Segments);
+        let document = result.document;
+        assert_eq!(result.duplicate_waypoints, 1);
+        assert_eq!(document.waypoints.len(), 2);
+        assert_eq!(
+            document.attributes,
+            [
+                ("xmlns:a".to_string(), "urn:a".to_string()),
+                ("xmlns:b".to_string(), "urn:b".to_string())
+            ]
+        );
+        let extensions = &document.tracks[0].segments[1].points[0].extensions;
+        let XmlNode::Element(extension) = &extensions[0] else {
+            panic!("{extensions:?}");
+        };
+        assert_eq!(extension.attribute("xmlns:a"), Some("urn:other"));
+        assert_eq!(extensions[1], XmlNode::Element(XmlElement::new("b:cad")));
+        let metadata = document.metadata.unwrap();
+        assert_eq!(metadata.name.as_deref(), Some("Ride"));
+        assert_eq!(
+            metadata.bounds,
+            Some(Bounds {
+                min_lat: 0.0,
+                min_lon: 10.0,
+                max_lat: 1.0,
+                max_lon: 11.0,
+            })
+        );
+    }
+}
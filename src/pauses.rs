@@ -0,0 +1,128 @@
+use crate::{TrackPoint, calculate_speed};
+use time::Duration;
+
+/// Returns the indices of the first and last point of every pause in a track segment.
+///
+/// A pause is a maximal stretch of consecutive intervals slower than `speed_threshold` (m/s)
+/// that lasts at least `min_pause`. Its first point is where the track stopped moving and its
+/// last point is where it started moving again.
+pub fn find_pauses(
+    track_points: &[TrackPoint],
+    speed_threshold: f64,
+    min_pause: Duration,
+) -> Vec<(usize, usize)> {
+    let mut pauses = Vec::new();
+    let mut pause_start = None;
+    for (idx, points) in track_points.windows(2).enumerate() {
+        if calculate_speed(&points[0], &points[1]) < speed_threshold {
+            pause_start.get_or_insert(idx);
+            continue;
+        }
+        if let Some(start) = pause_start.take() {
+            pauses.push((start, idx));
+        }
+    }
+    if let Some(start) = pause_start {
+        pauses.push((start, track_points.len() - 1));
+    }
+
+    pauses.retain(|&(start, end)| track_points[end].time - track_points[start].time >= min_pause);
+    pauses
+}
+
+/// Assigns every point of a track segment to the moving part it belongs to once the pauses
+/// found by [`find_pauses`] are removed, or `None` if it is removed.
+///
+/// The points where a pause starts and ends are kept as the last point of one part and the
+/// first point of the next, so that every part still covers the whole time it was moving. Of a
+/// pause at the start or end of the segment only the point next to the moving part is kept.
+pub fn pause_groups(
+    track_points: &[TrackPoint],
+    speed_threshold: f64,
+    min_pause: Duration,
+) -> Vec<Option<usize>> {
+    let pauses = find_pauses(track_points, speed_threshold, min_pause);
+    let mut groups = vec![None; track_points.len()];
+    let mut group = 0;
+    let mut part_start = 0;
+    for &(start, end) in &pauses {
+        if start > 0 {
+            groups[part_start..=start].fill(Some(group));
+            group += 1;
+        }
+        part_start = end;
+    }
+    if pauses
+        .last()
+        .is_none_or(|&(_, end)| end + 1 < track_points.len())
+    {
+        groups[part_start..].fill(Some(group));
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::OffsetDateTime;
+
+    /// One point per minute; `moving` tells whether the track moved about 1 km since the
+    /// previous point.
+    fn track(moving: &[bool]) -> Vec<TrackPoint> {
+        let start = OffsetDateTime::from_unix_timestamp(1_686_823_200).unwrap();
+        let mut lat = 37.7749;
+        let mut points = vec![TrackPoint::new(lat, -122.4194, start)];
+        for (minute, &moving) in moving.iter().enumerate() {
+            if moving {
+                lat += 0.01;
+            }
+            let time = start + Duration::minutes(minute as i64 + 1);
+            points.push(TrackPoint::new(lat, -122.4194, time));
+        }
+        points
+    }
+
+    #[test]
+    fn test_find_pauses() {
+        let points = track(&[true, false, false, true, false, true]);
+
+        assert_eq!(
+            find_pauses(&points, 1.0, Duration::ZERO),
+            vec![(1, 3), (4, 5)]
+        );
+        assert_eq!(
+            find_pauses(&points, 1.0, Duration::minutes(2)),
+            vec![(1, 3)]
+        );
+        assert!(find_pauses(&points[..2], 1.0, Duration::ZERO).is_empty());
+    }
+
+    #[test]
+    fn test_pause_groups_splits_at_pauses() {
+        let points = track(&[true, false, false, true, true]);
+        assert_eq!(
+            pause_groups(&points, 1.0, Duration::minutes(1)),
+            vec![Some(0), Some(0), None, Some(1), Some(1), Some(1)]
+        );
+        assert_eq!(
+            pause_groups(&points, 1.0, Duration::minutes(3)),
+            vec![Some(0); 6]
+        );
+    }
+
+    #[test]
+    fn test_pause_groups_at_segment_ends() {
+        let points = track(&[false, false, true, true, false, false]);
+        assert_eq!(
+            pause_groups(&points, 1.0, Duration::minutes(1)),
+            vec![None, None, Some(0), Some(0), Some(0), None, None]
+        );
+
+        let stationary = track(&[false, false]);
+        assert_eq!(
+            pause_groups(&stationary, 1.0, Duration::minutes(1)),
+            vec![None, None, None]
+        );
+        assert!(pause_groups(&[], 1.0, Duration::ZERO).is_empty());
+    }
+}
@@ -0,0 +1,627 @@
+//! Hiding sensitive locations before a track is shared: removing the points near them, cutting
+//! the ends of tracks, moving timestamps to another date and stripping author and device details.
+
+use crate::gpxxml::{
+    DistanceAccumulator, PointContext, PointSelector, TrackPointVisitor, visit_track_points,
+};
+use crate::{GpxWrenchError, haversine_distance, parse_distance};
+use quick_xml::Writer;
+use quick_xml::events::{BytesStart, BytesText, Event};
+use std::io::{BufRead, Write};
+use time::format_description::well_known::{Iso8601, Rfc3339};
+use time::{Date, Duration, OffsetDateTime, UtcOffset};
+
+/// An area whose track points [`PrivacySelector`] removes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Zone {
+    /// Everything within `radius` meters of a position.
+    Circle { lat: f64, lon: f64, radius: f64 },
+    /// The inside of a polygon with these `(lat, lon)` vertices. Edges are straight lines in
+    /// latitude and longitude, which is close enough for areas of a few kilometers.
+    Polygon(Vec<(f64, f64)>),
+}
+
+impl Zone {
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        match self {
+            Zone::Circle {
+                lat: center_lat,
+                lon: center_lon,
+                radius,
+            } => haversine_distance(*center_lat, *center_lon, lat, lon) <= *radius,
+            Zone::Polygon(vertices) => {
+                // Counts the edges crossed by a ray from the position towards the east.
+                let mut inside = false;
+                let mut previous = vertices.last();
+                for vertex in vertices {
+                    let (lat1, lon1) = *vertex;
+                    if let Some(&(lat2, lon2)) = previous
+                        && (lat1 > lat) != (lat2 > lat)
+                        && lon < lon1 + (lat - lat1) * (lon2 - lon1) / (lat2 - lat1)
+                    {
+                        inside = !inside;
+                    }
+                    previous = Some(vertex);
+                }
+                inside
+            }
+        }
+    }
+}
+
+/// Parses a `LAT,LON` position, rejecting coordinates out of range.
+fn parse_position(s: &str) -> Option<(f64, f64)> {
+    let (lat, lon) = s.split_once(',')?;
+    let lat: f64 = lat.trim().parse().ok()?;
+    let lon: f64 = lon.trim().parse().ok()?;
+    ((-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon)).then_some((lat, lon))
+}
+
+/// Parses a circle written as `LAT,LON,RADIUS`, such as `47.3769,8.5417,300m`. The radius is a
+/// distance in m, km or mi.
+pub fn parse_circle(s: &str) -> Result<Zone, GpxWrenchError> {
+    let invalid = || {
+        GpxWrenchError::InvalidArgument(format!(
+            "Invalid zone {s:?}; expected LAT,LON,RADIUS such as 47.3769,8.5417,300m"
+        ))
+    };
+    let (position, radius) = s.rsplit_once(',').ok_or_else(invalid)?;
+    let (lat, lon) = parse_position(position).ok_or_else(invalid)?;
+    let radius = parse_distance(radius.trim()).map_err(|_| invalid())?;
+    Ok(Zone::Circle { lat, lon, radius })
+}
+
+/// Parses the polygons of a zone file: one `LAT,LON` vertex per line, with blank lines between
+/// polygons. Text after `#` is a comment.
+pub fn parse_polygons(text: &str) -> Result<Vec<Zone>, GpxWrenchError> {
+    let mut polygons = Vec::new();
+    let mut vertices = Vec::new();
+    let mut first_line = 0;
+    let mut finish = |vertices: &mut Vec<(f64, f64)>, line: usize| {
+        if vertices.is_empty() {
+            return Ok(());
+        }
+        if vertices.len() < 3 {
+            return Err(GpxWrenchError::InvalidArgument(format!(
+                "Polygon starting on line {line} has fewer than 3 vertices"
+            )));
+        }
+        polygons.push(Zone::Polygon(std::mem::take(vertices)));
+        Ok(())
+    };
+
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            finish(&mut vertices, first_line)?;
+            continue;
+        }
+        if vertices.is_empty() {
+            first_line = idx + 1;
+        }
+        vertices.push(parse_position(line).ok_or_else(|| {
+            GpxWrenchError::InvalidArgument(format!(
+                "Invalid polygon vertex {line:?} on line {}; expected LAT,LON",
+                idx + 1
+            ))
+        })?);
+    }
+    finish(&mut vertices, first_line)?;
+    Ok(polygons)
+}
+
+/// Drops the track points inside any of the zones, and with [`PrivacySelector::with_trimmed_ends`]
+/// the ones near the start or end of their track. Points without a valid position are dropped as
+/// well, since they cannot be shown to be outside.
+///
+/// The kept points on either side of a removed stretch belong to different groups, so that no
+/// line is drawn across it.
+#[derive(Debug)]
+pub struct PrivacySelector {
+    zones: Vec<Zone>,
+    trimmed_ends: f64,
+    track_distances: Vec<f64>,
+    track: Option<usize>,
+    accumulator: DistanceAccumulator,
+    group: usize,
+    dropped: bool,
+    /// Number of track points seen so far.
+    pub total: usize,
+    /// Number of track points dropped so far.
+    pub removed: usize,
+}
+
+impl PrivacySelector {
+    pub fn new(zones: Vec<Zone>) -> Self {
+        PrivacySelector {
+            zones,
+            trimmed_ends: 0.0,
+            track_distances: Vec::new(),
+            track: None,
+            accumulator: DistanceAccumulator::default(),
+            group: 0,
+            dropped: false,
+            total: 0,
+            removed: 0,
+        }
+    }
+
+    /// Also drops the points within `meters` along the track from its first or last point.
+    /// `track_distances` holds the length of every `<trk>`, as returned by
+    /// [`crate::gpxxml::find_track_distances`].
+    pub fn with_trimmed_ends(mut self, meters: f64, track_distances: Vec<f64>) -> Self {
+        self.trimmed_ends = meters;
+        self.track_distances = track_distances;
+        self
+    }
+
+    fn is_hidden(&mut self, point: &PointContext) -> bool {
+        let (Some(lat), Some(lon)) = (point.lat, point.lon) else {
+            return true;
+        };
+        let distance = self.accumulator.add(point).unwrap_or_default();
+        if self.zones.iter().any(|zone| zone.contains(lat, lon)) {
+            return true;
+        }
+        let track_distance = self
+            .track
+            .and_then(|track| self.track_distances.get(track))
+            .copied()
+            .unwrap_or_default();
+        self.trimmed_ends > 0.0
+            && (distance < self.trimmed_ends || distance > track_distance - self.trimmed_ends)
+    }
+}
+
+impl PointSelector for PrivacySelector {
+    fn track_start(&mut self) {
+        self.track = Some(self.track.map_or(0, |track| track + 1));
+        self.accumulator = DistanceAccumulator::default();
+    }
+
+    fn segment_start(&mut self) {
+        self.accumulator.segment_start();
+    }
+
+    fn select(&mut self, point: &PointContext) -> Option<usize> {
+        self.total += 1;
+        if self.is_hidden(point) {
+            self.removed += 1;
+            self.dropped = true;
+            return None;
+        }
+        if std::mem::take(&mut self.dropped) {
+            self.group += 1;
+        }
+        Some(self.group)
+    }
+}
+
+/// How [`anonymize_xml`] rewrites a document.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Anonymization {
+    /// Waypoints and route points inside any of these zones, or without a valid position, are
+    /// removed. Track points are left to [`PrivacySelector`].
+    pub zones: Vec<Zone>,
+    /// Whether to remove the `<bounds>` of the document, which would still cover removed points.
+    pub strip_bounds: bool,
+    /// Added to every `<time>` in the document.
+    pub time_shift: Option<Duration>,
+    /// Whether to remove the author, copyright holder, `<src>` device names and the `creator`
+    /// application.
+    pub strip_metadata: bool,
+}
+
+/// Returns the whole number of days that moves `earliest` to `date`, both taken in UTC.
+pub fn days_until(earliest: OffsetDateTime, date: Date) -> Duration {
+    date.midnight() - earliest.to_offset(UtcOffset::UTC).date().midnight()
+}
+
+/// Parses the text of a `<time>` element.
+fn parse_time(text: &str) -> Option<OffsetDateTime> {
+    OffsetDateTime::parse(text.trim(), &Iso8601::DEFAULT).ok()
+}
+
+/// Collects the text of the `<time>` elements anywhere in a document.
+#[derive(Default)]
+struct TimeElementText {
+    depth: usize,
+    /// The depth of the open `<time>` element and its text so far.
+    current: Option<(usize, String)>,
+}
+
+impl TimeElementText {
+    /// Follows `event` and returns the text of a `<time>` element it closes.
+    fn event(&mut self, event: &Event<'_>) -> Option<String> {
+        match event {
+            Event::Start(element) => {
+                self.depth += 1;
+                if self.current.is_none() && element.local_name().as_ref() == b"time" {
+                    self.current = Some((self.depth, String::new()));
+                }
+            }
+            Event::Text(text) => {
+                if let Some((_, current)) = &mut self.current {
+                    current.push_str(&String::from_utf8_lossy(text));
+                }
+            }
+            Event::End(_) => {
+                self.depth = self.depth.saturating_sub(1);
+                if self
+                    .current
+                    .as_ref()
+                    .is_some_and(|(depth, _)| *depth > self.depth)
+                {
+                    return self.current.take().map(|(_, text)| text);
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+struct EarliestTimeVisitor {
+    times: TimeElementText,
+    earliest: Option<OffsetDateTime>,
+}
+
+impl TrackPointVisitor for EarliestTimeVisitor {
+    fn event(&mut self, event: &Event<'_>) -> Result<(), GpxWrenchError> {
+        if let Some(time) = self.times.event(event).as_deref().and_then(parse_time) {
+            self.earliest = Some(self.earliest.map_or(time, |earliest| earliest.min(time)));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the earliest `<time>` anywhere in `input`, including metadata and waypoints.
+pub fn find_earliest_time<R: BufRead>(input: R) -> Result<Option<OffsetDateTime>, GpxWrenchError> {
+    let mut visitor = EarliestTimeVisitor {
+        times: TimeElementText::default(),
+        earliest: None,
+    };
+    visit_track_points(input, &mut visitor)?;
+    Ok(visitor.earliest)
+}
+
+/// Returns the `lat` and `lon` attributes of a waypoint or route point.
+fn point_position(element: &BytesStart<'_>) -> Option<(f64, f64)> {
+    let mut position = (None, None);
+    for attr in element.attributes().flatten() {
+        let value = std::str::from_utf8(&attr.value)
+            .ok()
+            .and_then(|value| value.trim().parse::<f64>().ok())
+            .filter(|value| value.is_finite());
+        match attr.key.as_ref() {
+            b"lat" => position.0 = value,
+            b"lon" => position.1 = value,
+            _ => {}
+        }
+    }
+    position.0.zip(position.1)
+}
+
+/// Returns whether `strip_metadata` removes the element `name` inside `parent`.
+fn is_identifying(parent: Option<&[u8]>, name: &[u8]) -> bool {
+    match parent {
+        Some(b"metadata") => matches!(name, b"author" | b"copyright"),
+        // GPX 1.0 keeps the author directly in <gpx>.
+        Some(b"gpx") => matches!(name, b"author" | b"email"),
+        Some(b"wpt" | b"rtept" | b"trkpt" | b"rte" | b"trk") => name == b"src",
+        _ => false,
+    }
+}
+
+/// Copies `input` to `output`, shifting times and removing hidden waypoints, bounds and
+/// identifying metadata as chosen by `options` and leaving every other event untouched.
+///
+/// Shifted times are written in RFC 3339 with their original UTC offset. Times that cannot be
+/// parsed are removed when shifting, since they might still give the real date away.
+pub fn anonymize_xml<R: BufRead, W: Write>(
+    input: R,
+    options: &Anonymization,
+    output: W,
+) -> Result<(), GpxWrenchError> {
+    let mut visitor = AnonymizingVisitor {
+        writer: Writer::new(output),
+        options,
+        path: Vec::new(),
+        skip_depth: None,
+        times: TimeElementText::default(),
+        time_events: None,
+        pending_whitespace: None,
+    };
+    visit_track_points(input, &mut visitor)?;
+    if let Some(whitespace) = visitor.pending_whitespace {
+        visitor.writer.write_event(whitespace)?;
+    }
+    Ok(())
+}
+
+struct AnonymizingVisitor<'a, W: Write> {
+    writer: Writer<W>,
+    options: &'a Anonymization,
+    /// Local names of the open elements.
+    path: Vec<Vec<u8>>,
+    /// The depth of the element being removed; everything up to its end is dropped.
+    skip_depth: Option<usize>,
+    times: TimeElementText,
+    /// The events of the `<time>` element being shifted, written once its text is complete.
+    time_events: Option<Vec<Event<'static>>>,
+    /// Whitespace that is only written once the next event is known, so that removed elements
+    /// do not leave blank lines behind.
+    pending_whitespace: Option<Event<'static>>,
+}
+
+impl<W: Write> AnonymizingVisitor<'_, W> {
+    /// Returns whether the element `element` inside `parent` is removed with everything in it.
+    fn is_removed(&self, parent: Option<&[u8]>, element: &BytesStart<'_>) -> bool {
+        let name = element.local_name();
+        match (parent, name.as_ref()) {
+            (Some(b"gpx"), b"wpt") | (Some(b"rte"), b"rtept") if !self.options.zones.is_empty() => {
+                point_position(element).is_none_or(|(lat, lon)| {
+                    self.options
+                        .zones
+                        .iter()
+                        .any(|zone| zone.contains(lat, lon))
+                })
+            }
+            // GPX 1.0 keeps the bounds directly in <gpx>.
+            (Some(b"metadata" | b"gpx"), b"bounds") => self.options.strip_bounds,
+            (parent, name) => self.options.strip_metadata && is_identifying(parent, name),
+        }
+    }
+
+    fn write(&mut self, event: Event<'_>) -> Result<(), GpxWrenchError> {
+        if let Some(whitespace) = self.pending_whitespace.take() {
+            self.writer.write_event(whitespace)?;
+        }
+        self.writer.write_event(event)?;
+        Ok(())
+    }
+
+    /// Writes the buffered `<time>` element with its text moved by `shift`, or drops it when the
+    /// text is not a time.
+    fn write_shifted_time(&mut self, text: &str, shift: Duration) -> Result<(), GpxWrenchError> {
+        let mut events = self.time_events.take().unwrap_or_default();
+        let Some(time) = parse_time(text) else {
+            self.pending_whitespace = None;
+            return Ok(());
+        };
+        let shifted = time
+            .checked_add(shift)
+            .ok_or_else(|| {
+                GpxWrenchError::TimestampOverflow(format!("Shifting {text:?} overflows"))
+            })?
+            .format(&Rfc3339)
+            .map_err(|e| GpxWrenchError::TimestampOverflow(format!("Cannot format time: {e}")))?;
+        let end = events.pop();
+        let start = events.into_iter().next();
+        let text = Event::Text(BytesText::new(&shifted));
+        for event in start.into_iter().chain([text]).chain(end) {
+            self.write(event)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> TrackPointVisitor for AnonymizingVisitor<'_, W> {
+    fn event(&mut self, event: &Event<'_>) -> Result<(), GpxWrenchError> {
+        let parent = self.path.last().cloned();
+        let time_text = self.times.event(event);
+        match event {
+            Event::Start(element) => self.path.push(element.local_name().as_ref().to_vec()),
+            Event::End(_) => {
+                self.path.pop();
+            }
+            _ => {}
+        }
+
+        if let Some(depth) = self.skip_depth {
+            if self.path.len() < depth {
+                self.skip_depth = None;
+            }
+            return Ok(());
+        }
+        if let (Some(shift), Some(events)) = (self.options.time_shift, &mut self.time_events) {
+            events.push(event.clone().into_owned());
+            if let Some(text) = time_text {
+                self.write_shifted_time(&text, shift)?;
+            }
+            return Ok(());
+        }
+
+        match event {
+            Event::Start(element) | Event::Empty(element) => {
+                let name = element.local_name();
+                if self.is_removed(parent.as_deref(), element) {
+                    self.pending_whitespace = None;
+                    if matches!(event, Event::Start(_)) {
+                        self.skip_depth = Some(self.path.len());
+                    }
+                    return Ok(());
+                }
+                if self.options.time_shift.is_some()
+                    && matches!(event, Event::Start(_))
+                    && name.as_ref() == b"time"
+                {
+                    self.time_events = Some(vec![event.clone().into_owned()]);
+                    return Ok(());
+                }
+                if self.options.strip_metadata && parent.is_none() && name.as_ref() == b"gpx" {
+                    let root = without_creator(element)?;
+                    return self.write(match event {
+                        Event::Empty(_) => Event::Empty(root),
+                        _ => Event::Start(root),
+                    });
+                }
+                self.write(event.borrow())
+            }
+            Event::Text(text) if text.iter().all(u8::is_ascii_whitespace) => {
+                if let Some(whitespace) = self.pending_whitespace.take() {
+                    self.writer.write_event(whitespace)?;
+                }
+                self.pending_whitespace = Some(event.clone().into_owned());
+                Ok(())
+            }
+            _ => self.write(event.borrow()),
+        }
+    }
+}
+
+/// Returns a copy of the `<gpx>` start tag with `creator`, which usually names the recording
+/// device or app, replaced by gpxwrench.
+fn without_creator(element: &BytesStart<'_>) -> Result<BytesStart<'static>, GpxWrenchError> {
+    let mut root = BytesStart::new(String::from_utf8_lossy(element.name().as_ref()).into_owned());
+    for attr in element.attributes() {
+        let attr =
+            attr.map_err(|e| GpxWrenchError::InvalidGpx(format!("Invalid <gpx> attribute: {e}")))?;
+        match attr.key.as_ref() {
+            b"creator" => root.push_attribute(("creator", "gpxwrench")),
+            _ => root.push_attribute(attr),
+        }
+    }
+    Ok(root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpxxml::{GroupSplit, filter_xml_by_selector, find_track_distances};
+
+    const GPX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="Garmin Edge 530" xmlns="http://www.topografix.com/GPX/1/1">
+  <metadata>
+    <author>
+      <name>Jane Doe</name>
+    </author>
+    <time>2023-06-15T09:59:00Z</time>
+    <bounds minlat="47.0" minlon="8.0" maxlat="47.1" maxlon="8.0"/>
+  </metadata>
+  <wpt lat="47.0000" lon="8.0000"><name>Home</name></wpt>
+  <wpt lat="47.1000" lon="8.0000"><name>Summit</name></wpt>
+  <trk>
+    <src>Garmin Edge 530</src>
+    <trkseg>
+      <trkpt lat="47.0000" lon="8.0000"><time>2023-06-15T10:00:00Z</time></trkpt>
+      <trkpt lat="47.0010" lon="8.0000"><time>2023-06-15T10:00:10Z</time></trkpt>
+      <trkpt lat="47.0020" lon="8.0000"><time>2023-06-15T12:00:20+02:00</time></trkpt>
+      <trkpt lat="47.0030" lon="8.0000"><time>2023-06-15T10:00:30Z</time></trkpt>
+      <trkpt lat="47.0040" lon="8.0000"><time>2023-06-15T10:00:40Z</time></trkpt>
+    </trkseg>
+  </trk>
+</gpx>
+"#;
+
+    fn kept_latitudes(selector: &mut PrivacySelector) -> Vec<Vec<f64>> {
+        let mut output = Vec::new();
+        filter_xml_by_selector(GPX.as_bytes(), selector, GroupSplit::Segment, &mut output).unwrap();
+        let gpx = gpx::read(output.as_slice()).unwrap();
+        gpx.tracks[0]
+            .segments
+            .iter()
+            .map(|segment| segment.points.iter().map(|p| p.point().y()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_zone_contains() {
+        let circle = parse_circle("47.0, 8.0, 100m").unwrap();
+        assert!(circle.contains(47.0008, 8.0));
+        assert!(!circle.contains(47.0010, 8.0));
+
+        let square = Zone::Polygon(vec![(0.0, 0.0), (0.0, 1.0), (1.0, 1.0), (1.0, 0.0)]);
+        assert!(square.contains(0.5, 0.5));
+        assert!(!square.contains(0.5, 1.5));
+        assert!(!square.contains(-0.5, 0.5));
+
+        assert!(parse_circle("47.0,8.0").is_err());
+        assert!(parse_circle("91.0,8.0,100m").is_err());
+        assert!(parse_circle("47.0,8.0,100").is_err());
+    }
+
+    #[test]
+    fn test_parse_polygons() {
+        let polygons =
+            parse_polygons("# home\n0,0\n0,1\n1,1  # corner\n\n\n2,2\n2,3\n3,3\n3,2\n").unwrap();
+        assert_eq!(
+            polygons,
+            [
+                Zone::Polygon(vec![(0.0, 0.0), (0.0, 1.0), (1.0, 1.0)]),
+                Zone::Polygon(vec![(2.0, 2.0), (2.0, 3.0), (3.0, 3.0), (3.0, 2.0)]),
+            ]
+        );
+        assert!(parse_polygons("0,0\n0,1\n\n1,1\n").is_err());
+        assert!(parse_polygons("0,0\n0;1\n1,1\n").is_err());
+    }
+
+    #[test]
+    fn test_privacy_selector() {
+        // Removing the middle point splits the segment around it.
+        let zone = parse_circle("47.0020,8.0,50m").unwrap();
+        let mut selector = PrivacySelector::new(vec![zone]);
+        assert_eq!(
+            kept_latitudes(&mut selector),
+            [vec![47.0, 47.001], vec![47.003, 47.004]]
+        );
+        assert_eq!((selector.removed, selector.total), (1, 5));
+
+        // The points are about 111 m apart.
+        let distances = find_track_distances(GPX.as_bytes()).unwrap();
+        let mut selector = PrivacySelector::new(Vec::new()).with_trimmed_ends(150.0, distances);
+        assert_eq!(kept_latitudes(&mut selector), [vec![47.002]]);
+
+        // A point with a NaN latitude has no position: it is dropped and does not stop the
+        // distance along the track from growing, so the end is still trimmed.
+        let gpx = GPX.replace(r#"lat="47.0010""#, r#"lat="NaN""#);
+        let distances = find_track_distances(gpx.as_bytes()).unwrap();
+        let mut selector = PrivacySelector::new(Vec::new()).with_trimmed_ends(150.0, distances);
+        let mut output = Vec::new();
+        filter_xml_by_selector(
+            gpx.as_bytes(),
+            &mut selector,
+            GroupSplit::Segment,
+            &mut output,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(!output.contains("NaN"));
+        assert!(output.contains(r#"lat="47.0020""#));
+        assert!(!output.contains(r#"lat="47.0040""#));
+        assert_eq!((selector.removed, selector.total), (4, 5));
+    }
+
+    #[test]
+    fn test_anonymize_xml() {
+        let earliest = find_earliest_time(GPX.as_bytes()).unwrap().unwrap();
+        assert_eq!(earliest.unix_timestamp(), 1_686_823_140);
+        let date = Date::from_calendar_date(2000, time::Month::January, 1).unwrap();
+        let options = Anonymization {
+            zones: vec![parse_circle("47.0,8.0,50m").unwrap()],
+            strip_bounds: true,
+            time_shift: Some(days_until(earliest, date)),
+            strip_metadata: true,
+        };
+
+        let mut output = Vec::new();
+        anonymize_xml(GPX.as_bytes(), &options, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(r#"creator="gpxwrench""#));
+        assert!(!output.contains("Jane Doe"));
+        assert!(!output.contains("Garmin"));
+        assert!(output.contains(
+            "  <metadata>\n    <time>2000-01-01T09:59:00Z</time>\n  </metadata>\n  <wpt lat=\"47.1000\""
+        ));
+        assert!(!output.contains("Home"));
+        assert!(output.contains("  <trk>\n    <trkseg>\n"));
+        assert!(output.contains("<time>2000-01-01T10:00:10Z</time>"));
+        assert!(output.contains("<time>2000-01-01T12:00:20+02:00</time>"));
+        assert!(!output.contains("2023"));
+
+        let unchanged = Anonymization::default();
+        let mut output = Vec::new();
+        anonymize_xml(GPX.as_bytes(), &unchanged, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), GPX);
+    }
+}
@@ -0,0 +1,488 @@
+//! Resampling tracks to evenly spaced points.
+
+use crate::document::{GpxDocument, TrackSegment, Waypoint, XmlElement, XmlNode};
+use crate::error::GpxWrenchError;
+use crate::sensors::SensorData;
+use crate::{EARTH_RADIUS, MAX_TRACK_POINTS, TrackPoint, haversine_distance};
+use time::{Duration, OffsetDateTime};
+
+const TRACK_POINT_EXTENSION_NAMESPACE: &str =
+    "http://www.garmin.com/xmlschemas/TrackPointExtension/v2";
+const POWER_EXTENSION_NAMESPACE: &str = "http://www.garmin.com/xmlschemas/PowerExtension/v1";
+
+/// Spacing of the points produced by [`resample`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResampleStep {
+    /// A point every given duration, starting at the first point.
+    Time(Duration),
+    /// A point every given number of meters along the track, starting at the first point.
+    Distance(f64),
+}
+
+/// Resamples a track segment to points spaced evenly by `step`.
+///
+/// The segment is first cut wherever consecutive points are more than `max_gap` apart or time
+/// runs backwards, since nothing is known about the track in between. Each part is resampled on
+/// its own and returned as a separate list; parts too short to hold a step yield a single point.
+/// New points are interpolated along the great circle between the recorded points around them,
+/// with their time, elevation and sensor readings interpolated linearly. The last recorded point
+/// of a part is only kept if it falls on a step, so that all points are the same step apart.
+pub fn resample(
+    track_points: &[TrackPoint],
+    step: ResampleStep,
+    max_gap: Duration,
+) -> Result<Vec<Vec<TrackPoint>>, GpxWrenchError> {
+    let mut parts = Vec::new();
+    let mut part_start = 0;
+    for idx in 1..=track_points.len() {
+        let is_gap = idx == track_points.len() || {
+            let interval = track_points[idx].time - track_points[idx - 1].time;
+            interval > max_gap || interval.is_negative()
+        };
+        if is_gap {
+            parts.push(resample_part(&track_points[part_start..idx], step)?);
+            part_start = idx;
+        }
+    }
+    Ok(parts)
+}
+
+fn resample_part(
+    track_points: &[TrackPoint],
+    step: ResampleStep,
+) -> Result<Vec<TrackPoint>, GpxWrenchError> {
+    // Positions of the recorded points along the part, in the unit of the step.
+    let positions: Vec<f64> = match step {
+        ResampleStep::Time(_) => track_points
+            .iter()
+            .map(|point| (point.time - track_points[0].time).as_seconds_f64())
+            .collect(),
+        ResampleStep::Distance(_) => std::iter::once(0.0)
+            .chain(track_points.windows(2).scan(0.0, |total, points| {
+                *total +=
+                    haversine_distance(points[0].lat, points[0].lon, points[1].lat, points[1].lon);
+                Some(*total)
+            }))
+            .collect(),
+    };
+    let step_size = match step {
+        ResampleStep::Time(duration) => duration.as_seconds_f64(),
+        ResampleStep::Distance(meters) => meters,
+    };
+    let Some(&length) = positions.last() else {
+        return Ok(Vec::new());
+    };
+    let steps = match step {
+        // Whole durations are counted exactly, so that a point falling on a step is never lost
+        // to rounding.
+        ResampleStep::Time(duration) => {
+            let span = track_points[track_points.len() - 1].time - track_points[0].time;
+            (span.whole_nanoseconds() / duration.whole_nanoseconds()) as f64
+        }
+        ResampleStep::Distance(meters) => (length / meters).floor(),
+    };
+    let count = steps + 1.0;
+    if count > MAX_TRACK_POINTS as f64 {
+        return Err(GpxWrenchError::LimitExceeded(format!(
+            "Resampling would exceed the maximum supported track point count of {MAX_TRACK_POINTS}"
+        )));
+    }
+
+    let mut resampled = Vec::with_capacity(count as usize);
+    let mut idx = 0;
+    for sample in 0..count as usize {
+        let position = sample as f64 * step_size;
+        while idx + 2 < positions.len() && positions[idx + 1] <= position {
+            idx += 1;
+        }
+        let (a, b) = (
+            &track_points[idx],
+            &track_points[(idx + 1).min(positions.len() - 1)],
+        );
+        let span = positions[(idx + 1).min(positions.len() - 1)] - positions[idx];
+        let fraction = if span > 0.0 {
+            ((position - positions[idx]) / span).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let mut point = interpolate(a, b, fraction);
+        if let ResampleStep::Time(duration) = step {
+            // Step from the start in whole durations, avoiding rounding in the times.
+            point.time = track_points[0].time + duration * sample as u32;
+        }
+        resampled.push(point);
+    }
+    Ok(resampled)
+}
+
+/// Returns the point at `fraction` of the way from `a` to `b` along the great circle through
+/// them. Only the position, time, elevation and sensor readings are carried over.
+fn interpolate(a: &TrackPoint, b: &TrackPoint, fraction: f64) -> TrackPoint {
+    let (lat, lon) = great_circle_point(a, b, fraction);
+    let time = a.time + (b.time - a.time) * fraction;
+    let mut point = TrackPoint::new(lat, lon, time);
+    point.ele = match (a.ele, b.ele) {
+        (Some(a), Some(b)) => Some(a + (b - a) * fraction),
+        (a, _) if fraction < 0.5 => a,
+        (_, b) => b,
+    };
+    point.sensors = match (&a.sensors, &b.sensors) {
+        (None, None) => None,
+        (a_sensors, b_sensors) => {
+            let empty = SensorData::default();
+            let sensors = SensorData::interpolate(
+                a_sensors.as_ref().unwrap_or(&empty),
+                b_sensors.as_ref().unwrap_or(&empty),
+                fraction,
+            );
+            (!sensors.is_empty()).then_some(sensors)
+        }
+    };
+    point
+}
+
+fn great_circle_point(a: &TrackPoint, b: &TrackPoint, fraction: f64) -> (f64, f64) {
+    let angle = haversine_distance(a.lat, a.lon, b.lat, b.lon) / EARTH_RADIUS;
+    if angle < 1e-9 {
+        // Too close for the spherical formula to be stable; a straight line is just as good.
+        return (
+            a.lat + (b.lat - a.lat) * fraction,
+            a.lon + (b.lon - a.lon) * fraction,
+        );
+    }
+    let weight_a = ((1.0 - fraction) * angle).sin() / angle.sin();
+    let weight_b = (fraction * angle).sin() / angle.sin();
+    let to_vector = |point: &TrackPoint| {
+        let (lat, lon) = (point.lat.to_radians(), point.lon.to_radians());
+        [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()]
+    };
+    let (va, vb) = (to_vector(a), to_vector(b));
+    let [x, y, z] = [0, 1, 2].map(|axis| weight_a * va[axis] + weight_b * vb[axis]);
+    (z.atan2(x.hypot(y)).to_degrees(), y.atan2(x).to_degrees())
+}
+
+/// Resamples every track segment of `document` with [`resample`], replacing it by one segment
+/// per part. Points without a time cannot be placed and are dropped, as are the names,
+/// descriptions and extensions of the recorded points; interpolated sensor readings are written
+/// as a Garmin `TrackPointExtension` (and a Garmin `PowerInWatts` element).
+///
+/// A segment without any timestamps, such as a planned route, is resampled by distance along
+/// its positions and written without times. Resampling it by time fails with
+/// [`GpxWrenchError::InsufficientData`].
+pub fn resample_document(
+    document: &mut GpxDocument,
+    step: ResampleStep,
+    max_gap: Duration,
+) -> Result<(), GpxWrenchError> {
+    let mut total_points = 0;
+    for track in &mut document.tracks {
+        let mut segments = Vec::new();
+        for segment in std::mem::take(&mut track.segments) {
+            let untimed = segment.points.iter().all(|point| point.time.is_none());
+            let points: Vec<TrackPoint> = match step {
+                ResampleStep::Time(_) if untimed && !segment.points.is_empty() => {
+                    return Err(GpxWrenchError::InsufficientData(
+                        "Cannot resample a track segment without timestamps by time; use a distance step such as 10m".into(),
+                    ));
+                }
+                // Without times, all points share one placeholder time and form a single part.
+                ResampleStep::Distance(_) if untimed => segment
+                    .points
+                    .iter()
+                    .map(|waypoint| track_point(waypoint, OffsetDateTime::UNIX_EPOCH))
+                    .collect(),
+                _ => segment
+                    .points
+                    .iter()
+                    .filter_map(|waypoint| Some(track_point(waypoint, waypoint.time?)))
+                    .collect(),
+            };
+            let parts = resample(&points, step, max_gap)?;
+            total_points += parts.iter().map(Vec::len).sum::<usize>();
+            if total_points > MAX_TRACK_POINTS {
+                return Err(GpxWrenchError::LimitExceeded(format!(
+                    "Resampling would exceed the maximum supported track point count of {MAX_TRACK_POINTS}"
+                )));
+            }
+
+            // The first part keeps the segment's own extensions and other content.
+            let mut template = Some(TrackSegment {
+                points: Vec::new(),
+                ..segment
+            });
+            if parts.is_empty() {
+                segments.extend(template);
+                continue;
+            }
+            for part in parts {
+                segments.push(TrackSegment {
+                    points: part
+                        .iter()
+                        .map(|point| Waypoint {
+                            time: (!untimed).then_some(point.time),
+                            ..waypoint(point)
+                        })
+                        .collect(),
+                    ..template.take().unwrap_or_default()
+                });
+            }
+        }
+        track.segments = segments;
+    }
+    Ok(())
+}
+
+fn track_point(waypoint: &Waypoint, time: OffsetDateTime) -> TrackPoint {
+    let mut point = TrackPoint::new(waypoint.lat, waypoint.lon, time);
+    point.ele = waypoint.ele;
+    let mut sensors = SensorData::default();
+    read_sensors(&waypoint.extensions, &mut sensors);
+    point.sensors = (!sensors.is_empty()).then_some(sensors);
+    point
+}
+
+/// Reads sensor values from the leaf elements of a point's extensions, at any depth.
+fn read_sensors(nodes: &[XmlNode], sensors: &mut SensorData) {
+    for node in nodes {
+        if let XmlNode::Element(element) = node {
+            let has_elements = element
+                .children
+                .iter()
+                .any(|child| matches!(child, XmlNode::Element(_)));
+            if has_elements {
+                read_sensors(&element.children, sensors);
+            } else {
+                sensors.set_field(element.local_name(), &element.text());
+            }
+        }
+    }
+}
+
+/// Converts a resampled point back to a GPX track point, rounding interpolated coordinates to
+/// about a centimeter.
+fn waypoint(point: &TrackPoint) -> Waypoint {
+    let round = |value: f64, decimals: i32| {
+        let factor = 10f64.powi(decimals);
+        (value * factor).round() / factor
+    };
+    Waypoint {
+        ele: point.ele.map(|ele| round(ele, 2)),
+        time: Some(point.time),
+        extensions: point
+            .sensors
+            .as_ref()
+            .map(sensor_extensions)
+            .unwrap_or_default(),
+        ..Waypoint::new(round(point.lat, 7), round(point.lon, 7))
+    }
+}
+
+fn sensor_extensions(sensors: &SensorData) -> Vec<XmlNode> {
+    let text_element = |name: &str, value: Option<String>| {
+        value.map(|value| {
+            XmlNode::Element(XmlElement {
+                children: vec![XmlNode::Text(value)],
+                ..XmlElement::new(name)
+            })
+        })
+    };
+    let round = |value: f64| ((value * 100.0).round() / 100.0).to_string();
+
+    // The namespace is declared on the element itself, so the document root stays untouched.
+    let fields: Vec<XmlNode> = [
+        text_element("atemp", sensors.temperature.map(round)),
+        text_element("wtemp", sensors.water_temperature.map(round)),
+        text_element("depth", sensors.depth.map(round)),
+        text_element("hr", sensors.heart_rate.map(|value| value.to_string())),
+        text_element("cad", sensors.cadence.map(|value| value.to_string())),
+        text_element("speed", sensors.speed.map(round)),
+        text_element("course", sensors.course.map(round)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    let mut extensions = Vec::new();
+    if !fields.is_empty() {
+        extensions.push(XmlNode::Element(XmlElement {
+            name: "TrackPointExtension".to_string(),
+            attributes: vec![(
+                "xmlns".to_string(),
+                TRACK_POINT_EXTENSION_NAMESPACE.to_string(),
+            )],
+            children: fields,
+        }));
+    }
+    // TrackPointExtension has no power field, so it goes into Garmin's PowerExtension.
+    if let Some(power) = sensors.power {
+        extensions.push(XmlNode::Element(XmlElement {
+            name: "pwr:PowerInWatts".to_string(),
+            attributes: vec![(
+                "xmlns:pwr".to_string(),
+                POWER_EXTENSION_NAMESPACE.to_string(),
+            )],
+            children: vec![XmlNode::Text(power.to_string())],
+        }));
+    }
+    extensions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::OffsetDateTime;
+
+    fn time(seconds: i64) -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(1_686_823_200 + seconds).unwrap()
+    }
+
+    /// Points along the equator, `(seconds, meters east)`.
+    fn track(points: &[(i64, f64)]) -> Vec<TrackPoint> {
+        points
+            .iter()
+            .map(|&(seconds, meters)| {
+                let lon = (meters / EARTH_RADIUS).to_degrees();
+                TrackPoint::new(0.0, lon, time(seconds))
+            })
+            .collect()
+    }
+
+    fn meters_east(point: &TrackPoint) -> f64 {
+        point.lon.to_radians() * EARTH_RADIUS
+    }
+
+    #[test]
+    fn test_resample_by_time() {
+        let mut points = track(&[(0, 0.0), (4, 40.0), (5, 60.0)]);
+        points[0].ele = Some(100.0);
+        points[1].ele = Some(108.0);
+
+        let parts = resample(
+            &points,
+            ResampleStep::Time(Duration::seconds(2)),
+            Duration::minutes(1),
+        )
+        .unwrap();
+        assert_eq!(parts.len(), 1);
+        let times: Vec<_> = parts[0].iter().map(|point| point.time).collect();
+        assert_eq!(times, [time(0), time(2), time(4)]);
+        assert!((meters_east(&parts[0][1]) - 20.0).abs() < 1e-6);
+        assert_eq!(parts[0][1].ele, Some(104.0));
+    }
+
+    #[test]
+    fn test_resample_by_distance() {
+        let points = track(&[(0, 0.0), (10, 10.0), (30, 50.0)]);
+        let parts = resample(&points, ResampleStep::Distance(20.0), Duration::minutes(1)).unwrap();
+        let distances: Vec<_> = parts[0].iter().map(meters_east).collect();
+        assert_eq!(distances.len(), 3);
+        for (distance, expected) in distances.iter().zip([0.0, 20.0, 40.0]) {
+            assert!((distance - expected).abs() < 1e-6, "{distances:?}");
+        }
+        assert_eq!(parts[0][1].time, time(15));
+    }
+
+    #[test]
+    fn test_resample_does_not_bridge_gaps() {
+        let points = track(&[(0, 0.0), (2, 2.0), (600, 500.0), (602, 502.0)]);
+        let parts = resample(
+            &points,
+            ResampleStep::Time(Duration::seconds(1)),
+            Duration::minutes(1),
+        )
+        .unwrap();
+        let times: Vec<Vec<_>> = parts
+            .iter()
+            .map(|part| part.iter().map(|point| point.time).collect())
+            .collect();
+        assert_eq!(
+            times,
+            [
+                vec![time(0), time(1), time(2)],
+                vec![time(600), time(601), time(602)]
+            ]
+        );
+        assert!(
+            resample(&[], ResampleStep::Distance(1.0), Duration::ZERO)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_great_circle_point() {
+        let a = TrackPoint::new(60.0, -30.0, time(0));
+        let b = TrackPoint::new(60.0, 30.0, time(0));
+        let (lat, lon) = great_circle_point(&a, &b, 0.5);
+        // The great circle between two points on a parallel bends towards the pole.
+        assert!(lat > 60.0, "{lat}");
+        assert!(lon.abs() < 1e-9, "{lon}");
+    }
+
+    #[test]
+    fn test_resample_document_interpolates_sensors() {
+        let gpx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1"
+     xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v1"
+     xmlns:pwr="http://www.garmin.com/xmlschemas/PowerExtension/v1">
+  <trk><trkseg>
+    <trkpt lat="0" lon="0"><time>2023-06-15T10:00:00Z</time>
+      <extensions><gpxtpx:TrackPointExtension><gpxtpx:hr>100</gpxtpx:hr></gpxtpx:TrackPointExtension><pwr:PowerInWatts>200</pwr:PowerInWatts></extensions>
+    </trkpt>
+    <trkpt lat="0" lon="0.001"><time>2023-06-15T10:00:02Z</time>
+      <extensions><gpxtpx:TrackPointExtension><gpxtpx:hr>110</gpxtpx:hr></gpxtpx:TrackPointExtension><pwr:PowerInWatts>300</pwr:PowerInWatts></extensions>
+    </trkpt>
+    <trkpt lat="0" lon="0.002"/>
+  </trkseg></trk>
+</gpx>"#;
+        let mut document = GpxDocument::parse(gpx.as_bytes()).unwrap();
+        resample_document(
+            &mut document,
+            ResampleStep::Time(Duration::seconds(1)),
+            Duration::minutes(1),
+        )
+        .unwrap();
+
+        let points = &document.tracks[0].segments[0].points;
+        assert_eq!(points.len(), 3);
+        let mut sensors = SensorData::default();
+        read_sensors(&points[1].extensions, &mut sensors);
+        assert_eq!(sensors.heart_rate, Some(105));
+        assert_eq!(sensors.power, Some(250));
+
+        let mut output = Vec::new();
+        document.write_to(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(&format!(
+            r#"<TrackPointExtension xmlns="{TRACK_POINT_EXTENSION_NAMESPACE}">"#
+        )));
+        assert!(output.contains(&format!(
+            r#"<pwr:PowerInWatts xmlns:pwr="{POWER_EXTENSION_NAMESPACE}">250</pwr:PowerInWatts>"#
+        )));
+        assert!(output.contains(r#"<trkpt lat="0" lon="0.0005">"#));
+    }
+
+    #[test]
+    fn test_resample_document_without_timestamps() {
+        let gpx = r#"<gpx version="1.1" creator="test">
+  <trk><trkseg>
+    <trkpt lat="0" lon="0"/>
+    <trkpt lat="0" lon="0.0002"/>
+    <trkpt lat="0" lon="0.0004"/>
+  </trkseg></trk>
+</gpx>"#;
+        let mut document = GpxDocument::parse(gpx.as_bytes()).unwrap();
+        resample_document(&mut document, ResampleStep::Distance(20.0), Duration::ZERO).unwrap();
+        let points = &document.tracks[0].segments[0].points;
+        // About 44.5 m long, so points at 0, 20 and 40 m.
+        assert_eq!(points.len(), 3);
+        assert!(points.iter().all(|point| point.time.is_none()));
+
+        let mut document = GpxDocument::parse(gpx.as_bytes()).unwrap();
+        let result = resample_document(
+            &mut document,
+            ResampleStep::Time(Duration::seconds(1)),
+            Duration::ZERO,
+        );
+        assert!(matches!(result, Err(GpxWrenchError::InsufficientData(_))));
+    }
+}
@@ -0,0 +1,176 @@
+use crate::parse_finite;
+
+/// Sensor readings recorded in a track point's `<extensions>`.
+///
+/// Values are read from the Garmin `TrackPointExtension` (v1 and v2), the `PowerExtension`,
+/// the cluetrust `gpxdata` schema and the plain `<power>` element written by Strava and others.
+/// Elements are matched by local name, so any namespace prefix is accepted.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SensorData {
+    /// Heart rate in beats per minute.
+    pub heart_rate: Option<u32>,
+    /// Cadence in revolutions (or steps) per minute.
+    pub cadence: Option<u32>,
+    /// Power in watts.
+    pub power: Option<u32>,
+    /// Air temperature in degrees Celsius.
+    pub temperature: Option<f64>,
+    /// Water temperature in degrees Celsius.
+    pub water_temperature: Option<f64>,
+    /// Depth in meters.
+    pub depth: Option<f64>,
+    /// Speed in meters per second as reported by the device.
+    pub speed: Option<f64>,
+    /// Course over ground in degrees.
+    pub course: Option<f64>,
+}
+
+impl SensorData {
+    pub fn is_empty(&self) -> bool {
+        *self == SensorData::default()
+    }
+
+    /// Interpolates between the readings `a` and `b` at `fraction` (0 at `a`, 1 at `b`). A value
+    /// present in only one of them is taken from the nearer reading if that is the one having it.
+    pub fn interpolate(a: &SensorData, b: &SensorData, fraction: f64) -> SensorData {
+        let value = |a: Option<f64>, b: Option<f64>| match (a, b) {
+            (Some(a), Some(b)) => Some(a + (b - a) * fraction),
+            (Some(a), None) if fraction < 0.5 => Some(a),
+            (None, Some(b)) if fraction >= 0.5 => Some(b),
+            _ => None,
+        };
+        let count = |a: Option<u32>, b: Option<u32>| {
+            value(a.map(f64::from), b.map(f64::from)).map(|value| value.round() as u32)
+        };
+        // Courses are interpolated along the shorter way around the compass.
+        let course = value(
+            a.course,
+            b.course
+                .zip(a.course)
+                .map(|(b, a)| a + (b - a + 540.0).rem_euclid(360.0) - 180.0)
+                .or(b.course),
+        )
+        .map(|course| course.rem_euclid(360.0));
+
+        SensorData {
+            heart_rate: count(a.heart_rate, b.heart_rate),
+            cadence: count(a.cadence, b.cadence),
+            power: count(a.power, b.power),
+            temperature: value(a.temperature, b.temperature),
+            water_temperature: value(a.water_temperature, b.water_temperature),
+            depth: value(a.depth, b.depth),
+            speed: value(a.speed, b.speed),
+            course,
+        }
+    }
+
+    /// Stores the value of an extension element with the given local name. Unknown names and
+    /// unparseable or non-finite values are ignored.
+    pub(crate) fn set_field(&mut self, name: &str, text: &str) {
+        let text = text.trim();
+        match name.to_ascii_lowercase().as_str() {
+            "hr" | "heartrate" => self.heart_rate = parse_count(text).or(self.heart_rate),
+            "cad" | "cadence" => self.cadence = parse_count(text).or(self.cadence),
+            "power" | "powerinwatts" | "watts" => self.power = parse_count(text).or(self.power),
+            "atemp" | "temp" | "temperature" => {
+                self.temperature = parse_finite(text).or(self.temperature);
+            }
+            "wtemp" => self.water_temperature = parse_finite(text).or(self.water_temperature),
+            "depth" => self.depth = parse_finite(text).or(self.depth),
+            "speed" => self.speed = parse_finite(text).or(self.speed),
+            "course" => self.course = parse_finite(text).or(self.course),
+            _ => {}
+        }
+    }
+}
+
+/// Parses a whole-number reading. Some devices write these as decimals, e.g. `92.0`.
+fn parse_count(text: &str) -> Option<u32> {
+    text.parse().ok().or_else(|| {
+        parse_finite(text)
+            .filter(|value| *value >= 0.0 && *value <= f64::from(u32::MAX))
+            .map(|value| value.round() as u32)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_field_matches_known_names() {
+        let mut sensors = SensorData::default();
+        sensors.set_field("hr", "150");
+        sensors.set_field("cad", " 92.0 ");
+        sensors.set_field("PowerInWatts", "250");
+        sensors.set_field("atemp", "21.5");
+        sensors.set_field("wtemp", "18");
+        sensors.set_field("depth", "2.5");
+        sensors.set_field("speed", "4.2");
+        sensors.set_field("course", "270");
+        sensors.set_field("unknown", "1");
+
+        assert_eq!(
+            sensors,
+            SensorData {
+                heart_rate: Some(150),
+                cadence: Some(92),
+                power: Some(250),
+                temperature: Some(21.5),
+                water_temperature: Some(18.0),
+                depth: Some(2.5),
+                speed: Some(4.2),
+                course: Some(270.0),
+            }
+        );
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let a = SensorData {
+            heart_rate: Some(100),
+            power: Some(200),
+            course: Some(350.0),
+            temperature: Some(20.0),
+            ..SensorData::default()
+        };
+        let b = SensorData {
+            heart_rate: Some(111),
+            course: Some(30.0),
+            temperature: Some(22.0),
+            depth: Some(4.0),
+            ..SensorData::default()
+        };
+
+        let early = SensorData::interpolate(&a, &b, 0.25);
+        assert_eq!(early.heart_rate, Some(103));
+        assert_eq!(early.power, Some(200));
+        assert_eq!(early.depth, None);
+        assert_eq!(early.temperature, Some(20.5));
+        assert_eq!(early.course, Some(0.0));
+
+        let late = SensorData::interpolate(&a, &b, 0.75);
+        assert_eq!(late.power, None);
+        assert_eq!(late.depth, Some(4.0));
+        assert_eq!(late.course, Some(20.0));
+    }
+
+    #[test]
+    fn test_set_field_ignores_invalid_values() {
+        let mut sensors = SensorData::default();
+        sensors.set_field("heartrate", "150");
+        sensors.set_field("hr", "n/a");
+        sensors.set_field("cadence", "-5");
+        sensors.set_field("atemp", "NaN");
+        sensors.set_field("speed", "inf");
+        sensors.set_field("course", "-infinity");
+
+        assert_eq!(sensors.heart_rate, Some(150));
+        assert_eq!(sensors.cadence, None);
+        assert_eq!(sensors.temperature, None);
+        assert_eq!(sensors.speed, None);
+        assert_eq!(sensors.course, None);
+        assert!(!sensors.is_empty());
+        assert!(SensorData::default().is_empty());
+    }
+}
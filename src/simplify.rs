@@ -0,0 +1,324 @@
+//! Track simplification: dropping points that barely change the shape of a track.
+
+use crate::gpxxml::PositionedPoint;
+use crate::{EARTH_RADIUS, haversine_distance};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SimplifyAlgorithm {
+    /// Ramer–Douglas–Peucker: keeps every point that deviates more than the tolerance from the
+    /// line between the points kept around it.
+    #[default]
+    RamerDouglasPeucker,
+    /// Visvalingam–Whyatt: repeatedly removes the point forming the smallest triangle with its
+    /// neighbours, as long as that triangle is smaller than the tolerance squared.
+    VisvalingamWhyatt,
+}
+
+/// Returns for each point of a track segment whether it is kept.
+///
+/// `tolerance` is in meters. With `use_elevation`, distances and areas also account for the
+/// difference in elevation between points that have one. The first and last point are always
+/// kept.
+pub fn simplify(
+    track_points: &[PositionedPoint],
+    tolerance: f64,
+    algorithm: SimplifyAlgorithm,
+    use_elevation: bool,
+) -> Vec<bool> {
+    if track_points.len() <= 2 {
+        return vec![true; track_points.len()];
+    }
+    match algorithm {
+        SimplifyAlgorithm::RamerDouglasPeucker => {
+            ramer_douglas_peucker(track_points, tolerance, use_elevation)
+        }
+        SimplifyAlgorithm::VisvalingamWhyatt => {
+            visvalingam_whyatt(track_points, tolerance * tolerance, use_elevation)
+        }
+    }
+}
+
+fn ramer_douglas_peucker(
+    track_points: &[PositionedPoint],
+    tolerance: f64,
+    use_elevation: bool,
+) -> Vec<bool> {
+    let mut keep = vec![false; track_points.len()];
+    keep[0] = true;
+    keep[track_points.len() - 1] = true;
+
+    let mut ranges = vec![(0, track_points.len() - 1)];
+    while let Some((start, end)) = ranges.pop() {
+        let farthest = (start + 1..end)
+            .map(|idx| {
+                let distance = segment_distance(
+                    &track_points[idx],
+                    &track_points[start],
+                    &track_points[end],
+                    use_elevation,
+                );
+                (idx, distance)
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+        if let Some((idx, distance)) = farthest
+            && distance > tolerance
+        {
+            keep[idx] = true;
+            ranges.push((start, idx));
+            ranges.push((idx, end));
+        }
+    }
+    keep
+}
+
+/// A candidate for removal in [`visvalingam_whyatt`]. Entries become stale when a neighbour is
+/// removed; `version` tells them apart from the current one.
+struct Candidate {
+    area: f64,
+    idx: usize,
+    version: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    /// Reversed, so that [`BinaryHeap`] pops the smallest area first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .area
+            .total_cmp(&self.area)
+            .then_with(|| other.idx.cmp(&self.idx))
+    }
+}
+
+fn visvalingam_whyatt(
+    track_points: &[PositionedPoint],
+    max_area: f64,
+    use_elevation: bool,
+) -> Vec<bool> {
+    let len = track_points.len();
+    let mut keep = vec![true; len];
+    let mut previous: Vec<usize> = (0..len).map(|idx| idx.saturating_sub(1)).collect();
+    let mut next: Vec<usize> = (0..len).map(|idx| idx + 1).collect();
+    let mut versions = vec![0; len];
+    let area = |previous: usize, idx: usize, next: usize| {
+        triangle_area(
+            &track_points[previous],
+            &track_points[idx],
+            &track_points[next],
+            use_elevation,
+        )
+    };
+
+    let mut candidates: BinaryHeap<Candidate> = (1..len - 1)
+        .map(|idx| Candidate {
+            area: area(idx - 1, idx, idx + 1),
+            idx,
+            version: 0,
+        })
+        .collect();
+    // Areas never drop below the last removed one, so that removing a point cannot make an
+    // already considered neighbour look less important than it was.
+    let mut removed_area: f64 = 0.0;
+    while let Some(candidate) = candidates.pop() {
+        if candidate.version != versions[candidate.idx] {
+            continue;
+        }
+        if candidate.area >= max_area {
+            break;
+        }
+        removed_area = removed_area.max(candidate.area);
+        let (before, after) = (previous[candidate.idx], next[candidate.idx]);
+        keep[candidate.idx] = false;
+        next[before] = after;
+        previous[after] = before;
+
+        for neighbour in [before, after] {
+            if neighbour == 0 || neighbour == len - 1 {
+                continue;
+            }
+            versions[neighbour] += 1;
+            candidates.push(Candidate {
+                area: area(previous[neighbour], neighbour, next[neighbour]).max(removed_area),
+                idx: neighbour,
+                version: versions[neighbour],
+            });
+        }
+    }
+    keep
+}
+
+/// Returns the distance in meters between two points, including the elevation difference with
+/// `use_elevation` when both points have one.
+fn distance(a: &PositionedPoint, b: &PositionedPoint, use_elevation: bool) -> f64 {
+    let horizontal = haversine_distance(a.lat, a.lon, b.lat, b.lon);
+    match (use_elevation, a.ele, b.ele) {
+        (true, Some(a_ele), Some(b_ele)) => horizontal.hypot(b_ele - a_ele),
+        _ => horizontal,
+    }
+}
+
+/// Returns the initial bearing from `a` to `b` in radians.
+fn bearing(a: &PositionedPoint, b: &PositionedPoint) -> f64 {
+    let (lat1, lat2) = (a.lat.to_radians(), b.lat.to_radians());
+    let delta_lon = (b.lon - a.lon).to_radians();
+    let y = delta_lon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * delta_lon.cos();
+    y.atan2(x)
+}
+
+/// Returns the distance in meters from `point` to the great-circle segment from `start` to
+/// `end`, based on the cross-track distance.
+fn segment_distance(
+    point: &PositionedPoint,
+    start: &PositionedPoint,
+    end: &PositionedPoint,
+    use_elevation: bool,
+) -> f64 {
+    let segment_length = haversine_distance(start.lat, start.lon, end.lat, end.lon);
+    let start_distance = haversine_distance(start.lat, start.lon, point.lat, point.lon);
+    if segment_length == 0.0 {
+        return distance(start, point, use_elevation);
+    }
+
+    let angle = bearing(start, point) - bearing(start, end);
+    if angle.cos() < 0.0 {
+        // The point lies behind the start of the segment.
+        return distance(start, point, use_elevation);
+    }
+    let angular_distance = start_distance / EARTH_RADIUS;
+    let cross_track = (angular_distance.sin() * angle.sin()).asin();
+    let along_track =
+        ((angular_distance.cos() / cross_track.cos()).clamp(-1.0, 1.0)).acos() * EARTH_RADIUS;
+    if along_track > segment_length {
+        return distance(end, point, use_elevation);
+    }
+
+    let horizontal = (cross_track * EARTH_RADIUS).abs();
+    match (use_elevation, start.ele, end.ele, point.ele) {
+        (true, Some(start_ele), Some(end_ele), Some(ele)) => {
+            let fraction = along_track / segment_length;
+            let line_ele = start_ele + (end_ele - start_ele) * fraction;
+            horizontal.hypot(ele - line_ele)
+        }
+        _ => horizontal,
+    }
+}
+
+/// Returns the area in square meters of the triangle between three nearby points, using
+/// Heron's formula on the distances between them in its numerically stable form for thin
+/// triangles.
+fn triangle_area(
+    a: &PositionedPoint,
+    b: &PositionedPoint,
+    c: &PositionedPoint,
+    use_elevation: bool,
+) -> f64 {
+    let mut sides = [
+        distance(a, b, use_elevation),
+        distance(b, c, use_elevation),
+        distance(c, a, use_elevation),
+    ];
+    sides.sort_by(|x, y| y.total_cmp(x));
+    let [a, b, c] = sides;
+    let product = (a + (b + c)) * (c - (a - b)) * (c + (a - b)) * (a + (b - c));
+    product.max(0.0).sqrt() / 4.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Points along the equator every 0.001° of longitude (about 111 m), offset north by the
+    /// given number of meters.
+    fn track(offsets: &[f64]) -> Vec<PositionedPoint> {
+        offsets
+            .iter()
+            .enumerate()
+            .map(|(idx, offset)| {
+                let lat = (offset / EARTH_RADIUS).to_degrees();
+                PositionedPoint {
+                    lat,
+                    lon: idx as f64 * 0.001,
+                    ele: None,
+                    time: None,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_segment_distance() {
+        let points = track(&[0.0, 10.0, 0.0]);
+        let distance = segment_distance(&points[1], &points[0], &points[2], false);
+        assert!((distance - 10.0).abs() < 0.01, "{distance}");
+
+        // Beyond the end of the segment, the distance to the end point counts.
+        let distance = segment_distance(&points[2], &points[0], &points[1], false);
+        let expected = haversine_distance(points[1].lat, points[1].lon, 0.0, 0.002);
+        assert!((distance - expected).abs() < 0.01, "{distance}");
+    }
+
+    #[test]
+    fn test_simplify_rdp() {
+        let points = track(&[0.0, 1.0, 0.0, -1.0, 20.0, 0.0, 0.5, 0.0]);
+        let keep = simplify(&points, 5.0, SimplifyAlgorithm::RamerDouglasPeucker, false);
+        assert_eq!(keep, [true, false, false, true, true, true, false, true]);
+
+        // Point 2 lies exactly between its neighbours.
+        let fine = simplify(&points, 0.1, SimplifyAlgorithm::RamerDouglasPeucker, false);
+        assert_eq!(fine, [true, true, false, true, true, true, true, true]);
+        assert_eq!(
+            simplify(
+                &points[..2],
+                100.0,
+                SimplifyAlgorithm::RamerDouglasPeucker,
+                false
+            ),
+            [true, true]
+        );
+    }
+
+    #[test]
+    fn test_simplify_visvalingam() {
+        let points = track(&[0.0, 1.0, 0.0, -1.0, 20.0, 0.0, 0.5, 0.0]);
+        let keep = simplify(&points, 20.0, SimplifyAlgorithm::VisvalingamWhyatt, false);
+        assert_eq!(keep, [true, false, false, true, true, true, false, true]);
+
+        let straight = track(&[0.0; 6]);
+        let keep = simplify(&straight, 0.1, SimplifyAlgorithm::VisvalingamWhyatt, false);
+        assert_eq!(keep, [true, false, false, false, false, true]);
+    }
+
+    #[test]
+    fn test_simplify_with_elevation() {
+        let mut points = track(&[0.0; 5]);
+        for (point, ele) in points.iter_mut().zip([100.0, 100.0, 130.0, 100.0, 100.0]) {
+            point.ele = Some(ele);
+        }
+
+        for algorithm in [
+            SimplifyAlgorithm::RamerDouglasPeucker,
+            SimplifyAlgorithm::VisvalingamWhyatt,
+        ] {
+            let flat = simplify(&points, 10.0, algorithm, false);
+            assert_eq!(flat, [true, false, false, false, true], "{algorithm:?}");
+            let three_d = simplify(&points, 10.0, algorithm, true);
+            assert!(three_d[2], "{algorithm:?}");
+        }
+    }
+}
@@ -0,0 +1,352 @@
+//! Smoothing GPS noise out of track positions.
+
+use crate::{EARTH_RADIUS, TrackPoint};
+
+/// Settings of the constant-velocity Kalman filter used by [`smooth`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KalmanOptions {
+    /// Standard deviation of the acceleration between points in m/s². Higher values follow the
+    /// recorded positions more closely.
+    pub process_noise: f64,
+    /// Standard deviation of a recorded position in meters. Points with an HDOP use this value
+    /// multiplied by their HDOP instead.
+    pub measurement_noise: f64,
+    /// Whether a Rauch–Tung–Striebel backward pass refines every position with the points
+    /// recorded after it. Without it, each position only depends on the points before it.
+    pub backward_pass: bool,
+}
+
+impl Default for KalmanOptions {
+    fn default() -> Self {
+        KalmanOptions {
+            process_noise: 1.0,
+            measurement_noise: 5.0,
+            backward_pass: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SmoothingMethod {
+    Kalman(KalmanOptions),
+    /// The mean position of the point and up to `radius` points on either side. The window
+    /// shrinks towards the ends of the segment, so that the first and last point stay in place.
+    MovingAverage {
+        radius: usize,
+    },
+}
+
+impl Default for SmoothingMethod {
+    fn default() -> Self {
+        SmoothingMethod::Kalman(KalmanOptions::default())
+    }
+}
+
+/// Returns the points of a track segment with smoothed positions. Everything but latitude and
+/// longitude is left as recorded.
+pub fn smooth(track_points: &[TrackPoint], method: &SmoothingMethod) -> Vec<TrackPoint> {
+    let Some(origin) = track_points.first() else {
+        return Vec::new();
+    };
+    let projection = LocalProjection::new(origin);
+    let positions: Vec<[f64; 2]> = track_points
+        .iter()
+        .map(|point| projection.to_meters(point))
+        .collect();
+    let smoothed = match method {
+        SmoothingMethod::Kalman(options) => kalman(track_points, &positions, options),
+        SmoothingMethod::MovingAverage { radius } => moving_average(&positions, *radius),
+    };
+
+    track_points
+        .iter()
+        .zip(smoothed)
+        .map(|(point, position)| {
+            let (lat, lon) = projection.to_degrees(position);
+            TrackPoint {
+                lat,
+                lon,
+                ..point.clone()
+            }
+        })
+        .collect()
+}
+
+/// An equirectangular projection to meters east and north of a point, accurate enough over the
+/// short distances between neighbouring track points.
+struct LocalProjection {
+    lat: f64,
+    lon: f64,
+    meters_per_degree_lat: f64,
+    meters_per_degree_lon: f64,
+}
+
+impl LocalProjection {
+    fn new(origin: &TrackPoint) -> Self {
+        let meters_per_degree_lat = EARTH_RADIUS.to_radians();
+        LocalProjection {
+            lat: origin.lat,
+            lon: origin.lon,
+            meters_per_degree_lat,
+            meters_per_degree_lon: meters_per_degree_lat * origin.lat.to_radians().cos().max(1e-6),
+        }
+    }
+
+    fn to_meters(&self, point: &TrackPoint) -> [f64; 2] {
+        // Longitudes are taken the short way around, so that tracks crossing the antimeridian
+        // do not jump.
+        let delta_lon = (point.lon - self.lon + 540.0).rem_euclid(360.0) - 180.0;
+        [
+            delta_lon * self.meters_per_degree_lon,
+            (point.lat - self.lat) * self.meters_per_degree_lat,
+        ]
+    }
+
+    fn to_degrees(&self, [east, north]: [f64; 2]) -> (f64, f64) {
+        let lat = self.lat + north / self.meters_per_degree_lat;
+        let lon = self.lon + east / self.meters_per_degree_lon;
+        (lat, (lon + 540.0).rem_euclid(360.0) - 180.0)
+    }
+}
+
+type Matrix = [[f64; 2]; 2];
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let cell = |row: usize, column: usize| a[row][0] * b[0][column] + a[row][1] * b[1][column];
+    [[cell(0, 0), cell(0, 1)], [cell(1, 0), cell(1, 1)]]
+}
+
+fn transpose(m: &Matrix) -> Matrix {
+    [[m[0][0], m[1][0]], [m[0][1], m[1][1]]]
+}
+
+fn add(a: &Matrix, b: &Matrix) -> Matrix {
+    [
+        [a[0][0] + b[0][0], a[0][1] + b[0][1]],
+        [a[1][0] + b[1][0], a[1][1] + b[1][1]],
+    ]
+}
+
+fn inverse(m: &Matrix) -> Matrix {
+    let determinant = m[0][0] * m[1][1] - m[0][1] * m[1][0];
+    [
+        [m[1][1] / determinant, -m[0][1] / determinant],
+        [-m[1][0] / determinant, m[0][0] / determinant],
+    ]
+}
+
+fn apply(m: &Matrix, v: [f64; 2]) -> [f64; 2] {
+    [
+        m[0][0] * v[0] + m[0][1] * v[1],
+        m[1][0] * v[0] + m[1][1] * v[1],
+    ]
+}
+
+/// Variance of the initial velocity estimate in (m/s)², loose enough for any vehicle.
+const INITIAL_VELOCITY_VARIANCE: f64 = 100.0;
+
+/// Runs a constant-velocity Kalman filter over the projected positions. East and north are
+/// filtered independently with the state (position, velocity); since both axes share their
+/// noise model, they also share the covariances.
+fn kalman(
+    track_points: &[TrackPoint],
+    positions: &[[f64; 2]],
+    options: &KalmanOptions,
+) -> Vec<[f64; 2]> {
+    let measurement_variance = |point: &TrackPoint| {
+        let accuracy =
+            options.measurement_noise * point.hdop.filter(|hdop| *hdop > 0.0).unwrap_or(1.0);
+        accuracy * accuracy
+    };
+    let acceleration_variance = options.process_noise * options.process_noise;
+
+    // Filtered states per axis, with the covariance and the prediction they were updated from.
+    let mut states: Vec<[[f64; 2]; 2]> = Vec::with_capacity(positions.len());
+    let mut covariances: Vec<Matrix> = Vec::with_capacity(positions.len());
+    let mut predictions: Vec<([[f64; 2]; 2], Matrix, Matrix)> = Vec::with_capacity(positions.len());
+
+    let mut state = positions[0].map(|position| [position, 0.0]);
+    let mut covariance = [
+        [measurement_variance(&track_points[0]), 0.0],
+        [0.0, INITIAL_VELOCITY_VARIANCE],
+    ];
+    states.push(state);
+    covariances.push(covariance);
+
+    for idx in 1..positions.len() {
+        let dt = (track_points[idx].time - track_points[idx - 1].time)
+            .as_seconds_f64()
+            .max(0.0);
+        let transition = [[1.0, dt], [0.0, 1.0]];
+        let process = [
+            [dt.powi(4) / 4.0, dt.powi(3) / 2.0],
+            [dt.powi(3) / 2.0, dt * dt],
+        ]
+        .map(|row| row.map(|value| value * acceleration_variance));
+
+        let predicted_state = state.map(|axis| apply(&transition, axis));
+        let predicted_covariance = add(
+            &multiply(&multiply(&transition, &covariance), &transpose(&transition)),
+            &process,
+        );
+        predictions.push((predicted_state, predicted_covariance, transition));
+
+        // Only the position is measured, so the gain is the first column of the covariance
+        // divided by the innovation variance.
+        let innovation_variance =
+            predicted_covariance[0][0] + measurement_variance(&track_points[idx]);
+        let gain = [
+            predicted_covariance[0][0] / innovation_variance,
+            predicted_covariance[1][0] / innovation_variance,
+        ];
+        state = [0, 1].map(|axis| {
+            let [position, velocity] = predicted_state[axis];
+            let innovation = positions[idx][axis] - position;
+            [
+                position + gain[0] * innovation,
+                velocity + gain[1] * innovation,
+            ]
+        });
+        covariance = [
+            [
+                (1.0 - gain[0]) * predicted_covariance[0][0],
+                (1.0 - gain[0]) * predicted_covariance[0][1],
+            ],
+            [
+                predicted_covariance[1][0] - gain[1] * predicted_covariance[0][0],
+                predicted_covariance[1][1] - gain[1] * predicted_covariance[0][1],
+            ],
+        ];
+        states.push(state);
+        covariances.push(covariance);
+    }
+
+    if options.backward_pass {
+        for idx in (0..positions.len().saturating_sub(1)).rev() {
+            let (predicted_state, predicted_covariance, transition) = &predictions[idx];
+            let smoother_gain = multiply(
+                &multiply(&covariances[idx], &transpose(transition)),
+                &inverse(predicted_covariance),
+            );
+            let next = states[idx + 1];
+            for axis in 0..2 {
+                let correction = apply(
+                    &smoother_gain,
+                    [
+                        next[axis][0] - predicted_state[axis][0],
+                        next[axis][1] - predicted_state[axis][1],
+                    ],
+                );
+                states[idx][axis][0] += correction[0];
+                states[idx][axis][1] += correction[1];
+            }
+        }
+    }
+    states
+        .iter()
+        .map(|[east, north]| [east[0], north[0]])
+        .collect()
+}
+
+fn moving_average(positions: &[[f64; 2]], radius: usize) -> Vec<[f64; 2]> {
+    (0..positions.len())
+        .map(|idx| {
+            let radius = radius.min(idx).min(positions.len() - 1 - idx);
+            let window = &positions[idx - radius..=idx + radius];
+            let count = window.len() as f64;
+            [0, 1].map(|axis| window.iter().map(|position| position[axis]).sum::<f64>() / count)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::haversine_distance;
+    use time::{Duration, OffsetDateTime};
+
+    /// One point per second heading north at 2 m/s, with the given east offsets in meters.
+    fn zig_zag(offsets: &[f64]) -> Vec<TrackPoint> {
+        let start = OffsetDateTime::from_unix_timestamp(1_686_823_200).unwrap();
+        let origin = TrackPoint::new(47.0, 8.0, start);
+        let projection = LocalProjection::new(&origin);
+        offsets
+            .iter()
+            .enumerate()
+            .map(|(idx, &offset)| {
+                let (lat, lon) = projection.to_degrees([offset, idx as f64 * 2.0]);
+                TrackPoint::new(lat, lon, start + Duration::seconds(idx as i64))
+            })
+            .collect()
+    }
+
+    fn track_length(points: &[TrackPoint]) -> f64 {
+        points
+            .windows(2)
+            .map(|pair| haversine_distance(pair[0].lat, pair[0].lon, pair[1].lat, pair[1].lon))
+            .sum()
+    }
+
+    #[test]
+    fn test_projection_round_trip() {
+        let origin = TrackPoint::new(47.0, 179.9999, OffsetDateTime::UNIX_EPOCH);
+        let projection = LocalProjection::new(&origin);
+        let point = TrackPoint::new(47.001, -179.9999, OffsetDateTime::UNIX_EPOCH);
+        let meters = projection.to_meters(&point);
+        assert!(meters[0] > 0.0 && meters[0] < 20.0, "{meters:?}");
+        let (lat, lon) = projection.to_degrees(meters);
+        assert!((lat - 47.001).abs() < 1e-9 && (lon + 179.9999).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_kalman_reduces_zig_zag() {
+        let offsets: Vec<f64> = (0..60)
+            .map(|idx| if idx % 2 == 0 { 4.0 } else { -4.0 })
+            .collect();
+        let points = zig_zag(&offsets);
+        let original = track_length(&points);
+
+        for backward_pass in [false, true] {
+            let options = KalmanOptions {
+                backward_pass,
+                ..KalmanOptions::default()
+            };
+            let smoothed = smooth(&points, &SmoothingMethod::Kalman(options));
+            assert_eq!(smoothed.len(), points.len());
+            assert_eq!(smoothed[10].time, points[10].time);
+            let length = track_length(&smoothed);
+            assert!(
+                length < original * 0.5,
+                "{backward_pass}: {length} of {original}"
+            );
+            assert!(length > 110.0, "{backward_pass}: {length}");
+        }
+    }
+
+    #[test]
+    fn test_kalman_trusts_precise_points() {
+        let mut points = zig_zag(&[0.0, 0.0, 0.0, 0.0, 20.0, 0.0, 0.0, 0.0]);
+        let imprecise = smooth(&points, &SmoothingMethod::default());
+        points[4].hdop = Some(0.01);
+        let precise = smooth(&points, &SmoothingMethod::default());
+
+        let offset = |point: &TrackPoint| LocalProjection::new(&points[0]).to_meters(point)[0];
+        assert!(offset(&precise[4]) > offset(&imprecise[4]) + 5.0);
+        assert!((offset(&precise[4]) - 20.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_moving_average() {
+        let points = zig_zag(&[0.0, 3.0, -3.0, 3.0, 0.0]);
+        let smoothed = smooth(&points, &SmoothingMethod::MovingAverage { radius: 1 });
+        let projection = LocalProjection::new(&points[0]);
+        let offsets: Vec<f64> = smoothed
+            .iter()
+            .map(|point| projection.to_meters(point)[0])
+            .collect();
+        for (offset, expected) in offsets.iter().zip([0.0, 0.0, 1.0, 0.0, 0.0]) {
+            assert!((offset - expected).abs() < 1e-6, "{offsets:?}");
+        }
+        assert!(smooth(&[], &SmoothingMethod::default()).is_empty());
+    }
+}
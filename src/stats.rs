@@ -0,0 +1,213 @@
+use crate::document::Bounds;
+use crate::gpxxml::PositionedPoint;
+use crate::{TrackPoint, calculate_speed, haversine_distance};
+use time::{Duration, OffsetDateTime};
+
+/// Summary of one or more track segments.
+///
+/// Distances are in meters and speeds in meters per second. Consecutive points are only
+/// connected within a segment, so gaps between segments add neither distance nor moving time.
+/// Points without a time count towards the point count, distance, elevation and bounds, but
+/// times and speeds only come from consecutive points that both have a time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackStats {
+    pub point_count: usize,
+    pub segment_count: usize,
+    pub distance: f64,
+    /// Part of `distance` between consecutive points that both have a time.
+    pub timed_distance: f64,
+    pub start_time: Option<OffsetDateTime>,
+    pub end_time: Option<OffsetDateTime>,
+    /// Time between the earliest and the latest point with a time.
+    pub elapsed_time: Duration,
+    /// Total duration of the intervals whose speed is at or above the speed threshold.
+    pub moving_time: Duration,
+    /// Distance covered during `moving_time`.
+    pub moving_distance: f64,
+    pub max_speed: f64,
+    pub elevation_gain: f64,
+    pub elevation_loss: f64,
+    pub bounds: Option<Bounds>,
+}
+
+impl TrackStats {
+    /// Timed distance divided by elapsed time, or `None` if no time elapsed.
+    pub fn average_speed(&self) -> Option<f64> {
+        let seconds = self.elapsed_time.as_seconds_f64();
+        (seconds > 0.0).then(|| self.timed_distance / seconds)
+    }
+
+    /// Moving distance divided by moving time, or `None` if the track never moved.
+    pub fn average_moving_speed(&self) -> Option<f64> {
+        let seconds = self.moving_time.as_seconds_f64();
+        (seconds > 0.0).then(|| self.moving_distance / seconds)
+    }
+}
+
+/// Computes statistics for the given segments. Intervals between consecutive points count as
+/// moving when their speed is at or above `speed_threshold`, as in activity detection.
+pub fn compute_track_stats(segments: &[Vec<PositionedPoint>], speed_threshold: f64) -> TrackStats {
+    let mut stats = TrackStats {
+        point_count: 0,
+        segment_count: segments.len(),
+        distance: 0.0,
+        timed_distance: 0.0,
+        start_time: None,
+        end_time: None,
+        elapsed_time: Duration::ZERO,
+        moving_time: Duration::ZERO,
+        moving_distance: 0.0,
+        max_speed: 0.0,
+        elevation_gain: 0.0,
+        elevation_loss: 0.0,
+        bounds: None,
+    };
+
+    for point in segments.iter().flatten() {
+        stats.point_count += 1;
+        if let Some(time) = point.time {
+            if stats.start_time.is_none_or(|start| time < start) {
+                stats.start_time = Some(time);
+            }
+            if stats.end_time.is_none_or(|end| time > end) {
+                stats.end_time = Some(time);
+            }
+        }
+        let bounds = stats.bounds.get_or_insert(Bounds {
+            min_lat: point.lat,
+            min_lon: point.lon,
+            max_lat: point.lat,
+            max_lon: point.lon,
+        });
+        bounds.min_lat = bounds.min_lat.min(point.lat);
+        bounds.min_lon = bounds.min_lon.min(point.lon);
+        bounds.max_lat = bounds.max_lat.max(point.lat);
+        bounds.max_lon = bounds.max_lon.max(point.lon);
+    }
+
+    for points in segments.iter().flat_map(|segment| segment.windows(2)) {
+        let (p1, p2) = (&points[0], &points[1]);
+        let distance = haversine_distance(p1.lat, p1.lon, p2.lat, p2.lon);
+        stats.distance += distance;
+
+        if let (Some(time1), Some(time2)) = (p1.time, p2.time) {
+            let speed = calculate_speed(
+                &TrackPoint::new(p1.lat, p1.lon, time1),
+                &TrackPoint::new(p2.lat, p2.lon, time2),
+            );
+            let interval = time2 - time1;
+            stats.timed_distance += distance;
+            stats.max_speed = stats.max_speed.max(speed);
+            if speed >= speed_threshold && interval.is_positive() {
+                stats.moving_time += interval;
+                stats.moving_distance += distance;
+            }
+        }
+
+        if let (Some(ele1), Some(ele2)) = (p1.ele, p2.ele) {
+            let climb = ele2 - ele1;
+            if climb > 0.0 {
+                stats.elevation_gain += climb;
+            } else {
+                stats.elevation_loss -= climb;
+            }
+        }
+    }
+
+    if let (Some(start), Some(end)) = (stats.start_time, stats.end_time) {
+        stats.elapsed_time = end - start;
+    }
+
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(lat: f64, seconds: i64, ele: Option<f64>) -> PositionedPoint {
+        PositionedPoint {
+            lat,
+            lon: -122.4194,
+            ele,
+            time: Some(OffsetDateTime::UNIX_EPOCH + Duration::seconds(seconds)),
+        }
+    }
+
+    #[test]
+    fn test_compute_track_stats() {
+        // 0.001 degrees of latitude is about 111 m.
+        let segments = vec![
+            vec![
+                point(37.0, 0, Some(10.0)),
+                point(37.0, 10, Some(12.0)),
+                point(37.001, 20, Some(11.0)),
+            ],
+            vec![point(37.002, 100, None), point(37.003, 110, Some(20.0))],
+        ];
+
+        let stats = compute_track_stats(&segments, 1.0);
+
+        assert_eq!(stats.point_count, 5);
+        assert_eq!(stats.segment_count, 2);
+        assert!((stats.distance - 222.4).abs() < 0.5, "{}", stats.distance);
+        assert_eq!(stats.elapsed_time, Duration::seconds(110));
+        assert_eq!(stats.moving_time, Duration::seconds(20));
+        assert!(
+            (stats.max_speed - 11.12).abs() < 0.05,
+            "{}",
+            stats.max_speed
+        );
+        assert_eq!(stats.elevation_gain, 2.0);
+        assert_eq!(stats.elevation_loss, 1.0);
+        let bounds = stats.bounds.unwrap();
+        assert_eq!((bounds.min_lat, bounds.max_lat), (37.0, 37.003));
+        assert!((stats.average_moving_speed().unwrap() - 11.12).abs() < 0.05);
+        assert!((stats.average_speed().unwrap() - 2.02).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_compute_track_stats_without_times() {
+        let mut segments = vec![vec![
+            point(37.0, 0, Some(10.0)),
+            point(37.001, 0, Some(15.0)),
+            point(37.002, 0, Some(12.0)),
+        ]];
+        for point in &mut segments[0] {
+            point.time = None;
+        }
+
+        let stats = compute_track_stats(&segments, 1.0);
+
+        assert_eq!(stats.point_count, 3);
+        assert!((stats.distance - 222.4).abs() < 0.5, "{}", stats.distance);
+        assert_eq!(stats.timed_distance, 0.0);
+        assert_eq!((stats.elevation_gain, stats.elevation_loss), (5.0, 3.0));
+        assert_eq!(stats.bounds.unwrap().max_lat, 37.002);
+        assert_eq!((stats.start_time, stats.end_time), (None, None));
+        assert_eq!(stats.elapsed_time, Duration::ZERO);
+        assert_eq!(stats.moving_time, Duration::ZERO);
+        assert_eq!(stats.max_speed, 0.0);
+        assert_eq!(stats.average_speed(), None);
+
+        // Only the timed pair gives a speed, over its own part of the distance.
+        segments[0][1].time = Some(OffsetDateTime::UNIX_EPOCH);
+        segments[0][2].time = Some(OffsetDateTime::UNIX_EPOCH + Duration::seconds(10));
+        let stats = compute_track_stats(&segments, 1.0);
+        assert!((stats.timed_distance - 111.2).abs() < 0.5);
+        assert_eq!(stats.elapsed_time, Duration::seconds(10));
+        assert_eq!(stats.moving_time, Duration::seconds(10));
+        assert!((stats.average_speed().unwrap() - 11.12).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_compute_track_stats_without_points() {
+        let stats = compute_track_stats(&[Vec::new()], 1.0);
+
+        assert_eq!(stats.point_count, 0);
+        assert_eq!(stats.segment_count, 1);
+        assert_eq!(stats.bounds, None);
+        assert_eq!(stats.average_speed(), None);
+        assert_eq!(stats.average_moving_speed(), None);
+    }
+}
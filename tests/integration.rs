@@ -1,11 +1,20 @@
 use assert_cmd::cargo::cargo_bin_cmd;
 use predicates::prelude::*;
+use std::fs;
+use std::path::PathBuf;
 use time::OffsetDateTime;
 
 fn sample_gpx() -> &'static str {
     include_str!("../samples/activity.gpx")
 }
 
+fn test_dir(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
 fn parse_timestamp(timestamp: &str) -> gpx::Time {
     OffsetDateTime::parse(
         timestamp,
@@ -197,6 +206,709 @@ fn test_trim_to_activity_removes_idle_portions() {
     );
 }
 
+/// A ride with a 30 minute stop in the middle, one point per minute.
+fn two_activity_gpx() -> String {
+    let mut points = String::new();
+    let mut lat = 37.7749;
+    for (minute, moving) in [
+        (0, false),
+        (1, true),
+        (2, true),
+        (3, true),
+        (4, true),
+        (5, false),
+        (35, false),
+        (36, true),
+        (37, true),
+        (38, true),
+        (39, true),
+        (40, false),
+    ] {
+        if moving {
+            lat += 0.01;
+        }
+        points.push_str(&format!(
+            "      <trkpt lat=\"{lat:.4}\" lon=\"-122.4194\"><time>2023-06-15T10:{minute:02}:00Z</time></trkpt>\n"
+        ));
+    }
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<gpx version=\"1.1\" creator=\"test\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n  <trk>\n    <trkseg>\n{points}    </trkseg>\n  </trk>\n</gpx>\n"
+    )
+}
+
+#[test]
+fn test_trim_to_activity_max_pause_splits_activities() {
+    let run = |args: &[&str]| {
+        let output = cargo_bin_cmd!("gpxwrench")
+            .arg("trim-to-activity")
+            .args(["-b", "0"])
+            .args(args)
+            .write_stdin(two_activity_gpx())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{args:?} failed");
+        gpx::read(output.stdout.as_slice()).unwrap()
+    };
+
+    let single: gpx::Gpx = run(&[]);
+    assert_eq!(single.tracks[0].segments.len(), 1);
+    assert_eq!(single.tracks[0].segments[0].points.len(), 11);
+
+    let segments: gpx::Gpx = run(&["--max-pause", "600"]);
+    assert_eq!(segments.tracks.len(), 1);
+    let counts: Vec<usize> = segments.tracks[0]
+        .segments
+        .iter()
+        .map(|segment| segment.points.len())
+        .collect();
+    assert_eq!(counts, [5, 5]);
+
+    let tracks: gpx::Gpx = run(&["--max-pause", "600", "--split", "track"]);
+    assert_eq!(tracks.tracks.len(), 2);
+    assert_eq!(
+        tracks.tracks[1].segments[0].points[0].time,
+        Some(parse_timestamp("2023-06-15T10:35:00Z"))
+    );
+
+    let bridged: gpx::Gpx = run(&["--max-pause", "3600"]);
+    assert_eq!(bridged.tracks[0].segments.len(), 1);
+}
+
+#[test]
+fn test_trim_to_activity_split_files() {
+    let dir = test_dir("trim_to_activity_split_files");
+    let input = dir.join("ride.gpx");
+    fs::write(&input, two_activity_gpx()).unwrap();
+
+    cargo_bin_cmd!("gpxwrench")
+        .args(["trim-to-activity", "-b", "0", "--max-pause", "600"])
+        .args(["--split", "file", "-o"])
+        .arg(dir.join("out.gpx"))
+        .arg(&input)
+        .assert()
+        .success();
+
+    for name in ["out-1.gpx", "out-2.gpx"] {
+        let gpx: gpx::Gpx = gpx::read(fs::read(dir.join(name)).unwrap().as_slice()).unwrap();
+        assert_eq!(gpx.tracks[0].segments[0].points.len(), 5, "{name}");
+    }
+    assert!(!dir.join("out-3.gpx").exists());
+
+    cargo_bin_cmd!("gpxwrench")
+        .args(["trim-to-activity", "--split", "track"])
+        .write_stdin(two_activity_gpx())
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_trim_to_activity_detection_options() {
+    let point_count = |args: &[&str]| {
+        let output = cargo_bin_cmd!("gpxwrench")
+            .arg("trim-to-activity")
+            .args(args)
+            .write_stdin(two_activity_gpx())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{args:?} failed");
+        let gpx: gpx::Gpx = gpx::read(output.stdout.as_slice()).unwrap();
+        gpx.tracks[0].segments[0].points.len()
+    };
+
+    assert_eq!(point_count(&["-b", "0"]), 11);
+    // Each activity has only four fast intervals, so nothing is detected and all points remain.
+    assert_eq!(point_count(&["-b", "0", "--min-run", "5"]), 12);
+    assert_eq!(point_count(&["--profile", "drive", "-b", "0"]), 11);
+    assert_eq!(
+        point_count(&["--smooth", "mean", "--smooth-window", "3"]),
+        12
+    );
+
+    for args in [
+        &["--speed-threshold", "2", "--stop-threshold", "3"][..],
+        &["--min-run", "0"],
+        &["--smooth", "median", "--smooth-window", "0"],
+    ] {
+        cargo_bin_cmd!("gpxwrench")
+            .arg("trim-to-activity")
+            .args(args)
+            .write_stdin(two_activity_gpx())
+            .assert()
+            .code(2);
+    }
+}
+
+#[test]
+fn test_trim_to_activity_dry_run() {
+    let dir = test_dir("trim_to_activity_dry_run");
+    let input = dir.join("ride.gpx");
+    fs::write(&input, two_activity_gpx()).unwrap();
+
+    cargo_bin_cmd!("gpxwrench")
+        .args([
+            "trim-to-activity",
+            "-b",
+            "0",
+            "--dry-run",
+            "--max-pause",
+            "600",
+        ])
+        .arg(&input)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Activity 1:      2023-06-15T10:00:00Z to 2023-06-15T10:04:00Z",
+        ))
+        .stdout(predicate::str::contains(
+            "Activity 2:      2023-06-15T10:35:00Z to 2023-06-15T10:39:00Z",
+        ))
+        .stdout(predicate::str::contains("Start trigger: point 0"))
+        .stdout(predicate::str::contains(
+            "Removed between: 1 of 12 points, 0:31:00, 0.0 m",
+        ))
+        .stdout(predicate::str::contains(
+            "Removed at end:   1 of 12 points, 0:01:00, 0.0 m",
+        ))
+        .stdout(predicate::str::contains("<gpx").not());
+
+    // Batch reports are not named like GPX files.
+    for (flag, name) in [("--dry-run", "ride.txt"), ("--explain", "ride.csv")] {
+        cargo_bin_cmd!("gpxwrench")
+            .args(["trim-to-activity", flag, "--batch"])
+            .arg(&input)
+            .arg("--output-dir")
+            .arg(dir.join("reports"))
+            .assert()
+            .success();
+        assert!(dir.join("reports").join(name).exists(), "{name}");
+    }
+    assert!(!dir.join("reports").join("ride.gpx").exists());
+
+    cargo_bin_cmd!("gpxwrench")
+        .args(["trim-to-activity", "--dry-run", "--in-place"])
+        .arg(&input)
+        .assert()
+        .code(2);
+    assert_eq!(fs::read_to_string(&input).unwrap(), two_activity_gpx());
+}
+
+#[test]
+fn test_trim_to_activity_explain() {
+    let output = cargo_bin_cmd!("gpxwrench")
+        .args(["trim-to-activity", "--explain"])
+        .write_stdin(two_activity_gpx())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let csv = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+
+    assert_eq!(
+        lines[0],
+        "index,time,lat,lon,speed_mps,smoothed_speed_mps,state"
+    );
+    assert_eq!(lines.len(), 13);
+    assert!(lines[1].ends_with(",,,active"), "{}", lines[1]);
+    assert!(
+        lines[2].starts_with("1,2023-06-15T10:01:00Z,"),
+        "{}",
+        lines[2]
+    );
+    assert!(lines[6].ends_with(",idle"), "{}", lines[6]);
+    assert!(lines[8].ends_with(",active"), "{}", lines[8]);
+}
+
+#[test]
+fn test_remove_pauses_splits_segments() {
+    let run = |args: &[&str]| {
+        let output = cargo_bin_cmd!("gpxwrench")
+            .arg("remove-pauses")
+            .args(args)
+            .write_stdin(two_activity_gpx())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{args:?} failed");
+        let gpx: gpx::Gpx = gpx::read(output.stdout.as_slice()).unwrap();
+        gpx.tracks[0]
+            .segments
+            .iter()
+            .map(|segment| segment.points.len())
+            .collect::<Vec<_>>()
+    };
+
+    // The final stationary minute goes; the 30 minute stop splits the segment, keeping the
+    // points where the track stopped and started moving again.
+    assert_eq!(run(&[]), [5, 5]);
+    assert_eq!(run(&["--min-pause", "120"]), [5, 6]);
+    assert_eq!(run(&["--min-pause", "3600"]), [12]);
+
+    let output = cargo_bin_cmd!("gpxwrench")
+        .arg("remove-pauses")
+        .write_stdin(two_activity_gpx())
+        .output()
+        .unwrap();
+    let gpx: gpx::Gpx = gpx::read(output.stdout.as_slice()).unwrap();
+    assert_eq!(
+        gpx.tracks[0].segments[1].points[0].time,
+        Some(parse_timestamp("2023-06-15T10:35:00Z"))
+    );
+
+    cargo_bin_cmd!("gpxwrench")
+        .args(["remove-pauses", "--speed-threshold=-1"])
+        .write_stdin(two_activity_gpx())
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_split_command() {
+    let run = |args: &[&str]| {
+        let output = cargo_bin_cmd!("gpxwrench")
+            .arg("split")
+            .args(args)
+            .write_stdin(two_activity_gpx())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{args:?} failed");
+        gpx::read(output.stdout.as_slice()).unwrap()
+    };
+
+    let by_gap: gpx::Gpx = run(&["--gap", "10m"]);
+    let counts: Vec<usize> = by_gap.tracks[0]
+        .segments
+        .iter()
+        .map(|segment| segment.points.len())
+        .collect();
+    assert_eq!(counts, [6, 6]);
+
+    let by_points: gpx::Gpx = run(&["--points", "5", "--into", "track"]);
+    assert_eq!(by_points.tracks.len(), 3);
+    assert_eq!(by_points.tracks[2].segments[0].points.len(), 2);
+
+    let by_day: gpx::Gpx = run(&["--day", "--tz", "+13:30"]);
+    assert_eq!(by_day.tracks[0].segments.len(), 2);
+    assert_eq!(
+        by_day.tracks[0].segments[1].points[0].time,
+        Some(parse_timestamp("2023-06-15T10:35:00Z"))
+    );
+
+    let dir = test_dir("split_command");
+    let with_waypoint = two_activity_gpx().replacen(
+        "  <trk>",
+        "  <metadata><name>Trip</name></metadata>\n  <wpt lat=\"37.8\" lon=\"-122.4\"><name>Camp</name></wpt>\n  <trk>",
+        1,
+    );
+    cargo_bin_cmd!("gpxwrench")
+        .args(["split", "--distance", "3km", "--into", "file", "-o"])
+        .arg(dir.join("part.gpx"))
+        .write_stdin(with_waypoint)
+        .assert()
+        .success();
+    let parts: Vec<gpx::Gpx> = ["part-1.gpx", "part-2.gpx", "part-3.gpx"]
+        .iter()
+        .map(|name| gpx::read(fs::read(dir.join(name)).unwrap().as_slice()).unwrap())
+        .collect();
+    let points: Vec<usize> = parts
+        .iter()
+        .map(|gpx| {
+            assert_eq!(gpx.tracks.len(), 1);
+            assert_eq!(gpx.tracks[0].segments.len(), 1);
+            assert_eq!(gpx.metadata.as_ref().unwrap().name.as_deref(), Some("Trip"));
+            gpx.tracks[0].segments[0].points.len()
+        })
+        .collect();
+    // Moving points are about 1.1 km apart.
+    assert_eq!(points, [3, 5, 4]);
+    let waypoints: Vec<usize> = parts.iter().map(|gpx| gpx.waypoints.len()).collect();
+    assert_eq!(waypoints, [1, 0, 0]);
+    assert!(!dir.join("part-4.gpx").exists());
+
+    cargo_bin_cmd!("gpxwrench")
+        .arg("split")
+        .write_stdin(two_activity_gpx())
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_merge_command() {
+    let dir = test_dir("merge_command");
+    let later = dir.join("later.gpx");
+    let earlier = dir.join("earlier.gpx");
+    fs::write(&later, sample_gpx().replace("T10:0", "T11:0")).unwrap();
+    fs::write(&earlier, sample_gpx()).unwrap();
+
+    let output = cargo_bin_cmd!("gpxwrench")
+        .args(["merge", "--into", "segments"])
+        .arg(&later)
+        .arg(&earlier)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+    let merged: gpx::Gpx = gpx::read(output.stdout.as_slice()).unwrap();
+    let segments = &merged.tracks[0].segments;
+    assert_eq!(segments.len(), 2);
+    assert_eq!(
+        segments[0].points[0].time,
+        Some(parse_timestamp("2023-06-15T10:00:00Z"))
+    );
+    assert_eq!(
+        segments[1].points[0].time,
+        Some(parse_timestamp("2023-06-15T11:00:00Z"))
+    );
+    assert!(merged.metadata.unwrap().bounds.is_some());
+
+    cargo_bin_cmd!("gpxwrench")
+        .args(["merge", "--into", "continuous"])
+        .arg(&earlier)
+        .arg(&earlier)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("both cover 2023-06-15T10:00:00Z"));
+
+    cargo_bin_cmd!("gpxwrench")
+        .arg("merge")
+        .write_stdin(sample_gpx())
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_simplify_command() {
+    let run = |args: &[&str]| {
+        let output = cargo_bin_cmd!("gpxwrench")
+            .arg("simplify")
+            .args(args)
+            .write_stdin(sample_gpx())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{args:?} failed");
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let point_count = |output: &str| {
+        let gpx: gpx::Gpx = gpx::read(output.as_bytes()).unwrap();
+        gpx.tracks[0].segments[0].points.len()
+    };
+
+    let original = point_count(sample_gpx());
+    let coarse = run(&["--tolerance", "100"]);
+    assert_eq!(point_count(&coarse), 2);
+    assert!(coarse.contains("<name>Sample Activity Track</name>"));
+    assert!(point_count(&run(&["-t", "5"])) < original);
+    assert!(point_count(&run(&["-t", "20", "--algorithm", "visvalingam", "--3d"])) < original);
+
+    // A planned route without timestamps.
+    let untimed: String = sample_gpx()
+        .lines()
+        .filter(|line| !line.contains("<time>"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let output = cargo_bin_cmd!("gpxwrench")
+        .args(["simplify", "--tolerance", "100"])
+        .write_stdin(untimed)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(point_count(&String::from_utf8(output.stdout).unwrap()), 2);
+
+    cargo_bin_cmd!("gpxwrench")
+        .args(["simplify", "--tolerance=-1"])
+        .write_stdin(sample_gpx())
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_clean_command() {
+    // A spike at the start of the ride, points without a fix, one of them also without a time,
+    // and a repeated last point.
+    let last_point = r#"      <trkpt lat="37.8050" lon="-122.4576">
+        <ele>69.0</ele>
+        <time>2023-06-15T10:01:50Z</time>
+      </trkpt>
+"#;
+    let input = sample_gpx()
+        .replacen(
+            r#"<trkpt lat="37.7750" lon="-122.4200">"#,
+            r#"<trkpt lat="37.8150" lon="-122.4200">"#,
+            1,
+        )
+        .replacen(
+            "    </trkseg>",
+            &format!(
+                "      <trkpt lat=\"0\" lon=\"0\"><time>2023-06-15T10:01:51Z</time></trkpt>\n      <trkpt lat=\"0\" lon=\"0\"></trkpt>\n{last_point}    </trkseg>"
+            ),
+            1,
+        );
+
+    let output = cargo_bin_cmd!("gpxwrench")
+        .arg("clean")
+        .write_stdin(input.as_str())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report = String::from_utf8(output.stderr).unwrap();
+    assert!(report.contains("exceeds 90.0 m/s"), "{report}");
+    assert!(report.contains("zero coordinates"), "{report}");
+    assert!(
+        report.contains("duplicate of the previous point"),
+        "{report}"
+    );
+    assert!(
+        report.contains("Removed point without time (0, 0): zero coordinates"),
+        "{report}"
+    );
+    assert!(report.contains("Removed 4 of 26 points"), "{report}");
+
+    let gpx: gpx::Gpx = gpx::read(output.stdout.as_slice()).unwrap();
+    let points = &gpx.tracks[0].segments[0].points;
+    assert_eq!(points.len(), 22);
+    assert!(points.iter().all(|point| point.point().y() != 37.815));
+
+    // With files, every report line names the file it is about.
+    let dir = test_dir("clean");
+    let path = dir.join("ride.gpx");
+    fs::write(&path, &input).unwrap();
+    let output = cargo_bin_cmd!("gpxwrench")
+        .args(["clean", "--in-place"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(report.lines().count(), 5, "{report}");
+    let prefix = format!("{}: Removed ", path.display());
+    assert!(
+        report.lines().all(|line| line.starts_with(&prefix)),
+        "{report}"
+    );
+
+    cargo_bin_cmd!("gpxwrench")
+        .args(["clean", "--max-speed", "0"])
+        .write_stdin(sample_gpx())
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_privacy_command() {
+    let dir = test_dir("privacy");
+    let zone_file = dir.join("zones.txt");
+    // A square around the point at 37.7825, -122.4300.
+    fs::write(
+        &zone_file,
+        "# office\n37.7820,-122.4305\n37.7820,-122.4295\n37.7830,-122.4295\n37.7830,-122.4305\n",
+    )
+    .unwrap();
+    // Bounds that reveal the start, waypoints and a route inside and outside the zones.
+    let input = sample_gpx().replacen(
+        "  </metadata>\n",
+        r#"    <bounds minlat="37.7749" minlon="-122.4400" maxlat="37.7900" maxlon="-122.4194"/>
+  </metadata>
+  <wpt lat="37.7749" lon="-122.4194"><name>Home</name></wpt>
+  <wpt lat="37.7900" lon="-122.4400"><name>Viewpoint</name></wpt>
+  <rte>
+    <rtept lat="37.7824" lon="-122.4301"><name>Office</name></rtept>
+    <rtept lat="37.7900" lon="-122.4400"><name>Park</name></rtept>
+  </rte>
+"#,
+        1,
+    );
+
+    let output = cargo_bin_cmd!("gpxwrench")
+        .args(["privacy", "--zone", "37.7749,-122.4194,30m", "--zone-file"])
+        .arg(&zone_file)
+        .args(["--shift-date", "2000-01-01", "--strip-metadata"])
+        .write_stdin(input.as_str())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report = String::from_utf8(output.stderr).unwrap();
+    assert!(report.contains("Removed 4 of 23 points"), "{report}");
+
+    let text = String::from_utf8(output.stdout).unwrap();
+    assert!(text.contains(r#"creator="gpxwrench""#));
+    assert!(!text.contains("2023-06-15"));
+    assert!(!text.contains("<bounds"));
+    let gpx: gpx::Gpx = gpx::read(text.as_bytes()).unwrap();
+    let waypoints: Vec<_> = gpx
+        .waypoints
+        .iter()
+        .map(|wpt| wpt.name.as_deref())
+        .collect();
+    assert_eq!(waypoints, [Some("Viewpoint")]);
+    let route_points: Vec<_> = gpx.routes[0]
+        .points
+        .iter()
+        .map(|rtept| rtept.name.as_deref())
+        .collect();
+    assert_eq!(route_points, [Some("Park")]);
+    let segments = &gpx.tracks[0].segments;
+    assert_eq!(segments.len(), 2);
+    assert!(
+        segments
+            .iter()
+            .flat_map(|segment| &segment.points)
+            .all(|point| {
+                let lat = point.point().y();
+                lat != 37.7749 && lat != 37.7825
+            })
+    );
+    assert_eq!(
+        segments[0].points[0].time,
+        Some(parse_timestamp("2000-01-01T10:00:15Z"))
+    );
+
+    // Streaming spools the filtered document instead of buffering it, with the same result.
+    let streamed = cargo_bin_cmd!("gpxwrench")
+        .args([
+            "privacy",
+            "--stream",
+            "--zone",
+            "37.7749,-122.4194,30m",
+            "--zone-file",
+        ])
+        .arg(&zone_file)
+        .args(["--shift-date", "2000-01-01", "--strip-metadata"])
+        .write_stdin(input)
+        .output()
+        .unwrap();
+    assert!(streamed.status.success());
+    assert_eq!(String::from_utf8(streamed.stdout).unwrap(), text);
+
+    // Only the ends are cut; the rest of the file is copied unchanged.
+    let output = cargo_bin_cmd!("gpxwrench")
+        .args(["privacy", "--trim-ends", "200m"])
+        .write_stdin(sample_gpx())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let text = String::from_utf8(output.stdout).unwrap();
+    assert!(text.contains(r#"creator="GPX Wrench Sample""#));
+    let gpx: gpx::Gpx = gpx::read(text.as_bytes()).unwrap();
+    let points = &gpx.tracks[0].segments[0].points;
+    assert!(!points.is_empty() && points.len() < 23);
+
+    cargo_bin_cmd!("gpxwrench")
+        .arg("privacy")
+        .write_stdin(sample_gpx())
+        .assert()
+        .code(2);
+    cargo_bin_cmd!("gpxwrench")
+        .args(["privacy", "--zone", "37.7749,-122.4194"])
+        .write_stdin(sample_gpx())
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("Invalid zone"));
+}
+
+#[test]
+fn test_smooth_command() {
+    // Standing still for two minutes while the position jumps around by about 10 m.
+    let mut points = String::new();
+    for second in 0..120 {
+        let lat = if second % 2 == 0 { 37.77495 } else { 37.77485 };
+        points.push_str(&format!(
+            "      <trkpt lat=\"{lat}\" lon=\"-122.4194\"><ele>50</ele><time>2023-06-15T10:{:02}:{:02}Z</time></trkpt>\n",
+            second / 60,
+            second % 60
+        ));
+    }
+    let input = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<gpx version=\"1.1\" creator=\"test\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n  <trk>\n    <trkseg>\n{points}    </trkseg>\n  </trk>\n</gpx>\n"
+    );
+    let distance = |gpx: &str| -> f64 {
+        let gpx: gpx::Gpx = gpx::read(gpx.as_bytes()).unwrap();
+        gpx.tracks[0].segments[0]
+            .points
+            .windows(2)
+            .map(|pair| {
+                gpxwrench::haversine_distance(
+                    pair[0].point().y(),
+                    pair[0].point().x(),
+                    pair[1].point().y(),
+                    pair[1].point().x(),
+                )
+            })
+            .sum()
+    };
+    let run = |args: &[&str]| {
+        let output = cargo_bin_cmd!("gpxwrench")
+            .arg("smooth")
+            .args(args)
+            .write_stdin(input.clone())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{args:?} failed");
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let original = distance(&input);
+    for args in [
+        &[][..],
+        &["--forward-only"],
+        &["--method", "moving-average", "--window", "9"],
+    ] {
+        let output = run(args);
+        assert!(distance(&output) < original / 3.0, "{args:?}");
+        assert_eq!(output.matches("<ele>50</ele>").count(), 120);
+    }
+
+    cargo_bin_cmd!("gpxwrench")
+        .args(["smooth", "--measurement-noise", "0"])
+        .write_stdin(input)
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_resample_command() {
+    let run = |args: &[&str]| {
+        let output = cargo_bin_cmd!("gpxwrench")
+            .arg("resample")
+            .args(args)
+            .write_stdin(sample_gpx())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{args:?} failed");
+        gpx::read(output.stdout.as_slice()).unwrap()
+    };
+    let times = |gpx: &gpx::Gpx| -> Vec<i64> {
+        gpx.tracks[0].segments[0]
+            .points
+            .iter()
+            .map(|point| {
+                let time: OffsetDateTime = point.time.unwrap().into();
+                time.unix_timestamp()
+            })
+            .collect()
+    };
+
+    let every_second = run(&["--every-time", "1s"]);
+    let times = times(&every_second);
+    assert!(times.len() > 2);
+    assert!(times.windows(2).all(|pair| pair[1] - pair[0] == 1));
+
+    let every_10m = run(&["--every-distance", "10m"]);
+    let points = &every_10m.tracks[0].segments[0].points;
+    for pair in points.windows(2) {
+        let distance = gpxwrench::haversine_distance(
+            pair[0].point().y(),
+            pair[0].point().x(),
+            pair[1].point().y(),
+            pair[1].point().x(),
+        );
+        assert!((distance - 10.0).abs() < 0.5, "{distance}");
+    }
+
+    cargo_bin_cmd!("gpxwrench")
+        .args(["resample", "--every-distance", "0m"])
+        .write_stdin(sample_gpx())
+        .assert()
+        .code(2);
+}
+
 #[test]
 fn test_trim_to_activity_output_is_valid_gpx() {
     let mut cmd = cargo_bin_cmd!("gpxwrench");
@@ -289,6 +1001,40 @@ fn test_trim_invalid_range_fails() {
         .failure();
 }
 
+#[test]
+fn test_errors_map_to_distinct_exit_codes() {
+    cargo_bin_cmd!("gpxwrench")
+        .args(["trim", "5x,10s"])
+        .write_stdin(sample_gpx())
+        .assert()
+        .code(6);
+
+    cargo_bin_cmd!("gpxwrench")
+        .args(["trim", "0s,10s"])
+        .write_stdin("<gpx>\n  <trk>\n</gpx>\n")
+        .assert()
+        .code(3)
+        .stderr(predicate::str::contains("line 3, column"));
+
+    cargo_bin_cmd!("gpxwrench")
+        .arg("trim-to-activity")
+        .write_stdin(r#"<gpx version="1.1" creator="test"></gpx>"#)
+        .assert()
+        .code(8);
+
+    cargo_bin_cmd!("gpxwrench")
+        .args(["trim", "0s,10s", "--max-input-bytes", "10"])
+        .write_stdin(sample_gpx())
+        .assert()
+        .code(5);
+
+    cargo_bin_cmd!("gpxwrench")
+        .args(["trim-to-activity", "--speed-threshold=-1"])
+        .write_stdin(sample_gpx())
+        .assert()
+        .code(2);
+}
+
 #[test]
 fn test_trim_excludes_points_when_no_timestamps_exist() {
     let gpx = r#"<?xml version="1.0" encoding="UTF-8"?>
@@ -411,3 +1157,570 @@ fn test_trim_command_preserves_gpx_structure() {
         assert!(point.time.is_some(), "Each point should have a time");
     }
 }
+
+#[test]
+fn test_trim_reads_input_file_argument() {
+    let dir = test_dir("trim_reads_input_file_argument");
+    let input_path = dir.join("activity.gpx");
+    fs::write(&input_path, sample_gpx()).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("gpxwrench");
+    let output = cmd
+        .arg("trim")
+        .arg("10s,40s")
+        .arg(&input_path)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let gpx: gpx::Gpx = gpx::read(output.as_slice()).unwrap();
+    let points = &gpx.tracks[0].segments[0].points;
+    assert_eq!(
+        points.first().and_then(|point| point.time),
+        Some(parse_timestamp("2023-06-15T10:00:10Z"))
+    );
+}
+
+#[test]
+fn test_trim_writes_output_file() {
+    let dir = test_dir("trim_writes_output_file");
+    let output_path = dir.join("trimmed.gpx");
+
+    let mut cmd = cargo_bin_cmd!("gpxwrench");
+    cmd.arg("trim")
+        .arg("10s,40s")
+        .arg("--output")
+        .arg(&output_path)
+        .write_stdin(sample_gpx())
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+
+    let gpx: gpx::Gpx = gpx::read(fs::read(&output_path).unwrap().as_slice()).unwrap();
+    assert_eq!(gpx.tracks[0].segments[0].points.len(), 6);
+    let leftover_files = fs::read_dir(&dir).unwrap().count();
+    assert_eq!(leftover_files, 1, "Temporary files should be renamed away");
+}
+
+#[test]
+fn test_trim_to_activity_in_place_rewrites_every_input() {
+    let dir = test_dir("trim_to_activity_in_place_rewrites_every_input");
+    let paths = [dir.join("first.gpx"), dir.join("second.gpx")];
+    for path in &paths {
+        fs::write(path, sample_gpx()).unwrap();
+    }
+
+    let mut cmd = cargo_bin_cmd!("gpxwrench");
+    cmd.arg("trim-to-activity")
+        .arg("--buffer")
+        .arg("0")
+        .arg("--in-place")
+        .args(&paths)
+        .assert()
+        .success();
+
+    for path in &paths {
+        let gpx: gpx::Gpx = gpx::read(fs::read(path).unwrap().as_slice()).unwrap();
+        let points = &gpx.tracks[0].segments[0].points;
+        assert_eq!(
+            points.first().and_then(|point| point.time),
+            Some(parse_timestamp("2023-06-15T10:00:05Z"))
+        );
+    }
+}
+
+#[test]
+fn test_in_place_failure_leaves_input_untouched() {
+    let dir = test_dir("in_place_failure_leaves_input_untouched");
+    let path = dir.join("truncated.gpx");
+    let truncated = &sample_gpx()[..sample_gpx().len() / 2];
+    fs::write(&path, truncated).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("gpxwrench");
+    cmd.arg("trim")
+        .arg("0s,30s")
+        .arg("--in-place")
+        .arg(&path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("truncated.gpx"));
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), truncated);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+}
+
+#[test]
+fn test_multiple_inputs_require_in_place() {
+    let dir = test_dir("multiple_inputs_require_in_place");
+    let paths = [dir.join("first.gpx"), dir.join("second.gpx")];
+    for path in &paths {
+        fs::write(path, sample_gpx()).unwrap();
+    }
+
+    let mut cmd = cargo_bin_cmd!("gpxwrench");
+    cmd.arg("trim")
+        .arg("0s,30s")
+        .args(&paths)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("require --in-place"));
+}
+
+#[test]
+fn test_in_place_conflicts_with_output() {
+    let mut cmd = cargo_bin_cmd!("gpxwrench");
+    cmd.arg("trim")
+        .arg("0s,30s")
+        .arg("--in-place")
+        .arg("--output")
+        .arg("out.gpx")
+        .arg("in.gpx")
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_batch_mode_processes_matching_files_and_reports_failures() {
+    let dir = test_dir("batch_mode_processes_matching_files_and_reports_failures");
+    let input_dir = dir.join("exports");
+    let output_dir = dir.join("out");
+    fs::create_dir_all(&input_dir).unwrap();
+    fs::write(input_dir.join("morning.gpx"), sample_gpx()).unwrap();
+    fs::write(input_dir.join("evening.gpx"), sample_gpx()).unwrap();
+    fs::write(input_dir.join("broken.gpx"), "<gpx><trk>").unwrap();
+    fs::write(input_dir.join("notes.txt"), "not a track").unwrap();
+
+    let mut cmd = cargo_bin_cmd!("gpxwrench");
+    cmd.arg("trim-to-activity")
+        .arg("--batch")
+        .arg(input_dir.join("*.gpx"))
+        .arg("--output-dir")
+        .arg(&output_dir)
+        .arg("--name-template")
+        .arg("{stem}-active.gpx")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("failed"))
+        .stdout(predicate::str::contains("Unexpected EOF"))
+        .stdout(predicate::str::contains("2 succeeded, 1 failed"))
+        .stderr(predicate::str::contains("1 of 3 files failed"));
+
+    for name in ["morning-active.gpx", "evening-active.gpx"] {
+        let gpx: gpx::Gpx = gpx::read(fs::read(output_dir.join(name)).unwrap().as_slice()).unwrap();
+        assert!(!gpx.tracks[0].segments[0].points.is_empty());
+    }
+    assert!(!output_dir.join("broken-active.gpx").exists());
+}
+
+#[test]
+fn test_batch_mode_accepts_directory() {
+    let dir = test_dir("batch_mode_accepts_directory");
+    let input_dir = dir.join("exports");
+    let output_dir = dir.join("out");
+    fs::create_dir_all(&input_dir).unwrap();
+    fs::write(input_dir.join("ride.GPX"), sample_gpx()).unwrap();
+    fs::write(input_dir.join("notes.txt"), "not a track").unwrap();
+
+    let mut cmd = cargo_bin_cmd!("gpxwrench");
+    cmd.arg("trim")
+        .arg("0s,30s")
+        .arg("--batch")
+        .arg(&input_dir)
+        .arg("--output-dir")
+        .arg(&output_dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 succeeded, 0 failed"));
+
+    assert!(output_dir.join("ride.gpx").exists());
+    assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 1);
+}
+
+#[test]
+fn test_batch_mode_rejects_output_over_input() {
+    let dir = test_dir("batch_mode_rejects_output_over_input");
+    let input_dir = dir.join("exports");
+    fs::create_dir_all(&input_dir).unwrap();
+    fs::write(input_dir.join("ride.gpx"), sample_gpx()).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("gpxwrench");
+    cmd.arg("trim")
+        .arg("0s,30s")
+        .arg("--batch")
+        .arg(&input_dir)
+        .arg("--output-dir")
+        .arg(input_dir.join("."))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("would overwrite an input file"));
+
+    assert_eq!(
+        fs::read_to_string(input_dir.join("ride.gpx")).unwrap(),
+        sample_gpx()
+    );
+}
+
+#[test]
+fn test_stream_mode_matches_buffered_output() {
+    let dir = test_dir("stream_mode_matches_buffered_output");
+    let input_path = dir.join("activity.gpx");
+    fs::write(&input_path, sample_gpx()).unwrap();
+
+    let buffered = cargo_bin_cmd!("gpxwrench")
+        .arg("trim")
+        .arg("10s,40s")
+        .write_stdin(sample_gpx())
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let streamed_stdin = cargo_bin_cmd!("gpxwrench")
+        .arg("trim")
+        .arg("10s,40s")
+        .arg("--stream")
+        .write_stdin(sample_gpx())
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let streamed_file = cargo_bin_cmd!("gpxwrench")
+        .arg("trim-to-activity")
+        .arg("--stream")
+        .arg(&input_path)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    assert_eq!(buffered, streamed_stdin);
+    assert!(gpx::read(streamed_file.as_slice()).is_ok());
+}
+
+#[test]
+fn test_max_input_bytes_is_enforced() {
+    let dir = test_dir("max_input_bytes_is_enforced");
+    let input_path = dir.join("activity.gpx");
+    fs::write(&input_path, sample_gpx()).unwrap();
+
+    for stream in [false, true] {
+        let mut cmd = cargo_bin_cmd!("gpxwrench");
+        cmd.arg("trim").arg("0s,30s").arg("--max-input-bytes=100");
+        if stream {
+            cmd.arg("--stream");
+        }
+        cmd.write_stdin(sample_gpx())
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "maximum supported size of 100 bytes",
+            ));
+
+        let mut cmd = cargo_bin_cmd!("gpxwrench");
+        cmd.arg("trim")
+            .arg("0s,30s")
+            .arg("--max-input-bytes=100")
+            .arg(&input_path);
+        if stream {
+            cmd.arg("--stream");
+        }
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "maximum supported size of 100 bytes",
+        ));
+    }
+}
+
+#[test]
+fn test_stats_prints_summary() {
+    cargo_bin_cmd!("gpxwrench")
+        .arg("stats")
+        .write_stdin(sample_gpx())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Points:          23"))
+        .stdout(predicate::str::contains("Elapsed time:    0:01:50"))
+        .stdout(predicate::str::contains("Elevation gain:"));
+}
+
+#[test]
+fn test_stats_json_output() {
+    let output = cargo_bin_cmd!("gpxwrench")
+        .args(["stats", "--json", "--speed-threshold", "0"])
+        .write_stdin(sample_gpx())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let json = String::from_utf8(output.stdout).unwrap();
+    assert!(json.trim_start().starts_with('{'));
+    assert!(json.contains("\"points\": 23,"));
+    assert!(json.contains("\"segments\": 1,"));
+    assert!(json.contains("\"elapsed_s\": 110,"));
+    assert!(json.contains("\"moving_s\": 110,"));
+    assert!(json.contains("\"start_time\": \"2023-06-15T10:00:00Z\","));
+}
+
+#[test]
+fn test_stats_counts_points_without_time() {
+    let untimed: String = sample_gpx()
+        .lines()
+        .filter(|line| !line.contains("<time>"))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    cargo_bin_cmd!("gpxwrench")
+        .arg("stats")
+        .write_stdin(untimed)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Points:          23"))
+        .stdout(predicate::str::contains("Start:           -"))
+        .stdout(predicate::str::contains("Average speed:   -"))
+        .stdout(predicate::str::contains("Distance:        0.00 km").not());
+}
+
+#[test]
+fn test_stats_batch_writes_reports() {
+    let dir = test_dir("stats_batch");
+    let input_dir = dir.join("in");
+    fs::create_dir_all(&input_dir).unwrap();
+    fs::write(input_dir.join("ride.gpx"), sample_gpx()).unwrap();
+
+    for (args, name) in [
+        (&["stats"][..], "ride.txt"),
+        (&["stats", "--json"], "ride.json"),
+    ] {
+        cargo_bin_cmd!("gpxwrench")
+            .args(args)
+            .arg("--batch")
+            .arg(&input_dir)
+            .arg("--output-dir")
+            .arg(dir.join("out"))
+            .assert()
+            .success();
+        assert!(dir.join("out").join(name).exists(), "{name}");
+    }
+    assert!(!dir.join("out").join("ride.gpx").exists());
+}
+
+#[test]
+fn test_stats_rejects_in_place() {
+    let dir = test_dir("stats_in_place");
+    let input = dir.join("ride.gpx");
+    fs::write(&input, sample_gpx()).unwrap();
+
+    cargo_bin_cmd!("gpxwrench")
+        .args(["stats", "--in-place"])
+        .arg(&input)
+        .assert()
+        .code(2);
+    assert_eq!(fs::read_to_string(&input).unwrap(), sample_gpx());
+}
+
+#[test]
+fn test_trim_absolute_range_matches_relative_range() {
+    let relative = cargo_bin_cmd!("gpxwrench")
+        .args(["trim", "15s,100s"])
+        .write_stdin(sample_gpx())
+        .output()
+        .unwrap();
+    let absolute = cargo_bin_cmd!("gpxwrench")
+        .args(["trim", "2023-06-15T10:00:15Z,2023-06-15T10:01:40Z"])
+        .write_stdin(sample_gpx())
+        .output()
+        .unwrap();
+    let local = cargo_bin_cmd!("gpxwrench")
+        .args([
+            "trim",
+            "2023-06-15T12:00:15,2023-06-15T12:01:40",
+            "--tz",
+            "+02:00",
+        ])
+        .write_stdin(sample_gpx())
+        .output()
+        .unwrap();
+
+    assert!(absolute.status.success());
+    assert!(local.status.success());
+    assert_eq!(absolute.stdout, relative.stdout);
+    assert_eq!(local.stdout, relative.stdout);
+}
+
+#[test]
+fn test_trim_open_and_end_relative_ranges() {
+    let trim = |range: &str| {
+        let output = cargo_bin_cmd!("gpxwrench")
+            .args(["trim", range])
+            .write_stdin(sample_gpx())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "trim {range} failed");
+        output.stdout
+    };
+    let points = |stdout: &[u8]| {
+        let gpx: gpx::Gpx = gpx::read(stdout).unwrap();
+        gpx.tracks[0].segments[0].points.len()
+    };
+
+    // The sample track runs from 10:00:00 to 10:01:50.
+    assert_eq!(trim("1m,"), trim("1m,1h"));
+    assert_eq!(trim(",30s"), trim("0s,30s"));
+    assert_eq!(trim("0s,-20s"), trim("0s,90s"));
+    assert_eq!(trim("-00:30,"), trim("80s,1h"));
+    assert_eq!(points(&trim("0s,")), points(sample_gpx().as_bytes()));
+    assert!(points(&trim("0s,-20s")) < points(&trim("0s,")));
+}
+
+#[test]
+fn test_trim_compound_and_fractional_durations() {
+    let trim = |range: &str| {
+        cargo_bin_cmd!("gpxwrench")
+            .args(["trim", range])
+            .write_stdin(sample_gpx())
+            .output()
+            .unwrap()
+            .stdout
+    };
+
+    assert_eq!(trim("1m,1m30s"), trim("60s,90s"));
+    assert_eq!(trim("0.25m,90000ms"), trim("15s,90s"));
+    assert_eq!(trim("00:14.5,01:29.999"), trim("15s,90s"));
+}
+
+#[test]
+fn test_trim_multiple_ranges_become_segments() {
+    let output = cargo_bin_cmd!("gpxwrench")
+        .args(["trim", "1m,1m10s;0s,10s"])
+        .write_stdin(sample_gpx())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let gpx: gpx::Gpx = gpx::read(output.stdout.as_slice()).unwrap();
+    let segments = &gpx.tracks[0].segments;
+    assert_eq!(segments.len(), 2);
+    assert_eq!(
+        segments[0].points.first().unwrap().time,
+        Some(parse_timestamp("2023-06-15T10:00:00Z"))
+    );
+    assert_eq!(
+        segments[1].points.first().unwrap().time,
+        Some(parse_timestamp("2023-06-15T10:01:00Z"))
+    );
+}
+
+#[test]
+fn test_trim_split_output_writes_one_file_per_range() {
+    let dir = test_dir("trim_split_output_writes_one_file_per_range");
+    let input = dir.join("ride.gpx");
+    fs::write(&input, sample_gpx()).unwrap();
+
+    cargo_bin_cmd!("gpxwrench")
+        .args(["trim", "0s,10s;1m,1m10s", "--split-output", "-o"])
+        .arg(dir.join("out.gpx"))
+        .arg(&input)
+        .assert()
+        .success();
+
+    let first: gpx::Gpx = gpx::read(fs::read(dir.join("out-1.gpx")).unwrap().as_slice()).unwrap();
+    let second: gpx::Gpx = gpx::read(fs::read(dir.join("out-2.gpx")).unwrap().as_slice()).unwrap();
+    assert_eq!(first.tracks[0].segments.len(), 1);
+    assert_eq!(
+        second.tracks[0].segments[0].points[0].time,
+        Some(parse_timestamp("2023-06-15T10:01:00Z"))
+    );
+    assert!(!dir.join("out.gpx").exists());
+
+    // Overlapping ranges still get a file each, numbered in the given order.
+    cargo_bin_cmd!("gpxwrench")
+        .args(["trim", "1m,1m20s;0s,1m10s", "--split-output", "-o"])
+        .arg(dir.join("overlap.gpx"))
+        .arg(&input)
+        .assert()
+        .success();
+    let points = |name: &str| {
+        let gpx: gpx::Gpx = gpx::read(fs::read(dir.join(name)).unwrap().as_slice()).unwrap();
+        gpx.tracks[0].segments[0].points.len()
+    };
+    assert_eq!(points("overlap-1.gpx"), 4);
+    assert_eq!(points("overlap-2.gpx"), 14);
+
+    cargo_bin_cmd!("gpxwrench")
+        .args(["trim", "0s,10s;1h,2h", "--split-output", "-o"])
+        .arg(dir.join("empty.gpx"))
+        .arg(&input)
+        .assert()
+        .code(8)
+        .stderr(predicate::str::contains("Range 2 contains no track points"));
+    assert!(!dir.join("empty-1.gpx").exists());
+
+    cargo_bin_cmd!("gpxwrench")
+        .args(["trim", "0s,10s;1m,1m10s", "--split-output"])
+        .write_stdin(sample_gpx())
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_trim_by_distance() {
+    let count = |range: &str| {
+        let output = cargo_bin_cmd!("gpxwrench")
+            .args(["trim", "--by", "distance", range])
+            .write_stdin(sample_gpx())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "trim --by distance {range} failed");
+        let gpx: gpx::Gpx = gpx::read(output.stdout.as_slice()).unwrap();
+        gpx.tracks[0].segments[0].points.len()
+    };
+
+    // The sample track is about 4.8 km long with 23 points.
+    assert_eq!(count(",1km") + count("1km,"), 23);
+    assert_eq!(count("-1km,") + count(",-1km"), 23);
+    assert_eq!(count("0m,10km"), 23);
+    assert!(count("500m,2km") < count("0m,2km"));
+}
+
+#[test]
+fn test_trim_by_distance_rejects_time_ranges() {
+    cargo_bin_cmd!("gpxwrench")
+        .args(["trim", "--by", "distance", "5s,10s"])
+        .write_stdin(sample_gpx())
+        .assert()
+        .code(6);
+
+    cargo_bin_cmd!("gpxwrench")
+        .args(["trim", "--by", "distance", "0m,1km", "--tz", "+02:00"])
+        .write_stdin(sample_gpx())
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_trim_rejects_range_without_bounds() {
+    cargo_bin_cmd!("gpxwrench")
+        .args(["trim", ","])
+        .write_stdin(sample_gpx())
+        .assert()
+        .code(6);
+}
+
+#[test]
+fn test_trim_local_datetime_requires_tz() {
+    cargo_bin_cmd!("gpxwrench")
+        .args(["trim", "2023-06-15T12:00:15,2023-06-15T12:01:40"])
+        .write_stdin(sample_gpx())
+        .assert()
+        .code(6)
+        .stderr(predicate::str::contains("--tz"));
+
+    cargo_bin_cmd!("gpxwrench")
+        .args(["trim", "0s,10s", "--tz", "CEST"])
+        .write_stdin(sample_gpx())
+        .assert()
+        .code(2);
+}